@@ -0,0 +1,1032 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/// Every command and the categories it belongs to. Container commands are
+/// listed by subcommand as `container|subcommand`.
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("ping", &["fast", "connection"]),
+    ("set", &["write", "string", "slow"]),
+    ("get", &["read", "string", "fast"]),
+    ("del", &["keyspace", "write", "slow"]),
+    ("mget", &["read", "string", "fast"]),
+    ("select", &["fast", "connection"]),
+    ("move", &["keyspace", "write", "fast"]),
+    ("swapdb", &["keyspace", "write", "fast", "dangerous"]),
+    ("dbsize", &["keyspace", "read", "fast"]),
+    ("flushdb", &["keyspace", "write", "slow", "dangerous"]),
+    ("flushall", &["keyspace", "write", "slow", "dangerous"]),
+    ("hello", &["fast", "connection"]),
+    ("auth", &["fast", "connection"]),
+    ("quit", &["fast", "connection"]),
+    ("multi", &["fast", "transaction"]),
+    ("exec", &["slow", "transaction"]),
+    ("discard", &["fast", "transaction"]),
+    ("acl|setuser", &["admin", "slow", "dangerous"]),
+    ("acl|getuser", &["admin", "slow", "dangerous"]),
+    ("acl|deluser", &["admin", "slow", "dangerous"]),
+    ("acl|list", &["admin", "slow", "dangerous"]),
+    ("acl|users", &["admin", "slow", "dangerous"]),
+    ("acl|whoami", &["slow"]),
+    ("acl|cat", &["slow"]),
+    ("acl|log", &["admin", "slow", "dangerous"]),
+    ("acl|load", &["admin", "slow", "dangerous"]),
+    ("acl|save", &["admin", "slow", "dangerous"]),
+    ("info", &["slow", "dangerous"]),
+    ("replicaof", &["admin", "slow", "dangerous"]),
+    ("psync", &["admin", "slow", "dangerous"]),
+    ("replconf", &["admin", "slow", "dangerous"]),
+    ("cluster|info", &["slow"]),
+    ("cluster|myid", &["slow"]),
+    ("cluster|nodes", &["slow"]),
+    ("cluster|slots", &["slow"]),
+    ("cluster|shards", &["slow"]),
+    ("cluster|keyslot", &["slow"]),
+    ("cluster|countkeysinslot", &["slow"]),
+    ("cluster|getkeysinslot", &["slow"]),
+    ("cluster|meet", &["admin", "slow", "dangerous"]),
+    ("cluster|addslots", &["admin", "slow", "dangerous"]),
+    ("cluster|addslotsrange", &["admin", "slow", "dangerous"]),
+    ("cluster|delslots", &["admin", "slow", "dangerous"]),
+    ("cluster|delslotsrange", &["admin", "slow", "dangerous"]),
+    ("cluster|setslot", &["admin", "slow", "dangerous"]),
+    ("cluster|forget", &["admin", "slow", "dangerous"]),
+    ("asking", &["fast", "connection"]),
+    ("migrate", &["keyspace", "write", "slow", "dangerous"]),
+    ("eval", &["slow", "scripting"]),
+    ("evalsha", &["slow", "scripting"]),
+    ("script|load", &["slow", "scripting"]),
+    ("script|exists", &["slow", "scripting"]),
+    ("script|flush", &["slow", "scripting"]),
+    ("script|kill", &["slow", "scripting"]),
+    ("fcall", &["slow", "scripting"]),
+    ("fcall_ro", &["slow", "scripting"]),
+    ("function|load", &["write", "slow", "scripting"]),
+    ("function|list", &["slow", "scripting"]),
+    ("function|delete", &["write", "slow", "scripting"]),
+    ("function|dump", &["slow", "scripting"]),
+    (
+        "function|restore",
+        &["write", "slow", "scripting", "dangerous"],
+    ),
+    ("function|flush", &["write", "slow", "scripting"]),
+    ("subscribe", &["pubsub", "slow"]),
+    ("unsubscribe", &["pubsub", "slow"]),
+    ("psubscribe", &["pubsub", "slow"]),
+    ("punsubscribe", &["pubsub", "slow"]),
+    ("publish", &["pubsub", "fast"]),
+];
+
+/// Command categories, the same ones redis has
+const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "set",
+    "sortedset",
+    "list",
+    "hash",
+    "string",
+    "bitmap",
+    "hyperloglog",
+    "geo",
+    "stream",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+    "transaction",
+    "scripting",
+];
+
+/// Entries kept by `ACL LOG`
+const LOG_MAX_LEN: usize = 128;
+
+/// Longest glob pattern matched, see `matches`
+pub(crate) const MAX_PATTERN_LEN: usize = 1024;
+
+const WRONGPASS: &str = "WRONGPASS invalid username-password pair or user is disabled.";
+
+/// Handle to the shared user table. Like `Db` carries the selected
+/// database, every handle carries the user its connection authenticated as.
+#[derive(Debug, Clone)]
+pub(crate) struct Users {
+    shared: Arc<Shared>,
+
+    /// `None` until the connection authenticates
+    user: Option<String>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+
+    /// Users file read by `ACL LOAD` and written by `ACL SAVE`
+    file: Option<PathBuf>,
+
+    /// Hash of the `requirepass` password, the `default` user gets it
+    /// whenever it is created
+    requirepass: Option<String>,
+}
+
+#[derive(Debug)]
+struct State {
+    users: HashMap<String, User>,
+
+    /// Denied commands and failed authentications, the newest first
+    log: VecDeque<LogEntry>,
+    next_entry_id: u64,
+}
+
+#[derive(Debug, Clone)]
+struct User {
+    name: String,
+    enabled: bool,
+
+    /// Any password is accepted
+    nopass: bool,
+
+    /// SHA-256 of each accepted password, hex encoded
+    passwords: Vec<String>,
+
+    /// Commands the user may run
+    commands: HashSet<&'static str>,
+
+    /// Command rules as given, `commands` is what they add up to
+    command_rules: Vec<String>,
+
+    keys: Vec<KeyPattern>,
+    channels: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct KeyPattern {
+    pattern: String,
+    read: bool,
+    write: bool,
+}
+
+/// A user as `ACL GETUSER` shows it
+#[derive(Debug)]
+pub(crate) struct UserInfo {
+    pub(crate) flags: Vec<&'static str>,
+    pub(crate) passwords: Vec<String>,
+    pub(crate) commands: String,
+    pub(crate) keys: String,
+    pub(crate) channels: String,
+}
+
+/// An `ACL LOG` entry, repeats of the same denial are counted in one entry
+#[derive(Debug, Clone)]
+pub(crate) struct LogEntry {
+    pub(crate) count: u64,
+    /// One of `command`, `key`, `channel` or `auth`
+    pub(crate) reason: &'static str,
+    pub(crate) object: String,
+    pub(crate) username: String,
+    pub(crate) entry_id: u64,
+    pub(crate) created: SystemTime,
+    pub(crate) updated: SystemTime,
+}
+
+impl Users {
+    /// Create the user table with the `default` user, protected by
+    /// `requirepass` if given. Users in `file` are loaded on top.
+    pub(crate) fn new(requirepass: Option<&str>, file: Option<PathBuf>) -> crate::Result<Users> {
+        let requirepass = requirepass.map(|password| hash(password.as_bytes()));
+        let default = default_user(requirepass.as_deref());
+
+        let users = Users {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    users: HashMap::from([(default.name.clone(), default)]),
+                    log: VecDeque::new(),
+                    next_entry_id: 0,
+                }),
+                file,
+                requirepass,
+            }),
+            user: None,
+        };
+
+        if users.shared.file.is_some() {
+            users.load()?;
+        }
+
+        Ok(users)
+    }
+
+    /// Handle for a new connection, which is authenticated as `default`
+    /// right away if that user needs no password
+    pub(crate) fn session(&self) -> Users {
+        let state = self.shared.state.lock().unwrap();
+
+        let user = match state.users.get("default") {
+            Some(user) if user.enabled && user.nopass => Some(user.name.clone()),
+            _ => None,
+        };
+
+        Users {
+            shared: self.shared.clone(),
+            user,
+        }
+    }
+
+    /// Whether the connection is authenticated as a user that still exists
+    /// and is enabled
+    pub(crate) fn authenticated(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+
+        self.user
+            .as_ref()
+            .and_then(|name| state.users.get(name))
+            .is_some_and(|user| user.enabled)
+    }
+
+    pub(crate) fn whoami(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// Whether `AUTH <password>` makes sense, it does not when the default
+    /// user takes any password
+    pub(crate) fn default_has_password(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+
+        state.users.get("default").is_some_and(|user| !user.nopass)
+    }
+
+    /// Authenticate the connection as `username`. Every password of the
+    /// user is compared in constant time, so the reply time tells nothing
+    /// about how much of one matched.
+    pub(crate) fn authenticate(&mut self, username: &str, password: &[u8]) -> crate::Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        let given = hash(password);
+
+        let ok = match state.users.get(username) {
+            Some(user) if user.enabled => {
+                user.nopass
+                    || user.passwords.iter().fold(false, |found, password| {
+                        found | bool::from(password.as_bytes().ct_eq(given.as_bytes()))
+                    })
+            }
+            _ => false,
+        };
+
+        if !ok {
+            state.log("auth", "AUTH", username);
+            return Err(WRONGPASS.into());
+        }
+
+        self.user = Some(username.to_string());
+
+        Ok(())
+    }
+
+    /// Check the connection's user may run `command` on `keys` and
+    /// `channels`, `command` being the name used in rules, like `get` or
+    /// `acl|setuser`
+    pub(crate) fn check(
+        &self,
+        command: &str,
+        keys: &[&str],
+        channels: &[&[u8]],
+    ) -> crate::Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        let Some(user) = self.user.as_ref().and_then(|name| state.users.get(name)) else {
+            return Err("NOAUTH Authentication required.".into());
+        };
+
+        // A follower sends `REPLCONF` on its way to `PSYNC`, which allows it
+        let allowed = user.commands.contains(command)
+            || (command == "replconf" && user.commands.contains("psync"));
+
+        if !allowed {
+            let msg = format!(
+                "NOPERM User {} has no permissions to run the '{}' command",
+                user.name, command
+            );
+            let username = user.name.clone();
+            state.log("command", command, &username);
+            return Err(msg.into());
+        }
+
+        let write = is_write(command);
+
+        let denied = keys.iter().find(|key| {
+            !user.keys.iter().any(|pattern| {
+                (if write { pattern.write } else { pattern.read })
+                    && matches(pattern.pattern.as_bytes(), key.as_bytes())
+            })
+        });
+
+        if let Some(key) = denied {
+            let username = user.name.clone();
+            state.log("key", key, &username);
+            return Err("NOPERM No permissions to access a key".into());
+        }
+
+        // A pattern subscription is only allowed by the same pattern, a
+        // narrower allowed pattern would not cover all the channels it gets
+        let literal = command == "psubscribe";
+
+        let denied = channels.iter().find(|channel| {
+            !user.channels.iter().any(|allowed| {
+                allowed == "*"
+                    || if literal {
+                        allowed.as_bytes() == **channel
+                    } else {
+                        matches(allowed.as_bytes(), channel)
+                    }
+            })
+        });
+
+        if let Some(channel) = denied {
+            let username = user.name.clone();
+            state.log("channel", &String::from_utf8_lossy(channel), &username);
+            return Err("NOPERM No permissions to access a channel".into());
+        }
+
+        Ok(())
+    }
+
+    /// Create or modify `name` applying `rules` in order, nothing changes if
+    /// any rule is invalid
+    pub(crate) fn set_user(&self, name: &str, rules: &[String]) -> crate::Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        let mut user = match state.users.get(name) {
+            Some(user) => user.clone(),
+            None => User::new(name),
+        };
+
+        for rule in rules {
+            user.apply(rule).map_err(|reason| {
+                format!("ERR Error in ACL SETUSER modifier '{}': {}", rule, reason)
+            })?;
+        }
+
+        state.users.insert(name.to_string(), user);
+
+        Ok(())
+    }
+
+    pub(crate) fn get_user(&self, name: &str) -> Option<UserInfo> {
+        let state = self.shared.state.lock().unwrap();
+
+        state.users.get(name).map(|user| {
+            let mut flags = vec![if user.enabled { "on" } else { "off" }];
+            if user.nopass {
+                flags.push("nopass");
+            }
+
+            UserInfo {
+                flags,
+                passwords: user.passwords.clone(),
+                commands: user.command_rules.join(" "),
+                keys: user.describe_keys(),
+                channels: user.describe_channels(),
+            }
+        })
+    }
+
+    /// Delete users, returns how many existed. Their connections are not
+    /// authenticated anymore.
+    pub(crate) fn del_users(&self, names: &[String]) -> crate::Result<usize> {
+        if names.iter().any(|name| name == "default") {
+            return Err("ERR The 'default' user cannot be removed".into());
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        Ok(names
+            .iter()
+            .filter(|name| state.users.remove(name.as_str()).is_some())
+            .count())
+    }
+
+    /// Every user as a rule line, as in the users file
+    pub(crate) fn list(&self) -> Vec<String> {
+        let state = self.shared.state.lock().unwrap();
+
+        let mut users: Vec<_> = state.users.values().collect();
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+
+        users.into_iter().map(User::describe).collect()
+    }
+
+    pub(crate) fn usernames(&self) -> Vec<String> {
+        let state = self.shared.state.lock().unwrap();
+
+        let mut names: Vec<_> = state.users.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// The `count` most recent log entries
+    pub(crate) fn log(&self, count: usize) -> Vec<LogEntry> {
+        let state = self.shared.state.lock().unwrap();
+
+        state.log.iter().take(count).cloned().collect()
+    }
+
+    pub(crate) fn reset_log(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+
+        state.log.clear();
+    }
+
+    /// Replace every user with the ones in the users file. The file is
+    /// checked as a whole first, so a bad line changes nothing.
+    pub(crate) fn load(&self) -> crate::Result<()> {
+        let Some(path) = &self.shared.file else {
+            return Err("ERR This instance is not configured to use an ACL file.".into());
+        };
+
+        let contents = std::fs::read_to_string(path).map_err(|err| {
+            format!(
+                "ERR Error loading ACLs, opening file '{}': {}",
+                path.display(),
+                err
+            )
+        })?;
+
+        let mut users = HashMap::new();
+
+        for (number, line) in contents.lines().enumerate() {
+            let mut words = line.split_whitespace();
+
+            let name = match (words.next(), words.next()) {
+                (None, _) => continue,
+                (Some("user"), Some(name)) => name,
+                _ => {
+                    return Err(format!(
+                        "ERR {}:{}: line should start with user keyword",
+                        path.display(),
+                        number + 1
+                    )
+                    .into());
+                }
+            };
+
+            let mut user = User::new(name);
+            for rule in words {
+                user.apply(rule).map_err(|reason| {
+                    format!("ERR {}:{}: {}", path.display(), number + 1, reason)
+                })?;
+            }
+
+            users.insert(name.to_string(), user);
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        // The default user is always there, as created at startup when it
+        // is missing from the file
+        users
+            .entry("default".to_string())
+            .or_insert_with(|| default_user(self.shared.requirepass.as_deref()));
+
+        state.users = users;
+
+        Ok(())
+    }
+
+    /// Write every user to the users file
+    pub(crate) fn save(&self) -> crate::Result<()> {
+        let Some(path) = &self.shared.file else {
+            return Err("ERR This instance is not configured to use an ACL file.".into());
+        };
+
+        let mut contents = self.list().join("\n");
+        contents.push('\n');
+
+        // Write a new file and rename it over the old one, a crash halfway
+        // leaves the old file intact
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, contents)
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .map_err(|err| format!("ERR There was an error trying to save the ACLs: {}", err))?;
+
+        Ok(())
+    }
+}
+
+impl State {
+    /// Record a denial, counting it in the last matching entry if any
+    fn log(&mut self, reason: &'static str, object: &str, username: &str) {
+        let now = SystemTime::now();
+
+        let existing = self.log.iter_mut().find(|entry| {
+            entry.reason == reason && entry.object == object && entry.username == username
+        });
+
+        if let Some(entry) = existing {
+            entry.count += 1;
+            entry.updated = now;
+            return;
+        }
+
+        self.log.push_front(LogEntry {
+            count: 1,
+            reason,
+            object: object.to_string(),
+            username: username.to_string(),
+            entry_id: self.next_entry_id,
+            created: now,
+            updated: now,
+        });
+        self.log.truncate(LOG_MAX_LEN);
+        self.next_entry_id += 1;
+    }
+}
+
+impl User {
+    /// A new user can do nothing and is disabled until enabled with `on`
+    fn new(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: vec![],
+            commands: HashSet::new(),
+            command_rules: vec!["-@all".to_string()],
+            keys: vec![],
+            channels: vec![],
+        }
+    }
+
+    /// A user allowed everything without a password, as the default user
+    /// starts out
+    fn open(name: &str) -> User {
+        let mut user = User::new(name);
+        for rule in ["on", "nopass", "allkeys", "allchannels", "allcommands"] {
+            user.apply(rule).unwrap();
+        }
+        user
+    }
+
+    fn apply(&mut self, rule: &str) -> Result<(), &'static str> {
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.keys = vec![KeyPattern::new("*", true, true)],
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => self.channels = vec!["*".to_string()],
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => self.allow("@all", true)?,
+            "nocommands" => self.allow("@all", false)?,
+            "reset" => *self = User::new(&self.name),
+            _ => return self.apply_pattern(rule),
+        }
+
+        Ok(())
+    }
+
+    /// Rules carrying a value, which keeps its case
+    fn apply_pattern(&mut self, rule: &str) -> Result<(), &'static str> {
+        const BAD_HASH: &str = "The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters";
+
+        if let Some(password) = rule.strip_prefix('>') {
+            let hash = hash(password.as_bytes());
+            if !self.passwords.contains(&hash) {
+                self.passwords.push(hash);
+            }
+            self.nopass = false;
+        } else if let Some(password) = rule.strip_prefix('<') {
+            self.remove_password(&hash(password.as_bytes()))?;
+        } else if let Some(hash) = rule.strip_prefix('#') {
+            if !is_hash(hash) {
+                return Err(BAD_HASH);
+            }
+            if !self.passwords.iter().any(|p| p == hash) {
+                self.passwords.push(hash.to_string());
+            }
+            self.nopass = false;
+        } else if let Some(hash) = rule.strip_prefix('!') {
+            if !is_hash(hash) {
+                return Err(BAD_HASH);
+            }
+            self.remove_password(hash)?;
+        } else if let Some(pattern) = rule.strip_prefix('~') {
+            self.keys.push(KeyPattern::new(pattern, true, true));
+        } else if let Some(rest) = rule.strip_prefix('%') {
+            let (access, pattern) = rest.split_once('~').ok_or("Syntax error")?;
+            let read = access.contains(['r', 'R']);
+            let write = access.contains(['w', 'W']);
+
+            if access.is_empty() || access.contains(|c| !"rRwW".contains(c)) {
+                return Err("Syntax error");
+            }
+
+            self.keys.push(KeyPattern::new(pattern, read, write));
+        } else if let Some(pattern) = rule.strip_prefix('&') {
+            self.channels.push(pattern.to_string());
+        } else if let Some(name) = rule.strip_prefix('+') {
+            self.allow(&name.to_lowercase(), true)?;
+        } else if let Some(name) = rule.strip_prefix('-') {
+            self.allow(&name.to_lowercase(), false)?;
+        } else {
+            return Err("Syntax error");
+        }
+
+        Ok(())
+    }
+
+    /// Allow or deny a command, a container with all its subcommands or a
+    /// `@category`
+    fn allow(&mut self, name: &str, allow: bool) -> Result<(), &'static str> {
+        let commands: Vec<&'static str> = match name.strip_prefix('@') {
+            Some("all") => COMMANDS.iter().map(|(command, _)| *command).collect(),
+            Some(category) if CATEGORIES.contains(&category) => commands_in(category),
+            Some(_) => return Err("Unknown command or category name in ACL"),
+            None => COMMANDS
+                .iter()
+                .map(|(command, _)| *command)
+                .filter(|command| {
+                    *command == name
+                        || command
+                            .strip_prefix(name)
+                            .is_some_and(|rest| rest.starts_with('|'))
+                })
+                .collect(),
+        };
+
+        if commands.is_empty() && !name.starts_with('@') {
+            return Err("Unknown command or category name in ACL");
+        }
+
+        for command in commands {
+            if allow {
+                self.commands.insert(command);
+            } else {
+                self.commands.remove(command);
+            }
+        }
+
+        let rule = format!("{}{}", if allow { '+' } else { '-' }, name);
+        if name == "@all" {
+            self.command_rules = vec![rule];
+        } else {
+            self.command_rules.push(rule);
+        }
+
+        Ok(())
+    }
+
+    fn remove_password(&mut self, hash: &str) -> Result<(), &'static str> {
+        let before = self.passwords.len();
+        self.passwords.retain(|p| p != hash);
+
+        if self.passwords.len() == before {
+            return Err("The password you are trying to remove from the user does not exist");
+        }
+
+        Ok(())
+    }
+
+    fn describe_keys(&self) -> String {
+        let patterns: Vec<_> = self
+            .keys
+            .iter()
+            .map(|key| match (key.read, key.write) {
+                (true, true) => format!("~{}", key.pattern),
+                (true, false) => format!("%R~{}", key.pattern),
+                _ => format!("%W~{}", key.pattern),
+            })
+            .collect();
+
+        patterns.join(" ")
+    }
+
+    fn describe_channels(&self) -> String {
+        let patterns: Vec<_> = self
+            .channels
+            .iter()
+            .map(|pattern| format!("&{}", pattern))
+            .collect();
+
+        patterns.join(" ")
+    }
+
+    /// The user as a line of the users file
+    fn describe(&self) -> String {
+        let mut line = format!(
+            "user {} {}",
+            self.name,
+            if self.enabled { "on" } else { "off" }
+        );
+
+        if self.nopass {
+            line.push_str(" nopass");
+        }
+        for password in &self.passwords {
+            let _ = write!(line, " #{}", password);
+        }
+
+        let keys = self.describe_keys();
+        line.push(' ');
+        line.push_str(if keys.is_empty() { "resetkeys" } else { &keys });
+
+        let channels = self.describe_channels();
+        line.push(' ');
+        line.push_str(if channels.is_empty() {
+            "resetchannels"
+        } else {
+            &channels
+        });
+
+        for rule in &self.command_rules {
+            line.push(' ');
+            line.push_str(rule);
+        }
+
+        line
+    }
+}
+
+impl KeyPattern {
+    fn new(pattern: &str, read: bool, write: bool) -> KeyPattern {
+        KeyPattern {
+            pattern: pattern.to_string(),
+            read,
+            write,
+        }
+    }
+}
+
+pub(crate) fn categories_list() -> &'static [&'static str] {
+    CATEGORIES
+}
+
+/// Commands in `category`, `None` for an unknown category
+pub(crate) fn category_commands(category: &str) -> Option<Vec<&'static str>> {
+    CATEGORIES
+        .contains(&category)
+        .then(|| commands_in(category))
+}
+
+fn commands_in(category: &str) -> Vec<&'static str> {
+    COMMANDS
+        .iter()
+        .filter(|(_, categories)| categories.contains(&category))
+        .map(|(command, _)| *command)
+        .collect()
+}
+
+/// Whether `command` changes the keyspace, a read-only follower refuses it
+pub(crate) fn is_write(command: &str) -> bool {
+    categories(command).contains(&"write")
+}
+
+fn categories(command: &str) -> &'static [&'static str] {
+    COMMANDS
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map_or(&[], |(_, categories)| categories)
+}
+
+/// The `default` user as created at startup, protected by the hashed
+/// `requirepass` password if there is one
+fn default_user(requirepass: Option<&str>) -> User {
+    let mut default = User::open("default");
+    if let Some(hash) = requirepass {
+        default.nopass = false;
+        default.passwords.push(hash.to_string());
+    }
+    default
+}
+
+/// Passwords are kept as the hex encoded SHA-256, as redis does
+fn hash(password: &[u8]) -> String {
+    Sha256::digest(password)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+fn is_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Glob-style match of `string` against `pattern`, supporting `*`, `?`,
+/// `[...]` with ranges and `^` negation, and `\` escapes like redis does.
+///
+/// Only the last `*` is ever backtracked to, which is enough since a later
+/// `*` can absorb anything an earlier one could, so matching takes at most
+/// `pattern.len() * string.len()` steps. A pattern over `MAX_PATTERN_LEN`
+/// matches nothing, which keeps that bounded.
+pub(crate) fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    if pattern.len() > MAX_PATTERN_LEN {
+        return false;
+    }
+
+    let (mut p, mut s) = (0, 0);
+
+    // Where to resume after the last `*`: the pattern after it and the
+    // string position it absorbs up to
+    let mut star: Option<(usize, usize)> = None;
+
+    loop {
+        if p < pattern.len() && pattern[p] == b'*' {
+            while p < pattern.len() && pattern[p] == b'*' {
+                p += 1;
+            }
+            if p == pattern.len() {
+                return true;
+            }
+            star = Some((p, s));
+            continue;
+        }
+
+        if p < pattern.len() && s < string.len() {
+            if let Some(next) = match_one(pattern, p, string[s]) {
+                p = next;
+                s += 1;
+                continue;
+            }
+        } else if p == pattern.len() && s == string.len() {
+            return true;
+        }
+
+        // Let the last `*` absorb one more byte and try again from there
+        match star {
+            Some((after, absorbed)) if absorbed < string.len() => {
+                star = Some((after, absorbed + 1));
+                p = after;
+                s = absorbed + 1;
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// Match `c` against the pattern element at `p`, which is not a `*`.
+/// Returns where the next element starts if it matches.
+fn match_one(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+    match pattern[p] {
+        b'?' => Some(p + 1),
+        b'[' => {
+            let mut i = p + 1;
+            let negate = pattern.get(i) == Some(&b'^');
+            if negate {
+                i += 1;
+            }
+
+            let mut found = false;
+            while i < pattern.len() && pattern[i] != b']' {
+                if pattern[i] == b'\\' && i + 1 < pattern.len() {
+                    found |= pattern[i + 1] == c;
+                    i += 2;
+                } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']'
+                {
+                    let (low, high) = (
+                        pattern[i].min(pattern[i + 2]),
+                        pattern[i].max(pattern[i + 2]),
+                    );
+                    found |= (low..=high).contains(&c);
+                    i += 3;
+                } else {
+                    found |= pattern[i] == c;
+                    i += 1;
+                }
+            }
+
+            // An unterminated class matches like a terminated one
+            (found != negate).then_some((i + 1).min(pattern.len()))
+        }
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users(rules: &[&str]) -> Users {
+        let users = Users::new(None, None).unwrap();
+        let rules: Vec<String> = rules.iter().map(|rule| rule.to_string()).collect();
+        users.set_user("alice", &rules).unwrap();
+
+        let mut session = users.session();
+        session.authenticate("alice", b"secret").unwrap();
+        session
+    }
+
+    #[test]
+    fn glob_patterns_match_like_redis() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*", "", true),
+            ("a*c", "abbbc", true),
+            ("a*c", "abbb", false),
+            ("*b*d", "abcbd", true),
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("h[ae]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("h[b-a]llo", "hallo", true),
+            ("h\\*llo", "h*llo", true),
+            ("h\\*llo", "hello", false),
+            ("h[x", "hx", true),
+            ("user:*:name", "user:1:2:name", true),
+        ];
+
+        for (pattern, string, expected) in cases {
+            assert_eq!(
+                matches(pattern.as_bytes(), string.as_bytes()),
+                *expected,
+                "{:?} against {:?}",
+                pattern,
+                string
+            );
+        }
+    }
+
+    #[test]
+    fn pathological_patterns_match_quickly() {
+        let pattern = "*a".repeat(MAX_PATTERN_LEN / 2);
+        let string = "a".repeat(100_000);
+        assert!(!matches(
+            pattern.as_bytes(),
+            format!("{}b", string).as_bytes()
+        ));
+        assert!(matches(pattern.as_bytes(), string.as_bytes()));
+
+        let long = "a".repeat(300_000);
+        assert!(!matches(long.as_bytes(), long.as_bytes()));
+    }
+
+    #[test]
+    fn disabled_users_are_not_authenticated() {
+        let session = users(&["on", ">secret", "+@all", "~*"]);
+        assert!(session.authenticated());
+
+        session.set_user("alice", &["off".to_string()]).unwrap();
+        assert!(!session.authenticated());
+    }
+
+    #[test]
+    fn channels_are_checked() {
+        let session = users(&["on", ">secret", "+@all", "&news.*"]);
+
+        assert!(session.check("publish", &[], &[b"news.today"]).is_ok());
+        assert!(
+            session
+                .check("subscribe", &[], &[b"news.a", b"news.b"])
+                .is_ok()
+        );
+        let err = session
+            .check("subscribe", &[], &[b"news.a", b"secret"])
+            .unwrap_err();
+        assert!(err.to_string().starts_with("NOPERM"));
+
+        // A pattern is only allowed by the same pattern
+        assert!(session.check("psubscribe", &[], &[b"news.*"]).is_ok());
+        assert!(session.check("psubscribe", &[], &[b"news.t*"]).is_err());
+        assert!(session.check("psubscribe", &[], &[b"*"]).is_err());
+
+        let entry = &session.log(1)[0];
+        assert_eq!((entry.reason, entry.object.as_str()), ("channel", "*"));
+    }
+
+    #[test]
+    fn commands_and_keys_are_checked() {
+        let session = users(&["on", ">secret", "+get", "%R~cache:*"]);
+
+        assert!(session.check("get", &["cache:1"], &[]).is_ok());
+        assert!(session.check("get", &["other"], &[]).is_err());
+        assert!(session.check("set", &["cache:1"], &[]).is_err());
+        assert!(session.check("psync", &[], &[]).is_err());
+        assert!(session.check("replconf", &[], &[]).is_err());
+    }
+
+    #[test]
+    fn psync_allows_replconf() {
+        let session = users(&["on", ">secret", "+psync"]);
+
+        assert!(session.check("psync", &[], &[]).is_ok());
+        assert!(session.check("replconf", &[], &[]).is_ok());
+        assert!(session.check("get", &[], &[]).is_err());
+    }
+}
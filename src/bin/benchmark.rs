@@ -0,0 +1,341 @@
+use bytes::Bytes;
+use clap::Parser;
+use hdrhistogram::Histogram;
+use tokio::task::JoinSet;
+
+use std::hash::{BuildHasher, RandomState};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use tiny_redis::DEFUALT_PORT;
+use tiny_redis::client::{Client, ClientBuilder, Cmd, Pipeline};
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[arg(id = "hostname", long, default_value = "127.0.0.1")]
+    host: String,
+
+    #[clap(short, long, default_value_t = DEFUALT_PORT)]
+    port: u16,
+
+    /// Authenticate as this user, needs a password
+    #[arg(long, requires = "pass")]
+    user: Option<String>,
+
+    /// Password to authenticate with
+    #[arg(short = 'a', long)]
+    pass: Option<String>,
+
+    /// Number of parallel connections
+    #[arg(short, long, default_value_t = 50)]
+    clients: usize,
+
+    /// Total number of requests of each test
+    #[arg(short = 'n', long, default_value_t = 100_000)]
+    requests: u64,
+
+    /// Requests sent at once by each connection
+    #[arg(short = 'P', long, default_value_t = 1)]
+    pipeline: usize,
+
+    /// Use random keys out of this many instead of a single key
+    #[arg(short = 'r', long)]
+    keyspace: Option<u64>,
+
+    /// Size in bytes of the values written
+    #[arg(short = 'd', long, default_value_t = 3)]
+    data_size: usize,
+
+    /// Comma separated list of tests to run. The default ones are those
+    /// tiny-redis supports, the others are for comparing with redis.
+    #[arg(short, long, value_delimiter = ',', default_value = "ping,set,get")]
+    tests: Vec<Test>,
+
+    /// Print the results as CSV
+    #[arg(long)]
+    csv: bool,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum Test {
+    Ping,
+    Set,
+    Get,
+    Incr,
+    Lpush,
+    Rpush,
+    Lpop,
+    Rpop,
+    Sadd,
+    Hset,
+    Mset,
+}
+
+/// What every connection of a test needs to build its requests
+#[derive(Debug)]
+struct Workload {
+    test: Test,
+    keyspace: Option<u64>,
+    value: Bytes,
+    pipeline: usize,
+    requests: u64,
+
+    /// Requests handed out to connections so far
+    issued: AtomicU64,
+}
+
+/// Outcome of a test, latencies are in microseconds
+struct Report {
+    elapsed: Duration,
+    latency: Histogram<u64>,
+
+    /// First error reply, the test stops at the first one
+    error: Option<String>,
+}
+
+/// Keys to send with `MSET`
+const MSET_KEYS: usize = 10;
+
+/// Highest latency recorded, in microseconds
+const MAX_LATENCY: u64 = 60_000_000;
+
+impl Test {
+    fn name(&self) -> &'static str {
+        match self {
+            Test::Ping => "PING",
+            Test::Set => "SET",
+            Test::Get => "GET",
+            Test::Incr => "INCR",
+            Test::Lpush => "LPUSH",
+            Test::Rpush => "RPUSH",
+            Test::Lpop => "LPOP",
+            Test::Rpop => "RPOP",
+            Test::Sadd => "SADD",
+            Test::Hset => "HSET",
+            Test::Mset => "MSET (10 keys)",
+        }
+    }
+}
+
+impl Workload {
+    /// Claim up to a pipeline worth of requests, 0 once all were sent
+    fn claim(&self) -> usize {
+        let start = self
+            .issued
+            .fetch_add(self.pipeline as u64, Ordering::Relaxed);
+
+        self.requests
+            .saturating_sub(start)
+            .min(self.pipeline as u64) as usize
+    }
+
+    /// Build a request of the test, keys are picked with `rng`
+    fn cmd(&self, rng: &mut Rng) -> Cmd {
+        let mut key = |prefix: &str| match self.keyspace {
+            Some(keyspace) => format!("{}:{:012}", prefix, rng.next() % keyspace.max(1)),
+            None => format!("{}:__rand_int__", prefix),
+        };
+
+        match self.test {
+            Test::Ping => Cmd::new("PING"),
+            Test::Set => Cmd::new("SET").arg(key("key")).arg(&self.value),
+            Test::Get => Cmd::new("GET").arg(key("key")),
+            Test::Incr => Cmd::new("INCR").arg(key("counter")),
+            Test::Lpush => Cmd::new("LPUSH").arg("mylist").arg(&self.value),
+            Test::Rpush => Cmd::new("RPUSH").arg("mylist").arg(&self.value),
+            Test::Lpop => Cmd::new("LPOP").arg("mylist"),
+            Test::Rpop => Cmd::new("RPOP").arg("mylist"),
+            Test::Sadd => Cmd::new("SADD").arg("myset").arg(key("element")),
+            Test::Hset => Cmd::new("HSET")
+                .arg("myhash")
+                .arg(key("element"))
+                .arg(&self.value),
+            Test::Mset => (0..MSET_KEYS).fold(Cmd::new("MSET"), |cmd, _| {
+                cmd.arg(key("key")).arg(&self.value)
+            }),
+        }
+    }
+}
+
+/// Xorshift generator for picking keys, seeded through `RandomState` so no
+/// extra dependency is needed
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Rng {
+        Rng(RandomState::new().hash_one(0u64) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[tokio::main]
+async fn main() -> tiny_redis::Result<()> {
+    let cli = Cli::parse();
+
+    let addr = format!("{}:{}", cli.host, cli.port);
+
+    if cli.csv {
+        println!(
+            "\"test\",\"rps\",\"avg_latency_ms\",\"p50_latency_ms\",\"p99_latency_ms\",\"p999_latency_ms\",\"max_latency_ms\""
+        );
+    }
+
+    for &test in &cli.tests {
+        let workload = Arc::new(Workload {
+            test,
+            keyspace: cli.keyspace,
+            value: Bytes::from(vec![b'x'; cli.data_size]),
+            pipeline: cli.pipeline.max(1),
+            requests: cli.requests,
+            issued: AtomicU64::new(0),
+        });
+
+        let mut builder = ClientBuilder::new(&addr);
+        if let Some(pass) = &cli.pass {
+            builder = match &cli.user {
+                Some(user) => builder.user(user, pass.clone()),
+                None => builder.password(pass.clone()),
+            };
+        }
+
+        let report = run(builder, cli.clients.max(1), workload).await?;
+
+        if let Some(error) = &report.error {
+            eprintln!("{}: skipped, the server replied: {}", test.name(), error);
+        } else if cli.csv {
+            print_csv(test, cli.requests, &report);
+        } else {
+            print_report(test, &cli, &report);
+        }
+    }
+
+    Ok(())
+}
+
+/// Run one test over `clients` connections
+async fn run(
+    builder: ClientBuilder,
+    clients: usize,
+    workload: Arc<Workload>,
+) -> tiny_redis::Result<Report> {
+    // Connect everyone first so connecting is not part of the measure
+    let mut connections = Vec::with_capacity(clients);
+    for _ in 0..clients {
+        connections.push(builder.clone().connect().await?);
+    }
+
+    let start = Instant::now();
+
+    let mut tasks = JoinSet::new();
+    for client in connections {
+        tasks.spawn(drive(client, workload.clone()));
+    }
+
+    let mut report = Report {
+        elapsed: Duration::ZERO,
+        latency: Histogram::new_with_bounds(1, MAX_LATENCY, 3)?,
+        error: None,
+    };
+
+    while let Some(result) = tasks.join_next().await {
+        let (latency, error) = result??;
+
+        report.latency.add(latency)?;
+        report.error = report.error.or(error);
+    }
+
+    report.elapsed = start.elapsed();
+
+    Ok(report)
+}
+
+/// Send requests of the workload on `client` until all of them were sent.
+/// Every request of a batch is given the latency of the whole batch.
+async fn drive(
+    mut client: Client,
+    workload: Arc<Workload>,
+) -> tiny_redis::Result<(Histogram<u64>, Option<String>)> {
+    let mut latency = Histogram::new_with_bounds(1, MAX_LATENCY, 3)?;
+    let mut rng = Rng::new();
+
+    loop {
+        let count = workload.claim();
+        if count == 0 {
+            return Ok((latency, None));
+        }
+
+        let mut pipeline = Pipeline::new();
+        for _ in 0..count {
+            pipeline.add(workload.cmd(&mut rng));
+        }
+
+        let start = Instant::now();
+        let replies = pipeline.execute(&mut client).await?;
+        let micros = start.elapsed().as_micros() as u64;
+
+        latency.saturating_record_n(micros.max(1), count as u64);
+
+        if let Some(Err(err)) = replies.into_iter().find(Result::is_err) {
+            // Make the other connections stop too
+            workload.issued.store(workload.requests, Ordering::Relaxed);
+            return Ok((latency, Some(err.to_string())));
+        }
+    }
+}
+
+fn print_report(test: Test, cli: &Cli, report: &Report) {
+    let latency = &report.latency;
+    let secs = report.elapsed.as_secs_f64();
+
+    println!("====== {} ======", test.name());
+    println!(
+        "  {} requests completed in {:.2} seconds",
+        cli.requests, secs
+    );
+    println!("  {} parallel clients", cli.clients);
+    println!("  {} bytes payload", cli.data_size);
+    println!("  pipeline depth {}", cli.pipeline);
+    println!();
+    println!("Latency summary (msec):");
+    println!(
+        "  {:>9} {:>9} {:>9} {:>9} {:>9}",
+        "avg", "p50", "p99", "p99.9", "max"
+    );
+    println!(
+        "  {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+        latency.mean() / 1000.0,
+        millis(latency.value_at_quantile(0.5)),
+        millis(latency.value_at_quantile(0.99)),
+        millis(latency.value_at_quantile(0.999)),
+        millis(latency.max()),
+    );
+    println!();
+    println!("  {:.2} requests per second", cli.requests as f64 / secs);
+    println!();
+}
+
+fn print_csv(test: Test, requests: u64, report: &Report) {
+    let latency = &report.latency;
+
+    println!(
+        "\"{}\",\"{:.2}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\"",
+        test.name(),
+        requests as f64 / report.elapsed.as_secs_f64(),
+        latency.mean() / 1000.0,
+        millis(latency.value_at_quantile(0.5)),
+        millis(latency.value_at_quantile(0.99)),
+        millis(latency.value_at_quantile(0.999)),
+        millis(latency.max()),
+    );
+}
+
+fn millis(micros: u64) -> f64 {
+    micros as f64 / 1000.0
+}
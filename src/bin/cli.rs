@@ -1,19 +1,62 @@
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use clap::{Parser, Subcommand};
 use log::info;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::oneshot;
+use tokio_util::codec::Decoder;
 
-use tiny_redis::{DEFUALT_PORT, client::Client};
+use std::path::PathBuf;
+
+use tiny_redis::client::{Client, ClientBuilder, Cmd, Pipeline, ReconnectPolicy};
+use tiny_redis::{DEFUALT_PORT, codec::FrameCodec, frame::Frame};
 
 #[derive(Parser, Debug)]
 struct Cli {
+    /// Without a command, start an interactive session
     #[clap(subcommand)]
-    command: Command,
+    command: Option<Command>,
 
     #[arg(id = "hostname", long, default_value = "127.0.0.1")]
     host: String,
 
     #[clap(short, long, default_value_t = DEFUALT_PORT)]
     port: u16,
+
+    /// Authenticate as this user, needs a password
+    #[arg(long, requires = "pass")]
+    user: Option<String>,
+
+    /// Password to authenticate with
+    #[arg(short = 'a', long)]
+    pass: Option<String>,
+
+    /// Connect over TLS
+    #[arg(long, requires = "cacert")]
+    tls: bool,
+
+    /// CA certificate the server certificate must be signed by
+    #[arg(long, requires = "tls")]
+    cacert: Option<PathBuf>,
+
+    /// Client certificate, for a server that verifies its clients
+    #[arg(long, requires_all = ["tls", "key"])]
+    cert: Option<PathBuf>,
+
+    /// Private key of the client certificate
+    #[arg(long, requires = "cert")]
+    key: Option<PathBuf>,
+
+    /// Name the server certificate must be valid for, the hostname by
+    /// default
+    #[arg(long, requires = "tls")]
+    sni: Option<String>,
+
+    /// Send the commands read from stdin, either RESP or one command per
+    /// line, and report how many replies and errors came back
+    #[arg(long)]
+    pipe: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -23,11 +66,14 @@ enum Command {
     Set { key: String, value: Bytes },
 }
 
-fn init_env_logger() {
+/// File the interactive session keeps its history in, under `$HOME`
+const HISTORY_FILE: &str = ".tiny_redis_cli_history";
+
+fn init_env_logger(default_filter: &str) {
     use chrono::Local;
     use std::io::Write;
 
-    let env = env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "trace");
+    let env = env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, default_filter);
     env_logger::Builder::from_env(env)
         .format(|buf, record| {
             writeln!(
@@ -46,15 +92,45 @@ fn init_env_logger() {
 
 #[tokio::main]
 async fn main() -> tiny_redis::Result<()> {
-    init_env_logger();
-
     let cli = Cli::parse();
 
     let addr = format!("{}:{}", cli.host, cli.port);
 
-    let mut client = Client::connect(addr).await.unwrap();
+    let mut builder = ClientBuilder::new(&addr);
+    if let Some(pass) = cli.pass {
+        builder = match &cli.user {
+            Some(user) => builder.user(user, pass),
+            None => builder.password(pass),
+        };
+    }
+    if cli.tls
+        && let Some(cacert) = cli.cacert
+    {
+        builder = builder.tls(cacert);
+        if let (Some(cert), Some(key)) = (cli.cert, cli.key) {
+            builder = builder.client_cert(cert, key);
+        }
+        if let Some(sni) = cli.sni {
+            builder = builder.server_name(sni);
+        }
+    }
+
+    if cli.pipe {
+        init_env_logger("warn");
+        return pipe(builder).await;
+    }
+
+    let Some(command) = cli.command else {
+        // Log lines would get in the way of the replies
+        init_env_logger("warn");
+        return repl(addr, builder).await;
+    };
 
-    match cli.command {
+    init_env_logger("trace");
+
+    let mut client = builder.connect().await?;
+
+    match command {
         Command::Ping { msg } => {
             let value = client.ping(msg).await?;
             let string = str::from_utf8(&value)?;
@@ -65,7 +141,7 @@ async fn main() -> tiny_redis::Result<()> {
             info!("OK");
         }
         Command::Get { key } => {
-            if let Some(value) = client.get(&key).await? {
+            if let Some(value) = client.get::<Option<Bytes>>(&key).await? {
                 if let Ok(string) = str::from_utf8(&value) {
                     info!("\"{}\"", string);
                 } else {
@@ -75,8 +151,290 @@ async fn main() -> tiny_redis::Result<()> {
                 info!("(nil)")
             }
         }
-        _ => unimplemented!(),
     }
 
     Ok(())
 }
+
+/// Read commands from the terminal and print their replies, like
+/// `redis-cli` does without a command
+async fn repl(addr: String, builder: ClientBuilder) -> tiny_redis::Result<()> {
+    let mut client = builder
+        .reconnect(ReconnectPolicy::default())
+        .connect()
+        .await
+        .map_err(|err| format!("Could not connect to {}: {}", addr, err))?;
+
+    let mut editor = DefaultEditor::new()?;
+    let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+    if let Some(path) = &history {
+        // There is no history on the first run
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        // The selected database is shown when it is not 0. The client
+        // selects it again if it reconnects, so the prompt stays true.
+        let prompt = match client.db() {
+            0 => format!("{}> ", addr),
+            db => format!("{}[{}]> ", addr, db),
+        };
+
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        let args: Vec<Bytes> = match Frame::from_inline(line.as_bytes()) {
+            Ok(Frame::Array(args)) => args
+                .into_iter()
+                .filter_map(|arg| match arg {
+                    Frame::Bulk(arg) => Some(arg),
+                    _ => None,
+                })
+                .collect(),
+            _ => {
+                println!("Invalid argument(s)");
+                continue;
+            }
+        };
+
+        let Some(name) = args.first() else {
+            continue;
+        };
+        let name = String::from_utf8_lossy(name).to_lowercase();
+
+        editor.add_history_entry(line.as_str())?;
+
+        if name == "quit" || name == "exit" {
+            break;
+        }
+
+        match run(&mut client, &name, &args[1..]).await {
+            Ok(reply) => print!("{}", format_reply(&reply, "")),
+            Err(err) => println!("Could not send the command to {}: {}", addr, err),
+        }
+    }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+
+    Ok(())
+}
+
+/// Send one command typed at the prompt. Error replies are returned as
+/// frames, an error means the server could not be reached.
+async fn run(client: &mut Client, name: &str, args: &[Bytes]) -> tiny_redis::Result<Frame> {
+    // The client must know about a protocol switch to read the replies
+    if let ("hello", [protocol]) = (name, args)
+        && let Some(protocol) = atoi::atoi::<u64>(protocol)
+    {
+        return Ok(client
+            .hello(Some(protocol))
+            .await
+            .unwrap_or_else(|err| Frame::Error(err.to_string())));
+    }
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add(Cmd::new(name).arg(args));
+
+    let reply = pipeline.execute(client).await?.pop().unwrap();
+
+    Ok(reply.unwrap_or_else(|err| Frame::Error(err.to_string())))
+}
+
+/// Stream the commands on stdin to the server over a single connection, for
+/// mass insertion. Replies are read by a task of their own while commands are
+/// still being written, so neither direction waits for the other.
+async fn pipe(builder: ClientBuilder) -> tiny_redis::Result<()> {
+    // The client authenticates, then its stream carries the raw frames
+    let (stream, buffered) = builder.connect().await?.into_connection().into_inner();
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    let (sent_tx, sent_rx) = oneshot::channel();
+    let replies = tokio::spawn(read_replies(reader, buffered, sent_rx));
+
+    // The codec reads inline commands as well as RESP, like the server does
+    let mut codec = FrameCodec::new();
+    let mut stdin = tokio::io::stdin();
+    let mut input = BytesMut::with_capacity(64 * 1024);
+    let mut out = BytesMut::with_capacity(64 * 1024);
+    let mut sent = 0u64;
+
+    loop {
+        let eof = stdin.read_buf(&mut input).await? == 0;
+
+        // The last command may not end with a newline
+        if eof && !input.is_empty() && !input.ends_with(b"\n") {
+            input.extend_from_slice(b"\r\n");
+        }
+
+        loop {
+            let frame = if eof {
+                codec.decode_eof(&mut input)?
+            } else {
+                codec.decode(&mut input)?
+            };
+            let Some(frame) = frame else {
+                break;
+            };
+
+            frame.encode(&mut out);
+            sent += 1;
+        }
+
+        writer.write_all(&out).await?;
+        out.clear();
+
+        if eof {
+            break;
+        }
+    }
+
+    writer.flush().await?;
+
+    // Tell the reader how many replies to expect
+    let _ = sent_tx.send(sent);
+    let (replies, errors) = replies.await??;
+
+    println!("All data transferred, last reply received from server.");
+    println!("errors: {}, replies: {}", errors, replies);
+
+    if errors > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Read the replies of pipe mode until as many as `sent` came back, printing
+/// the errors. Returns the number of replies and of errors.
+async fn read_replies(
+    mut reader: impl AsyncRead + Unpin,
+    mut buffer: BytesMut,
+    mut sent: oneshot::Receiver<u64>,
+) -> tiny_redis::Result<(u64, u64)> {
+    let mut codec = FrameCodec::new();
+    let mut expected = None;
+
+    let mut replies = 0u64;
+    let mut errors = 0u64;
+
+    loop {
+        while let Some(frame) = codec.decode(&mut buffer)? {
+            if let Frame::Error(msg) = frame {
+                eprintln!("{}", msg);
+                errors += 1;
+            }
+
+            replies += 1;
+        }
+
+        if expected == Some(replies) {
+            return Ok((replies, errors));
+        }
+
+        tokio::select! {
+            read = reader.read_buf(&mut buffer) => {
+                if read? == 0 {
+                    return Err("connection reset by server".into());
+                }
+            }
+            total = &mut sent, if expected.is_none() => {
+                expected = Some(total?);
+            }
+        }
+    }
+}
+
+/// Render a reply the way `redis-cli` does on a terminal. Nested aggregates
+/// are numbered and every line after the first one starts with `indent`.
+fn format_reply(frame: &Frame, indent: &str) -> String {
+    match frame {
+        Frame::Simple(s) => format!("{}\n", s),
+        Frame::Error(msg) => format!("(error) {}\n", msg),
+        Frame::Integer(n) => format!("(integer) {}\n", n),
+        Frame::Bulk(data) => format!("{}\n", quote(data)),
+        Frame::Null => "(nil)\n".to_string(),
+        Frame::Double(n) => format!("(double) {}\n", n),
+        Frame::Boolean(b) => format!("({})\n", b),
+        Frame::BigNumber(n) => format!("(big number) {}\n", n),
+        Frame::Verbatim { data, .. } => format!("{}\n", String::from_utf8_lossy(data)),
+        Frame::Attribute { data, .. } => format_reply(data, indent),
+        Frame::Array(items) | Frame::Push(items) => format_list(items, ')', "array", indent),
+        Frame::Set(items) => format_list(items, '~', "set", indent),
+        Frame::Map(pairs) => {
+            if pairs.is_empty() {
+                return "(empty hash)\n".to_string();
+            }
+
+            let width = pairs.len().to_string().len();
+            let mut out = String::new();
+
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(indent);
+                }
+
+                let label = format!("{:>width$}# ", i + 1);
+                let key = format_reply(key, "");
+                let key = key.trim_end();
+                let nested = format!("{}{}", indent, " ".repeat(label.len() + key.len() + 4));
+
+                out.push_str(&label);
+                out.push_str(key);
+                out.push_str(" => ");
+                out.push_str(&format_reply(value, &nested));
+            }
+
+            out
+        }
+    }
+}
+
+fn format_list(items: &[Frame], marker: char, kind: &str, indent: &str) -> String {
+    if items.is_empty() {
+        return format!("(empty {})\n", kind);
+    }
+
+    let width = items.len().to_string().len();
+    let mut out = String::new();
+
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(indent);
+        }
+
+        let label = format!("{:>width$}{} ", i + 1, marker);
+        let nested = format!("{}{}", indent, " ".repeat(label.len()));
+
+        out.push_str(&label);
+        out.push_str(&format_reply(item, &nested));
+    }
+
+    out
+}
+
+/// Double quote `data`, escaping what would not print
+fn quote(data: &[u8]) -> String {
+    let mut out = String::from("\"");
+
+    for &c in data {
+        match c {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            c if c.is_ascii_graphic() || c == b' ' => out.push(c as char),
+            c => out.push_str(&format!("\\x{:02x}", c)),
+        }
+    }
+
+    out.push('"');
+    out
+}
@@ -1,13 +1,108 @@
-use clap::Parser;
-use log::info;
+use clap::{ArgAction, Parser};
+use log::{error, info};
 use tokio::net::TcpListener;
 
-use tiny_redis::{DEFUALT_PORT, server};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tiny_redis::frame::Limits;
+use tiny_redis::{
+    DEFAULT_BACKLOG_SIZE, DEFAULT_BUSY_REPLY_THRESHOLD, DEFAULT_DATABASES, DEFUALT_PORT,
+    KeyspaceEvents, server,
+};
 
 #[derive(Parser, Debug)]
 struct Cli {
     #[arg(long)]
     port: Option<u16>,
+
+    /// Number of logical databases
+    #[arg(long, default_value_t = DEFAULT_DATABASES)]
+    databases: usize,
+
+    /// Longest accepted bulk string in bytes
+    #[arg(long)]
+    proto_max_bulk_len: Option<u64>,
+
+    /// Most elements accepted in a multibulk request
+    #[arg(long)]
+    max_multibulk_len: Option<u64>,
+
+    /// Deepest accepted nesting of aggregate frames
+    #[arg(long)]
+    max_nesting_depth: Option<usize>,
+
+    /// Most unparsed bytes buffered per client
+    #[arg(long)]
+    client_query_buffer_limit: Option<usize>,
+
+    /// Require clients to `AUTH` with this password
+    #[arg(long)]
+    requirepass: Option<String>,
+
+    /// Load users and their permissions from this file
+    #[arg(long)]
+    aclfile: Option<PathBuf>,
+
+    /// Also accept TLS connections on this port
+    #[arg(long, requires_all = ["tls_cert_file", "tls_key_file"])]
+    tls_port: Option<u16>,
+
+    /// Server certificate, PEM encoded
+    #[arg(long, requires = "tls_port")]
+    tls_cert_file: Option<PathBuf>,
+
+    /// Private key of the server certificate, PEM encoded
+    #[arg(long, requires = "tls_port")]
+    tls_key_file: Option<PathBuf>,
+
+    /// Verify client certificates against this CA, PEM encoded
+    #[arg(long, requires = "tls_port")]
+    tls_ca_cert_file: Option<PathBuf>,
+
+    /// Whether clients must present a certificate when a CA is set
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    tls_auth_clients: bool,
+
+    /// Follow the leader at this host and port
+    #[arg(long, num_args = 2, value_names = ["HOST", "PORT"])]
+    replicaof: Option<Vec<String>>,
+
+    /// Refuse writes from clients while following a leader
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    replica_read_only: bool,
+
+    /// User to authenticate to the leader as
+    #[arg(long, requires = "masterauth")]
+    masteruser: Option<String>,
+
+    /// Password to authenticate to the leader with
+    #[arg(long)]
+    masterauth: Option<String>,
+
+    /// Bytes of the replication stream kept for followers that reconnect
+    #[arg(long, default_value_t = DEFAULT_BACKLOG_SIZE)]
+    repl_backlog_size: usize,
+
+    /// Run as a cluster node
+    #[arg(long)]
+    cluster_enabled: bool,
+
+    /// Keep the cluster state in this file
+    #[arg(long, requires = "cluster_enabled")]
+    cluster_config_file: Option<PathBuf>,
+
+    /// Milliseconds a script runs before other clients get a `BUSY` reply
+    #[arg(long, alias = "lua-time-limit")]
+    busy_reply_threshold: Option<u64>,
+
+    /// Keep the function libraries in this file
+    #[arg(long)]
+    functions_file: Option<PathBuf>,
+
+    /// Keyspace event classes published, like `KEA`, none by default
+    #[arg(long, default_value = "", value_parser = KeyspaceEvents::parse)]
+    notify_keyspace_events: KeyspaceEvents,
 }
 
 // Use beijing time (UTC+8)
@@ -43,5 +138,54 @@ pub async fn main() {
         .await
         .unwrap();
 
-    server::run(listener).await;
+    let defaults = Limits::default();
+
+    let replicaof = match cli.replicaof.as_deref() {
+        Some([host, port]) => match port.parse() {
+            Ok(port) => Some((host.clone(), port)),
+            Err(_) => {
+                error!("invalid leader port: {}", port);
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+
+    let config = server::Config {
+        databases: cli.databases,
+        limits: Limits {
+            max_bulk_len: cli.proto_max_bulk_len.unwrap_or(defaults.max_bulk_len),
+            max_multibulk_len: cli.max_multibulk_len.unwrap_or(defaults.max_multibulk_len),
+            max_depth: cli.max_nesting_depth.unwrap_or(defaults.max_depth),
+            query_buffer_limit: cli
+                .client_query_buffer_limit
+                .unwrap_or(defaults.query_buffer_limit),
+        },
+        requirepass: cli.requirepass,
+        aclfile: cli.aclfile,
+        tls: cli.tls_port.map(|port| server::TlsConfig {
+            port,
+            cert_file: cli.tls_cert_file.unwrap(),
+            key_file: cli.tls_key_file.unwrap(),
+            ca_cert_file: cli.tls_ca_cert_file,
+            auth_clients: cli.tls_auth_clients,
+        }),
+        replicaof,
+        replica_read_only: cli.replica_read_only,
+        masteruser: cli.masteruser,
+        masterauth: cli.masterauth,
+        repl_backlog_size: cli.repl_backlog_size,
+        cluster_enabled: cli.cluster_enabled,
+        cluster_config_file: cli.cluster_config_file,
+        busy_reply_threshold: cli
+            .busy_reply_threshold
+            .map_or(DEFAULT_BUSY_REPLY_THRESHOLD, Duration::from_millis),
+        functions_file: cli.functions_file,
+        notify_keyspace_events: cli.notify_keyspace_events,
+    };
+
+    if let Err(err) = server::run(listener, config).await {
+        error!("server failed: {}", err);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,308 @@
+use bytes::Bytes;
+use std::collections::{HashSet, VecDeque};
+use tokio::net::ToSocketAddrs;
+use tokio::runtime::Runtime;
+
+use crate::client::{self, ClientBuilder, Cmd, Pipeline};
+use crate::connection::Connection;
+use crate::frame::Frame;
+use crate::types::{FromFrame, ToArgs};
+
+/// Blocking counterpart of `client::Client`, for code that doesn't run
+/// inside a tokio runtime. It owns a current thread runtime and blocks on
+/// the matching async method.
+#[derive(Debug)]
+pub struct Client {
+    inner: client::Client,
+
+    // runs the futures of `inner`
+    rt: Runtime,
+}
+
+/// A `Cmd` being built for a blocking client, returned by `Client::cmd`
+#[derive(Debug)]
+pub struct CmdBuilder<'a> {
+    client: &'a mut Client,
+    cmd: Cmd,
+}
+
+/// A client turned into a subscriber by `Client::subscribe` or
+/// `Client::psubscribe`. Its connection only receives messages from then
+/// on, and changes of its subscriptions.
+#[derive(Debug)]
+pub struct Subscriber {
+    connection: Connection,
+    rt: Runtime,
+
+    channels: HashSet<Bytes>,
+    patterns: HashSet<Bytes>,
+
+    // messages read while waiting for a subscription to be confirmed
+    pending: VecDeque<Message>,
+}
+
+/// A message published to a channel a `Subscriber` receives
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub channel: Bytes,
+    /// The pattern the channel matched, for a pattern subscription
+    pub pattern: Option<Bytes>,
+    pub payload: Bytes,
+}
+
+/// What the server pushes to a subscriber
+enum Push {
+    Message(Message),
+    /// A subscription changed, as `subscribe` and the channel or pattern
+    Confirmation(Bytes, Option<Bytes>),
+}
+
+impl Client {
+    pub fn connect<T: ToSocketAddrs>(addr: T) -> crate::Result<Client> {
+        let rt = runtime()?;
+
+        let inner = rt.block_on(client::Client::connect(addr))?;
+
+        Ok(Client { inner, rt })
+    }
+
+    /// Connect with the options of `builder`
+    pub fn connect_with(builder: ClientBuilder) -> crate::Result<Client> {
+        let rt = runtime()?;
+
+        let inner = rt.block_on(builder.connect())?;
+
+        Ok(Client { inner, rt })
+    }
+
+    /// Start building an arbitrary command, for example
+    /// `client.cmd("SELECT").arg(1).query::<()>()`
+    pub fn cmd(&mut self, name: &str) -> CmdBuilder<'_> {
+        CmdBuilder {
+            client: self,
+            cmd: Cmd::new(name),
+        }
+    }
+
+    /// Send `cmd` and convert its reply, see `client::Client::query`
+    pub fn query<T: FromFrame>(&mut self, cmd: Cmd) -> crate::Result<T> {
+        self.rt.block_on(self.inner.query(cmd))
+    }
+
+    pub fn ping(&mut self, msg: Option<Bytes>) -> crate::Result<Bytes> {
+        self.rt.block_on(self.inner.ping(msg))
+    }
+
+    pub fn hello(&mut self, protocol: Option<u64>) -> crate::Result<Frame> {
+        self.rt.block_on(self.inner.hello(protocol))
+    }
+
+    pub fn select(&mut self, db: u64) -> crate::Result<()> {
+        self.rt.block_on(self.inner.select(db))
+    }
+
+    pub fn set<V: ToArgs>(&mut self, key: &str, value: V) -> crate::Result<()> {
+        self.rt.block_on(self.inner.set(key, value))
+    }
+
+    pub fn get<T: FromFrame>(&mut self, key: &str) -> crate::Result<T> {
+        self.rt.block_on(self.inner.get(key))
+    }
+
+    pub fn mget<T: FromFrame>(&mut self, keys: &[&str]) -> crate::Result<T> {
+        self.rt.block_on(self.inner.mget(keys))
+    }
+
+    pub fn del(&mut self, keys: &[&str]) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.del(keys))
+    }
+
+    /// Run a pipeline, see `Pipeline::query`
+    pub fn query_pipeline<T: FromFrame>(&mut self, pipeline: &Pipeline) -> crate::Result<T> {
+        self.rt.block_on(pipeline.query(&mut self.inner))
+    }
+
+    /// Run a pipeline, see `Pipeline::execute`
+    pub fn execute(&mut self, pipeline: &Pipeline) -> crate::Result<Vec<crate::Result<Frame>>> {
+        self.rt.block_on(pipeline.execute(&mut self.inner))
+    }
+
+    /// Subscribe to `channels`, the client becomes a `Subscriber`
+    pub fn subscribe(self, channels: &[&str]) -> crate::Result<Subscriber> {
+        let mut subscriber = self.into_subscriber();
+        subscriber.subscribe(channels)?;
+        Ok(subscriber)
+    }
+
+    /// Subscribe to the channels matching `patterns`, the client becomes a
+    /// `Subscriber`
+    pub fn psubscribe(self, patterns: &[&str]) -> crate::Result<Subscriber> {
+        let mut subscriber = self.into_subscriber();
+        subscriber.psubscribe(patterns)?;
+        Ok(subscriber)
+    }
+
+    fn into_subscriber(self) -> Subscriber {
+        Subscriber {
+            connection: self.inner.into_connection(),
+            rt: self.rt,
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl CmdBuilder<'_> {
+    /// Append the arguments `arg` converts to
+    pub fn arg<A: ToArgs>(mut self, arg: A) -> Self {
+        self.cmd = self.cmd.arg(arg);
+        self
+    }
+
+    /// Send the command and convert its reply, use `Frame` for the raw reply
+    pub fn query<T: FromFrame>(self) -> crate::Result<T> {
+        self.client.query(self.cmd)
+    }
+}
+
+impl Subscriber {
+    pub fn subscribe(&mut self, channels: &[&str]) -> crate::Result<()> {
+        self.request("subscribe", channels, channels.len())
+    }
+
+    pub fn psubscribe(&mut self, patterns: &[&str]) -> crate::Result<()> {
+        self.request("psubscribe", patterns, patterns.len())
+    }
+
+    /// Stop receiving the messages of `channels`, of every channel if empty
+    pub fn unsubscribe(&mut self, channels: &[&str]) -> crate::Result<()> {
+        // Without channels the server confirms each one, or once if none
+        let confirmations = match channels.len() {
+            0 => self.channels.len().max(1),
+            len => len,
+        };
+
+        self.request("unsubscribe", channels, confirmations)
+    }
+
+    /// Stop receiving the messages of `patterns`, of every pattern if empty
+    pub fn punsubscribe(&mut self, patterns: &[&str]) -> crate::Result<()> {
+        let confirmations = match patterns.len() {
+            0 => self.patterns.len().max(1),
+            len => len,
+        };
+
+        self.request("punsubscribe", patterns, confirmations)
+    }
+
+    /// Channels subscribed to, patterns included
+    pub fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    /// Wait for the next message
+    pub fn next_message(&mut self) -> crate::Result<Message> {
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(message);
+        }
+
+        loop {
+            if let Push::Message(message) = self.read()? {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Send `command` and wait for its confirmations, keeping the messages
+    /// that arrive meanwhile for `next_message`
+    fn request(
+        &mut self,
+        command: &str,
+        names: &[&str],
+        confirmations: usize,
+    ) -> crate::Result<()> {
+        let frame = Cmd::new(command).arg(names).into_frame();
+        self.rt.block_on(self.connection.write_frame(&frame))?;
+
+        let mut confirmed = 0;
+        while confirmed < confirmations {
+            match self.read()? {
+                Push::Message(message) => self.pending.push_back(message),
+                Push::Confirmation(kind, name) => {
+                    self.track(&kind, name);
+                    confirmed += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keep the subscriptions the server confirmed
+    fn track(&mut self, kind: &[u8], name: Option<Bytes>) {
+        let Some(name) = name else {
+            return;
+        };
+
+        match kind {
+            b"subscribe" => self.channels.insert(name),
+            b"unsubscribe" => self.channels.remove(&name),
+            b"psubscribe" => self.patterns.insert(name),
+            b"punsubscribe" => self.patterns.remove(&name),
+            _ => false,
+        };
+    }
+
+    /// Read the next push, an error reply is returned as an error
+    fn read(&mut self) -> crate::Result<Push> {
+        let frame = self
+            .rt
+            .block_on(self.connection.read_frame())?
+            .ok_or("connection reset by server")?;
+
+        let parts = match frame {
+            Frame::Array(parts) | Frame::Push(parts) => parts,
+            Frame::Error(msg) => return Err(msg.into()),
+            frame => return Err(frame.to_error()),
+        };
+
+        let push = match &parts[..] {
+            [
+                Frame::Bulk(kind),
+                Frame::Bulk(channel),
+                Frame::Bulk(payload),
+            ] if kind == "message" => Push::Message(Message {
+                channel: channel.clone(),
+                pattern: None,
+                payload: payload.clone(),
+            }),
+            [
+                Frame::Bulk(kind),
+                Frame::Bulk(pattern),
+                Frame::Bulk(channel),
+                Frame::Bulk(payload),
+            ] if kind == "pmessage" => Push::Message(Message {
+                channel: channel.clone(),
+                pattern: Some(pattern.clone()),
+                payload: payload.clone(),
+            }),
+            [Frame::Bulk(kind), name, Frame::Integer(_)] => {
+                let name = match name {
+                    Frame::Bulk(name) => Some(name.clone()),
+                    _ => None,
+                };
+                Push::Confirmation(kind.clone(), name)
+            }
+            _ => return Err(Frame::Array(parts).to_error()),
+        };
+
+        Ok(push)
+    }
+}
+
+fn runtime() -> crate::Result<Runtime> {
+    Ok(tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?)
+}
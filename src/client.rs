@@ -1,76 +0,0 @@
-use bytes::Bytes;
-use std::io::{Error, ErrorKind};
-use tokio::net::{TcpStream, ToSocketAddrs};
-
-use crate::cmd::{Get, Ping, Set};
-use crate::connection::{self, Connection};
-use crate::frame::Frame;
-
-pub struct Client {
-    connection: Connection,
-}
-
-impl Client {
-    pub async fn connect<T: ToSocketAddrs>(addr: T) -> crate::Result<Client> {
-        let socket = TcpStream::connect(addr).await?;
-
-        let connection = Connection::new(socket);
-
-        Ok(Client { connection })
-    }
-
-    async fn read_response(&mut self) -> crate::Result<Frame> {
-        let response = self.connection.read_frame().await?;
-
-        match response {
-            Some(Frame::Error(msg)) => Err(msg.into()),
-            Some(frame) => Ok(frame),
-            None => {
-                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by server");
-                Err(err.into())
-            }
-        }
-    }
-
-    pub async fn ping(&mut self, msg: Option<Bytes>) -> crate::Result<Bytes> {
-        let frame = Ping::new(msg).into_frame();
-
-        self.connection.write_frame(&frame).await?;
-
-        match self.read_response().await? {
-            Frame::Simple(s) => Ok(s.into()),
-            Frame::Bulk(value) => Ok(value),
-            frame => Err(frame.to_error()),
-        }
-    }
-
-    pub async fn set(&mut self, key: &str, value: Bytes) -> crate::Result<()> {
-        let frame = Set::new(key, value).into_frame();
-
-        self.connection.write_frame(&frame).await?;
-
-        match self.read_response().await? {
-            Frame::Simple(response) => {
-                if response == "OK" {
-                    Ok(())
-                } else {
-                    Err(format!("error response {}", response).into())
-                }
-            }
-            frame => Err(frame.to_error()),
-        }
-    }
-
-    pub async fn get(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
-        let frame = Get::new(key).into_frame();
-
-        self.connection.write_frame(&frame).await?;
-
-        match self.read_response().await? {
-            Frame::Simple(value) => Ok(Some(value.into())),
-            Frame::Bulk(response) => Ok(Some(response)),
-            Frame::Null => Ok(None),
-            frame => Err(frame.to_error()),
-        }
-    }
-}
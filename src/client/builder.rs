@@ -0,0 +1,252 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use rustls::ClientConfig;
+use rustls::pki_types::ServerName;
+
+use crate::client::{Client, ClusterClient, MultiplexedClient, Pool, PoolConfig};
+use crate::tls;
+
+/// Configure a `Client` before connecting
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    addr: String,
+    options: Options,
+    tls: TlsFiles,
+}
+
+/// Where the TLS settings are read from when connecting, TLS is used once
+/// `ca_cert_file` is set
+#[derive(Debug, Clone, Default)]
+struct TlsFiles {
+    ca_cert_file: Option<PathBuf>,
+    identity: Option<(PathBuf, PathBuf)>,
+    server_name: Option<String>,
+}
+
+/// How a `Client` replaces a failed connection
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Attempts after the first failed one before giving up
+    pub max_retries: u32,
+    /// Wait after the first failed attempt, doubled after each one
+    pub initial_backoff: Duration,
+    /// Upper bound of the wait between attempts
+    pub max_backoff: Duration,
+}
+
+/// Settings a client keeps to reopen its connection
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Options {
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) request_timeout: Option<Duration>,
+    pub(crate) reconnect: Option<ReconnectPolicy>,
+    pub(crate) credentials: Option<Credentials>,
+    pub(crate) tls: Option<Tls>,
+    /// RESP version switched to with `HELLO`, `None` stays on RESP2
+    pub(crate) protocol: Option<u8>,
+}
+
+/// TLS settings, loaded once and shared by every connection
+#[derive(Debug, Clone)]
+pub(crate) struct Tls {
+    pub(crate) config: Arc<ClientConfig>,
+    /// Name the server certificate must be valid for, `None` for the host
+    /// of the address connected to
+    pub(crate) server_name: Option<ServerName<'static>>,
+}
+
+/// What a client authenticates with on every new connection
+#[derive(Clone)]
+pub(crate) struct Credentials {
+    /// `None` authenticates as the `default` user
+    pub(crate) username: Option<String>,
+    pub(crate) password: Bytes,
+}
+
+impl ClientBuilder {
+    /// Start configuring a client for the server at `addr`, as `host:port`
+    pub fn new(addr: impl ToString) -> ClientBuilder {
+        ClientBuilder {
+            addr: addr.to_string(),
+            options: Options::default(),
+            tls: TlsFiles::default(),
+        }
+    }
+
+    /// Give up connecting after `timeout`, this applies to reconnects too
+    pub fn connect_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.options.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail a request whose reply takes longer than `timeout`
+    pub fn request_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.options.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Reconnect after the connection fails instead of failing every
+    /// following request. Requests that are safe to repeat are sent again on
+    /// the new connection.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> ClientBuilder {
+        self.options.reconnect = Some(policy);
+        self
+    }
+
+    /// Authenticate as the `default` user with `password` after connecting
+    pub fn password(mut self, password: impl Into<Bytes>) -> ClientBuilder {
+        self.options.credentials = Some(Credentials {
+            username: None,
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Authenticate as `username` with `password` after connecting
+    pub fn user(mut self, username: impl ToString, password: impl Into<Bytes>) -> ClientBuilder {
+        self.options.credentials = Some(Credentials {
+            username: Some(username.to_string()),
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Speak RESP version `protocol`, 2 or 3, on every connection
+    pub fn protocol(mut self, protocol: u8) -> ClientBuilder {
+        self.options.protocol = Some(protocol);
+        self
+    }
+
+    /// Connect over TLS, the server certificate is verified against the CA
+    /// certificates in `ca_cert_file`
+    pub fn tls(mut self, ca_cert_file: impl Into<PathBuf>) -> ClientBuilder {
+        self.tls.ca_cert_file = Some(ca_cert_file.into());
+        self
+    }
+
+    /// Present `cert_file` to a server that verifies its clients. Connecting
+    /// fails unless `tls` is set too.
+    pub fn client_cert(
+        mut self,
+        cert_file: impl Into<PathBuf>,
+        key_file: impl Into<PathBuf>,
+    ) -> ClientBuilder {
+        self.tls.identity = Some((cert_file.into(), key_file.into()));
+        self
+    }
+
+    /// Name the server certificate must be valid for, the host part of the
+    /// address by default. Connecting fails unless `tls` is set too.
+    pub fn server_name(mut self, name: impl ToString) -> ClientBuilder {
+        self.tls.server_name = Some(name.to_string());
+        self
+    }
+
+    pub async fn connect(self) -> crate::Result<Client> {
+        let (addr, options) = self.build()?;
+
+        Client::connect_with(addr, options).await
+    }
+
+    /// Connect a client that many tasks can share, see `MultiplexedClient`
+    pub async fn connect_multiplexed(self) -> crate::Result<MultiplexedClient> {
+        Ok(self.connect().await?.into_multiplexed())
+    }
+
+    /// Open a pool of connections to the server, see `Pool`. The settings
+    /// apply to every connection of the pool.
+    pub async fn connect_pool(self, config: PoolConfig) -> crate::Result<Pool> {
+        let (addr, options) = self.build()?;
+
+        Pool::connect_with(addr, options, config).await
+    }
+
+    /// Connect to the cluster the server belongs to, see `ClusterClient`.
+    /// The settings apply to the connection to every node.
+    pub async fn connect_cluster(self) -> crate::Result<ClusterClient> {
+        let (addr, options) = self.build()?;
+
+        ClusterClient::connect_with(vec![addr], options).await
+    }
+
+    /// Load the TLS settings, the address and options are then ready to
+    /// connect with
+    fn build(mut self) -> crate::Result<(String, Options)> {
+        if let Some(protocol) = self.options.protocol
+            && !matches!(protocol, 2 | 3)
+        {
+            return Err(format!("unsupported protocol version {}", protocol).into());
+        }
+
+        let files = &self.tls;
+
+        // Connecting in plain text would silently drop these
+        let Some(ca_cert_file) = &files.ca_cert_file else {
+            if files.identity.is_some() || files.server_name.is_some() {
+                return Err("a client certificate or server name needs TLS, set with `tls`".into());
+            }
+            return Ok((self.addr, self.options));
+        };
+
+        let identity = files
+            .identity
+            .as_ref()
+            .map(|(cert, key)| (cert.as_path(), key.as_path()));
+
+        let server_name = match &files.server_name {
+            Some(name) => Some(ServerName::try_from(name.clone())?),
+            None => None,
+        };
+
+        self.options.tls = Some(Tls {
+            config: tls::client_config(ca_cert_file, identity)?,
+            server_name,
+        });
+
+        Ok((self.addr, self.options))
+    }
+}
+
+// Keep the password out of logs
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Wait before retry number `attempt`, starting at 0. The exponential
+    /// delay is jittered down by up to half, so clients cut off together
+    /// don't all come back at the same moment.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+
+        // Every `RandomState` is seeded differently, which is random enough
+        // for spreading reconnects
+        let random = RandomState::new().build_hasher().finish();
+        let jitter = (random % 1000) as f64 / 1000.0;
+
+        delay.mul_f64(1.0 - jitter / 2.0)
+    }
+}
@@ -0,0 +1,323 @@
+use bytes::Bytes;
+use log::debug;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::client::builder::Options;
+use crate::client::{Client, Cmd, into_result, ping_reply, set_reply, single_arg};
+use crate::cluster::{SLOTS, key_slot};
+use crate::cmd::{Del, Get, MGet, Ping, Set};
+use crate::frame::Frame;
+use crate::types::{FromFrame, ToArgs};
+
+/// Redirections followed for one request before giving up
+const MAX_REDIRECTS: usize = 16;
+
+/// Client for a cluster of nodes, each serving part of the hash slots.
+///
+/// The slot map is read with `CLUSTER SLOTS` and every command goes to the
+/// node serving the slot of its key, over a connection opened the first
+/// time that node is needed. A `MOVED` reply means the map is stale, it is
+/// read again and the command sent to the new node. An `ASK` reply sends
+/// the command once to the node importing the slot, after `ASKING`.
+#[derive(Debug)]
+pub struct ClusterClient {
+    /// Nodes to read the slot map from when none of the connected ones
+    /// answers
+    seeds: Vec<String>,
+
+    /// Settings of every node connection
+    options: Options,
+
+    /// Address of the node serving each slot
+    slots: Vec<Option<String>>,
+
+    /// Open connections, by node address
+    nodes: HashMap<String, Client>,
+}
+
+/// Redirection replied by a node that doesn't serve the slot
+enum Redirect {
+    Moved(u16, String),
+    Ask(String),
+}
+
+impl ClusterClient {
+    /// Connect to the cluster the `seeds` nodes belong to, the slot map is
+    /// read from the first one that answers
+    pub async fn connect<T: ToString>(seeds: &[T]) -> crate::Result<ClusterClient> {
+        let seeds = seeds.iter().map(ToString::to_string).collect();
+
+        ClusterClient::connect_with(seeds, Options::default()).await
+    }
+
+    /// Connect with the options set on a `ClientBuilder`, they apply to
+    /// every node
+    pub(crate) async fn connect_with(
+        seeds: Vec<String>,
+        options: Options,
+    ) -> crate::Result<ClusterClient> {
+        let mut client = ClusterClient {
+            seeds,
+            options,
+            slots: vec![None; SLOTS as usize],
+            nodes: HashMap::new(),
+        };
+        client.refresh().await?;
+
+        Ok(client)
+    }
+
+    /// Send `frame` to the node serving `slot`, any node when there is no
+    /// key, following redirections. An error reply is returned as an error.
+    async fn request(
+        &mut self,
+        slot: Option<u16>,
+        frame: &Frame,
+        idempotent: bool,
+    ) -> crate::Result<Frame> {
+        let mut ask = None;
+
+        for _ in 0..MAX_REDIRECTS {
+            let (addr, asking) = match ask.take() {
+                Some(addr) => (addr, true),
+                None => (self.node_for(slot)?, false),
+            };
+
+            let reply = self.send(&addr, frame, asking, idempotent).await?;
+
+            match redirect(&reply) {
+                Some(Redirect::Moved(slot, addr)) => {
+                    debug!("slot {} moved to {}", slot, addr);
+
+                    // The command goes to the new node even if the map
+                    // can't be read again right now
+                    self.slots[slot as usize] = Some(addr);
+                    if let Err(err) = self.refresh().await {
+                        debug!("cannot refresh the slot map: {}", err);
+                    }
+                }
+                Some(Redirect::Ask(addr)) => ask = Some(addr),
+                None => return into_result(reply),
+            }
+        }
+
+        Err("too many cluster redirections".into())
+    }
+
+    /// Send `frame` to the node at `addr`, preceded by `ASKING` if `asking`.
+    /// A connection that fails is dropped, the node may be gone.
+    async fn send(
+        &mut self,
+        addr: &str,
+        frame: &Frame,
+        asking: bool,
+        idempotent: bool,
+    ) -> crate::Result<Frame> {
+        let client = self.connection(addr).await?;
+
+        let result = if asking {
+            let asking = Cmd::new("ASKING").into_frame();
+            client.send(&[&asking, frame], idempotent).await
+        } else {
+            client.send(&[frame], idempotent).await
+        };
+
+        match result {
+            Ok(mut replies) => Ok(replies.pop().unwrap()),
+            Err(err) => {
+                self.nodes.remove(addr);
+                Err(err)
+            }
+        }
+    }
+
+    /// Address of the node serving `slot`, or of any node
+    fn node_for(&self, slot: Option<u16>) -> crate::Result<String> {
+        let addr = match slot {
+            Some(slot) => self.slots[slot as usize].as_ref(),
+            None => self.slots.iter().flatten().next(),
+        };
+
+        match addr {
+            Some(addr) => Ok(addr.clone()),
+            None => match slot {
+                Some(slot) => Err(format!("hash slot {} is not served", slot).into()),
+                None => Err("no cluster node serves any slot".into()),
+            },
+        }
+    }
+
+    /// The connection to `addr`, opened if needed
+    async fn connection(&mut self, addr: &str) -> crate::Result<&mut Client> {
+        if !self.nodes.contains_key(addr) {
+            let client = Client::connect_with(addr.to_string(), self.options.clone()).await?;
+            self.nodes.insert(addr.to_string(), client);
+        }
+
+        Ok(self.nodes.get_mut(addr).unwrap())
+    }
+
+    /// Read the slot map again, from the first node that answers, connected
+    /// ones first. Connections to nodes left without slots are closed.
+    pub async fn refresh(&mut self) -> crate::Result<()> {
+        let mut addrs: Vec<String> = self.nodes.keys().cloned().collect();
+        for seed in &self.seeds {
+            if !addrs.contains(seed) {
+                addrs.push(seed.clone());
+            }
+        }
+
+        let mut last_err = None;
+
+        for addr in addrs {
+            match self.read_slots(&addr).await {
+                Ok(slots) => {
+                    let serving: HashSet<&String> = slots.iter().flatten().collect();
+                    self.nodes.retain(|addr, _| serving.contains(addr));
+
+                    self.slots = slots;
+
+                    return Ok(());
+                }
+                Err(err) => {
+                    debug!("cannot read the slot map from {}: {}", addr, err);
+
+                    self.nodes.remove(&addr);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| "no cluster node to connect to".into()))
+    }
+
+    async fn read_slots(&mut self, addr: &str) -> crate::Result<Vec<Option<String>>> {
+        let client = self.connection(addr).await?;
+        let ranges: Vec<Vec<Frame>> = client.query(Cmd::new("CLUSTER").arg("SLOTS")).await?;
+
+        let mut slots = vec![None; SLOTS as usize];
+
+        for range in ranges {
+            // The first, last slot and the node serving them, then replicas
+            let mut fields = range.into_iter();
+            let (Some(start), Some(end), Some(node)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err("invalid CLUSTER SLOTS reply".into());
+            };
+
+            let start = u16::from_frame(start)?;
+            let end = u16::from_frame(end)?;
+            if start > end || end >= SLOTS {
+                return Err(format!("invalid slot range {}-{}", start, end).into());
+            }
+
+            let mut node = Vec::<Frame>::from_frame(node)?.into_iter();
+            let (Some(host), Some(port)) = (node.next(), node.next()) else {
+                return Err("invalid CLUSTER SLOTS reply".into());
+            };
+
+            let host = String::from_frame(host)?;
+            let port = u16::from_frame(port)?;
+            let addr = if host.contains(':') {
+                format!("[{}]:{}", host, port)
+            } else {
+                format!("{}:{}", host, port)
+            };
+
+            for slot in start..=end {
+                slots[slot as usize] = Some(addr.clone());
+            }
+        }
+
+        Ok(slots)
+    }
+
+    /// Send `cmd` and convert its reply, use `Frame` for the raw reply. It is
+    /// routed by its first argument, which is the key for most commands.
+    pub async fn query<T: FromFrame>(&mut self, cmd: Cmd) -> crate::Result<T> {
+        let slot = cmd.first_arg().map(key_slot);
+
+        T::from_frame(self.request(slot, &cmd.into_frame(), false).await?)
+    }
+
+    pub async fn ping(&mut self, msg: Option<Bytes>) -> crate::Result<Bytes> {
+        let frame = Ping::new(msg).into_frame();
+
+        ping_reply(self.request(None, &frame, true).await?)
+    }
+
+    pub async fn set<V: ToArgs>(&mut self, key: &str, value: V) -> crate::Result<()> {
+        let frame = Set::new(key, single_arg(value)?).into_frame();
+
+        set_reply(self.request(Some(key_slot(key)), &frame, true).await?)
+    }
+
+    pub async fn get<T: FromFrame>(&mut self, key: &str) -> crate::Result<T> {
+        let frame = Get::new(key).into_frame();
+
+        T::from_frame(self.request(Some(key_slot(key)), &frame, true).await?)
+    }
+
+    /// Get the values of `keys`, which may be served by different nodes.
+    /// One `MGET` is sent per slot and the values are put back in order.
+    pub async fn mget<T: FromFrame>(&mut self, keys: &[&str]) -> crate::Result<T> {
+        let mut values = vec![Frame::Null; keys.len()];
+
+        for (slot, group) in by_slot(keys) {
+            let keys: Vec<&str> = group.iter().map(|&(_, key)| key).collect();
+            let frame = MGet::new(&keys).into_frame();
+
+            let reply = self.request(Some(slot), &frame, true).await?;
+            for ((i, _), value) in group.into_iter().zip(Vec::<Frame>::from_frame(reply)?) {
+                values[i] = value;
+            }
+        }
+
+        T::from_frame(Frame::Array(values))
+    }
+
+    /// Remove `keys`, returns how many existed. One `DEL` is sent per slot,
+    /// so after an error the keys of some slots may be removed already.
+    pub async fn del(&mut self, keys: &[&str]) -> crate::Result<u64> {
+        let mut removed = 0;
+
+        for (slot, group) in by_slot(keys) {
+            let keys: Vec<&str> = group.iter().map(|&(_, key)| key).collect();
+            let frame = Del::new(&keys).into_frame();
+
+            removed += u64::from_frame(self.request(Some(slot), &frame, false).await?)?;
+        }
+
+        Ok(removed)
+    }
+}
+
+/// `keys` grouped by slot, each with its position in `keys`
+fn by_slot<'a>(keys: &[&'a str]) -> BTreeMap<u16, Vec<(usize, &'a str)>> {
+    let mut groups: BTreeMap<u16, Vec<_>> = BTreeMap::new();
+
+    for (i, &key) in keys.iter().enumerate() {
+        groups.entry(key_slot(key)).or_default().push((i, key));
+    }
+
+    groups
+}
+
+/// A `MOVED slot host:port` or `ASK slot host:port` error reply
+fn redirect(frame: &Frame) -> Option<Redirect> {
+    let Frame::Error(msg) = frame else {
+        return None;
+    };
+
+    let mut parts = msg.split(' ');
+    let kind = parts.next()?;
+    let slot = parts.next()?.parse().ok().filter(|&slot| slot < SLOTS)?;
+    let addr = parts.next()?.to_string();
+
+    match kind {
+        "MOVED" => Some(Redirect::Moved(slot, addr)),
+        "ASK" => Some(Redirect::Ask(addr)),
+        _ => None,
+    }
+}
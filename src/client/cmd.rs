@@ -0,0 +1,84 @@
+use bytes::Bytes;
+
+use crate::client::Client;
+use crate::frame::Frame;
+use crate::types::{FromFrame, ToArgs};
+
+/// Any command, built from its name and arguments. It is sent as an array
+/// of bulk strings, so commands without a dedicated client method are
+/// reachable too.
+#[derive(Debug, Clone)]
+pub struct Cmd {
+    args: Vec<Bytes>,
+}
+
+/// A `Cmd` being built for a client, returned by `Client::cmd`
+#[derive(Debug)]
+pub struct CmdBuilder<'a> {
+    client: &'a mut Client,
+    cmd: Cmd,
+}
+
+impl Cmd {
+    pub fn new(name: &str) -> Cmd {
+        Cmd {
+            args: vec![Bytes::copy_from_slice(name.as_bytes())],
+        }
+    }
+
+    /// Append the arguments `arg` converts to
+    pub fn arg<A: ToArgs>(mut self, arg: A) -> Cmd {
+        arg.write_args(&mut self.args);
+        self
+    }
+
+    /// The first argument, the key for most commands
+    pub(crate) fn first_arg(&self) -> Option<&Bytes> {
+        self.args.get(1)
+    }
+
+    pub fn into_frame(self) -> Frame {
+        Frame::Array(self.args.into_iter().map(Frame::Bulk).collect())
+    }
+}
+
+impl<'a> CmdBuilder<'a> {
+    pub(crate) fn new(client: &'a mut Client, name: &str) -> CmdBuilder<'a> {
+        CmdBuilder {
+            client,
+            cmd: Cmd::new(name),
+        }
+    }
+
+    /// Append the arguments `arg` converts to
+    pub fn arg<A: ToArgs>(mut self, arg: A) -> CmdBuilder<'a> {
+        self.cmd = self.cmd.arg(arg);
+        self
+    }
+
+    /// Send the command and convert its reply, use `Frame` for the raw reply
+    pub async fn query<T: FromFrame>(self) -> crate::Result<T> {
+        self.client.query(self.cmd).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_are_flattened_into_bulk_strings() {
+        let frame = Cmd::new("SET")
+            .arg(("k", 1.5))
+            .arg(None::<&str>)
+            .arg(&["a", "b"][..])
+            .arg(Some(-7))
+            .into_frame();
+
+        let args: Vec<String> = match frame {
+            Frame::Array(args) => args.iter().map(ToString::to_string).collect(),
+            frame => panic!("unexpected frame {:?}", frame),
+        };
+        assert_eq!(args, ["SET", "k", "1.5", "a", "b", "-7"]);
+    }
+}
@@ -0,0 +1,429 @@
+use bytes::Bytes;
+use log::debug;
+use rustls::pki_types::ServerName;
+use std::io::{Error, ErrorKind};
+use std::str;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::time;
+use tokio_rustls::TlsConnector;
+
+use crate::cmd::{Auth, Del, Get, Hello, MGet, Ping, Select, Set};
+use crate::connection::Connection;
+use crate::frame::Frame;
+use crate::types::{FromFrame, ToArgs};
+
+mod builder;
+use builder::Options;
+pub use builder::{ClientBuilder, ReconnectPolicy};
+
+mod cmd;
+pub use cmd::{Cmd, CmdBuilder};
+
+mod pipeline;
+pub use pipeline::Pipeline;
+
+mod multiplexed;
+pub use multiplexed::MultiplexedClient;
+
+mod pool;
+pub use pool::{Pool, PoolConfig, PooledClient};
+
+mod cluster;
+pub use cluster::ClusterClient;
+
+#[derive(Debug)]
+pub struct Client {
+    connection: Connection,
+
+    // where to reconnect to
+    addr: String,
+
+    options: Options,
+
+    // the database selected last, selected again on a new connection
+    db: u64,
+
+    // a request failed or was cancelled, the connection may be out of sync
+    // with the server and must be replaced before it is used again
+    broken: bool,
+}
+
+impl Client {
+    pub async fn connect<T: ToSocketAddrs>(addr: T) -> crate::Result<Client> {
+        let socket = TcpStream::connect(addr).await?;
+
+        let addr = socket.peer_addr()?.to_string();
+
+        let connection = Connection::boxed(socket);
+
+        Ok(Client {
+            connection,
+            addr,
+            options: Options::default(),
+            db: 0,
+            broken: false,
+        })
+    }
+
+    /// Connect with the options set on a `ClientBuilder`
+    pub(crate) async fn connect_with(addr: String, options: Options) -> crate::Result<Client> {
+        let connection = open(&addr, &options).await?;
+        let protocol = options.protocol.unwrap_or(2);
+
+        let mut client = Client {
+            connection,
+            addr,
+            options,
+            db: 0,
+            broken: false,
+        };
+        client.handshake(protocol).await?;
+
+        Ok(client)
+    }
+
+    /// The database selected, `0` unless changed with `SELECT`
+    pub fn db(&self) -> u64 {
+        self.db
+    }
+
+    /// Take the connection out of this client, to exchange frames directly
+    pub fn into_connection(self) -> Connection {
+        self.connection
+    }
+
+    /// Turn this client into a cloneable handle that many tasks can use at
+    /// once, see `MultiplexedClient`
+    pub fn into_multiplexed(self) -> MultiplexedClient {
+        MultiplexedClient::new(self.connection)
+    }
+
+    /// Send `frame` and return its reply, an error reply is returned as an
+    /// error. If the connection fails and a reconnect policy is set, the
+    /// client reconnects and, for an `idempotent` request only, sends it
+    /// once more.
+    async fn request(&mut self, frame: &Frame, idempotent: bool) -> crate::Result<Frame> {
+        let mut replies = self.send(&[frame], idempotent).await?;
+
+        into_result(replies.pop().unwrap())
+    }
+
+    /// Like `request` for several frames sent at once, error replies are
+    /// returned as frames
+    async fn send(&mut self, frames: &[&Frame], idempotent: bool) -> crate::Result<Vec<Frame>> {
+        let mut retried = false;
+
+        loop {
+            match self.send_batch(frames).await {
+                Ok(replies) => return Ok(replies),
+                Err(err) => {
+                    if !idempotent || retried || self.options.reconnect.is_none() {
+                        return Err(err);
+                    }
+
+                    debug!("request failed, retrying on a new connection: {}", err);
+                    retried = true;
+                }
+            }
+        }
+    }
+
+    /// Write `frames` at once and read one reply per frame, error replies
+    /// are returned as frames. The connection counts as broken until every
+    /// reply is read, so a failed or cancelled request leaves it broken
+    /// since replies may still be on their way.
+    async fn send_batch(&mut self, frames: &[&Frame]) -> crate::Result<Vec<Frame>> {
+        if self.broken {
+            self.reconnect().await?;
+        }
+
+        self.broken = true;
+
+        let replies = match self.options.request_timeout {
+            Some(timeout) => time::timeout(timeout, self.write_and_read(frames))
+                .await
+                .unwrap_or_else(|_| Err("request timed out".into())),
+            None => self.write_and_read(frames).await,
+        }?;
+
+        self.broken = false;
+
+        Ok(replies)
+    }
+
+    /// Whether the last request failed or was cancelled, leaving the
+    /// connection out of sync with the server
+    pub(crate) fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    async fn write_and_read(&mut self, frames: &[&Frame]) -> crate::Result<Vec<Frame>> {
+        for frame in frames {
+            self.connection.feed_frame(frame).await?;
+        }
+
+        self.connection.flush().await?;
+
+        let mut replies = Vec::with_capacity(frames.len());
+        for _ in 0..frames.len() {
+            replies.push(self.read_reply().await?);
+        }
+
+        // However the database was selected, a new connection selects it
+        for (frame, reply) in frames.iter().zip(&replies) {
+            if let Some(db) = selected_db(frame)
+                && matches!(reply, Frame::Simple(_))
+            {
+                self.db = db;
+            }
+        }
+
+        Ok(replies)
+    }
+
+    /// Read the next reply, an error reply is returned as a frame
+    async fn read_reply(&mut self) -> crate::Result<Frame> {
+        match self.connection.read_frame().await? {
+            Some(frame) => Ok(frame),
+            None => {
+                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by server");
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Replace a broken connection following the reconnect policy, waiting
+    /// longer after each failed attempt
+    async fn reconnect(&mut self) -> crate::Result<()> {
+        let policy = match &self.options.reconnect {
+            Some(policy) => policy.clone(),
+            None => return Err("connection is broken".into()),
+        };
+
+        let mut attempt = 0;
+
+        loop {
+            match open(&self.addr, &self.options).await {
+                Ok(connection) => {
+                    let protocol = self.connection.protocol();
+
+                    self.connection = connection;
+                    self.handshake(protocol).await?;
+                    self.broken = false;
+
+                    return Ok(());
+                }
+                Err(err) if attempt >= policy.max_retries => return Err(err),
+                Err(err) => {
+                    let backoff = policy.backoff(attempt);
+
+                    debug!("reconnect failed: {}, retrying in {:?}", err, backoff);
+
+                    time::sleep(backoff).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Authenticate a new connection and bring it to the state of the one
+    /// it replaces, in a single round trip
+    async fn handshake(&mut self, protocol: u8) -> crate::Result<()> {
+        let credentials = self.options.credentials.clone();
+
+        let mut frames = vec![];
+        match (protocol, credentials) {
+            (2, None) => {}
+            (2, Some(credentials)) => {
+                frames.push(Auth::new(credentials.username, credentials.password).into_frame());
+            }
+            (protocol, credentials) => {
+                let mut hello = Hello::new(Some(protocol as u64));
+                if let Some(credentials) = credentials {
+                    let username = credentials.username.unwrap_or_else(|| "default".into());
+                    hello = hello.auth(username, credentials.password);
+                }
+                frames.push(hello.into_frame());
+            }
+        }
+
+        if self.db != 0 {
+            frames.push(Select::new(self.db).into_frame());
+        }
+
+        if frames.is_empty() {
+            return Ok(());
+        }
+
+        let frames: Vec<&Frame> = frames.iter().collect();
+        for reply in self.write_and_read(&frames).await? {
+            into_result(reply)?;
+        }
+
+        self.connection.set_protocol(protocol);
+
+        Ok(())
+    }
+
+    /// Start building an arbitrary command, for example
+    /// `client.cmd("SELECT").arg(1).query::<()>()`
+    pub fn cmd(&mut self, name: &str) -> CmdBuilder<'_> {
+        CmdBuilder::new(self, name)
+    }
+
+    /// Send `cmd` and convert its reply, use `Frame` for the raw reply. The
+    /// command is not known to be safe to repeat, so it is not sent again
+    /// after a reconnect.
+    pub async fn query<T: FromFrame>(&mut self, cmd: Cmd) -> crate::Result<T> {
+        T::from_frame(self.request(&cmd.into_frame(), false).await?)
+    }
+
+    pub async fn ping(&mut self, msg: Option<Bytes>) -> crate::Result<Bytes> {
+        let frame = Ping::new(msg).into_frame();
+
+        ping_reply(self.request(&frame, true).await?)
+    }
+
+    /// Handshake with the server, switching to RESP version `protocol` if
+    /// given. Returns the server properties.
+    pub async fn hello(&mut self, protocol: Option<u64>) -> crate::Result<Frame> {
+        let frame = Hello::new(protocol).into_frame();
+
+        let response = self.request(&frame, true).await?;
+
+        if let Some(protocol) = protocol {
+            self.connection.set_protocol(protocol as u8);
+        }
+
+        Ok(response)
+    }
+
+    /// Select the logical database `db`, it stays selected across
+    /// reconnects
+    pub async fn select(&mut self, db: u64) -> crate::Result<()> {
+        let frame = Select::new(db).into_frame();
+
+        set_reply(self.request(&frame, true).await?)
+    }
+
+    pub async fn set<V: ToArgs>(&mut self, key: &str, value: V) -> crate::Result<()> {
+        let frame = Set::new(key, single_arg(value)?).into_frame();
+
+        set_reply(self.request(&frame, true).await?)
+    }
+
+    /// Get the value of `key` converted to `T`, use an `Option` to tell a
+    /// missing key apart
+    pub async fn get<T: FromFrame>(&mut self, key: &str) -> crate::Result<T> {
+        let frame = Get::new(key).into_frame();
+
+        T::from_frame(self.request(&frame, true).await?)
+    }
+
+    /// Get the values of `keys`, as a `Vec` of `Option`s to tell missing
+    /// keys apart
+    pub async fn mget<T: FromFrame>(&mut self, keys: &[&str]) -> crate::Result<T> {
+        let frame = MGet::new(keys).into_frame();
+
+        T::from_frame(self.request(&frame, true).await?)
+    }
+
+    /// Remove `keys`, returns how many existed
+    pub async fn del(&mut self, keys: &[&str]) -> crate::Result<u64> {
+        let frame = Del::new(keys).into_frame();
+
+        u64::from_frame(self.request(&frame, false).await?)
+    }
+}
+
+/// Open a connection to `addr`, giving up after the connect timeout. The
+/// timeout covers the TLS handshake too.
+async fn open(addr: &str, options: &Options) -> crate::Result<Connection> {
+    let connect = async {
+        let socket = TcpStream::connect(addr).await?;
+
+        let connection = match &options.tls {
+            Some(tls) => {
+                let server_name = match &tls.server_name {
+                    Some(name) => name.clone(),
+                    None => ServerName::try_from(host(addr).to_string())?,
+                };
+                let stream = TlsConnector::from(tls.config.clone())
+                    .connect(server_name, socket)
+                    .await?;
+                Connection::boxed(stream)
+            }
+            None => Connection::boxed(socket),
+        };
+
+        Ok::<_, crate::Error>(connection)
+    };
+
+    match options.connect_timeout {
+        Some(timeout) => time::timeout(timeout, connect)
+            .await
+            .map_err(|_| "connect timed out")?,
+        None => connect.await,
+    }
+}
+
+/// The host of `host:port`, without the brackets of an IPv6 address
+fn host(addr: &str) -> &str {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/* Replies are interpreted by these so every client flavor agrees */
+
+fn ping_reply(frame: Frame) -> crate::Result<Bytes> {
+    match frame {
+        Frame::Simple(s) => Ok(s.into()),
+        Frame::Bulk(value) => Ok(value),
+        frame => Err(frame.to_error()),
+    }
+}
+
+fn set_reply(frame: Frame) -> crate::Result<()> {
+    match frame {
+        Frame::Simple(response) => {
+            if response == "OK" {
+                Ok(())
+            } else {
+                Err(format!("error response {}", response).into())
+            }
+        }
+        frame => Err(frame.to_error()),
+    }
+}
+
+/// The argument `value` converts to, for commands taking a single value
+fn single_arg<V: ToArgs>(value: V) -> crate::Result<Bytes> {
+    let mut args = value.to_args();
+
+    if args.len() != 1 {
+        return Err(format!("expected a single value, got {} arguments", args.len()).into());
+    }
+
+    Ok(args.pop().unwrap())
+}
+
+/// The database `frame` selects if it is a `SELECT`
+fn selected_db(frame: &Frame) -> Option<u64> {
+    match frame {
+        Frame::Array(args) => match &args[..] {
+            [Frame::Bulk(name), Frame::Bulk(index)] if name.eq_ignore_ascii_case(b"select") => {
+                str::from_utf8(index).ok()?.parse().ok()
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Turn an error reply into an error
+fn into_result(frame: Frame) -> crate::Result<Frame> {
+    match frame {
+        Frame::Error(msg) => Err(msg.into()),
+        frame => Ok(frame),
+    }
+}
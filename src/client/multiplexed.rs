@@ -0,0 +1,137 @@
+use bytes::Bytes;
+use log::debug;
+use std::collections::VecDeque;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::client::{Cmd, into_result, ping_reply, set_reply, single_arg};
+use crate::cmd::{Get, Ping, Set};
+use crate::connection::Connection;
+use crate::frame::Frame;
+use crate::types::{FromFrame, ToArgs};
+
+/// Most requests waiting to be written before callers have to wait
+const REQUEST_BUFFER: usize = 1024;
+
+/// Cloneable client sharing one connection between many tasks.
+///
+/// A background task owns the connection. Requests reach it over a channel
+/// and replies are matched back in the order the requests were written, as
+/// the server answers in order. Requests sent at the same time by different
+/// tasks are written together, which pipelines them.
+#[derive(Debug, Clone)]
+pub struct MultiplexedClient {
+    requests: mpsc::Sender<Request>,
+}
+
+#[derive(Debug)]
+struct Request {
+    frame: Frame,
+    reply: oneshot::Sender<crate::Result<Frame>>,
+}
+
+impl MultiplexedClient {
+    /// Connect to `addr`, use `ClientBuilder::connect_multiplexed` to
+    /// authenticate or use TLS
+    pub async fn connect<T: ToSocketAddrs>(addr: T) -> crate::Result<MultiplexedClient> {
+        let socket = TcpStream::connect(addr).await?;
+
+        Ok(MultiplexedClient::new(Connection::boxed(socket)))
+    }
+
+    /// Spawn the task driving `connection`, it ends once every handle is
+    /// dropped or the connection fails
+    pub(crate) fn new(connection: Connection) -> MultiplexedClient {
+        let (requests, rx) = mpsc::channel(REQUEST_BUFFER);
+
+        tokio::spawn(run(connection, rx));
+
+        MultiplexedClient { requests }
+    }
+
+    /// Send a request frame and wait for its reply, an error reply is
+    /// returned as an error
+    pub async fn send(&self, frame: Frame) -> crate::Result<Frame> {
+        let (reply, rx) = oneshot::channel();
+
+        self.requests
+            .send(Request { frame, reply })
+            .await
+            .map_err(|_| "connection closed")?;
+
+        rx.await.map_err(|_| "connection closed")?
+    }
+
+    /// Send `cmd` and convert its reply, use `Frame` for the raw reply
+    pub async fn query<T: FromFrame>(&self, cmd: Cmd) -> crate::Result<T> {
+        T::from_frame(self.send(cmd.into_frame()).await?)
+    }
+
+    pub async fn ping(&self, msg: Option<Bytes>) -> crate::Result<Bytes> {
+        ping_reply(self.send(Ping::new(msg).into_frame()).await?)
+    }
+
+    pub async fn set<V: ToArgs>(&self, key: &str, value: V) -> crate::Result<()> {
+        set_reply(
+            self.send(Set::new(key, single_arg(value)?).into_frame())
+                .await?,
+        )
+    }
+
+    pub async fn get<T: FromFrame>(&self, key: &str) -> crate::Result<T> {
+        T::from_frame(self.send(Get::new(key).into_frame()).await?)
+    }
+}
+
+/// Write requests as they come and hand replies back in FIFO order
+async fn run(mut connection: Connection, mut requests: mpsc::Receiver<Request>) {
+    // reply senders of the requests written so far, oldest first
+    let mut pending = VecDeque::new();
+
+    loop {
+        tokio::select! {
+            Some(request) = requests.recv() => {
+                let mut batch = vec![request];
+
+                // Take every request already waiting, they go out in one write
+                while let Ok(request) = requests.try_recv() {
+                    batch.push(request);
+                }
+
+                debug!("writing {} multiplexed requests", batch.len());
+
+                for request in batch {
+                    if let Err(err) = connection.feed_frame(&request.frame).await {
+                        let _ = request.reply.send(Err(err.to_string().into()));
+                        return fail(pending, "connection closed");
+                    }
+                    pending.push_back(request.reply);
+                }
+
+                if connection.flush().await.is_err() {
+                    return fail(pending, "connection closed");
+                }
+            }
+            frame = connection.read_frame(), if !pending.is_empty() => {
+                match frame {
+                    Ok(Some(frame)) => {
+                        if let Some(reply) = pending.pop_front() {
+                            // the caller may have given up waiting
+                            let _ = reply.send(into_result(frame));
+                        }
+                    }
+                    Ok(None) => return fail(pending, "connection reset by server"),
+                    Err(err) => return fail(pending, &err.to_string()),
+                }
+            }
+            // every handle is gone and nothing is left to read
+            else => return,
+        }
+    }
+}
+
+fn fail(pending: VecDeque<oneshot::Sender<crate::Result<Frame>>>, msg: &str) {
+    for reply in pending {
+        let _ = reply.send(Err(msg.into()));
+    }
+}
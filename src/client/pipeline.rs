@@ -0,0 +1,150 @@
+use bytes::Bytes;
+
+use crate::client::{Client, Cmd, into_result, single_arg};
+use crate::cmd::{Exec, Get, Multi, Ping, Set};
+use crate::frame::Frame;
+use crate::types::{FromFrame, ToArgs};
+
+/// A batch of commands sent to the server in a single write, the replies
+/// are read back in the order the commands were queued
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    commands: Vec<Frame>,
+
+    // wrap the batch in `MULTI`/`EXEC`
+    atomic: bool,
+
+    // a command could not be built, reported by `execute`
+    error: Option<crate::Error>,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Run the batch as a transaction, no command of another client runs in
+    /// between its commands
+    pub fn atomic(&mut self) -> &mut Pipeline {
+        self.atomic = true;
+        self
+    }
+
+    pub fn ping(&mut self, msg: Option<Bytes>) -> &mut Pipeline {
+        self.commands.push(Ping::new(msg).into_frame());
+        self
+    }
+
+    pub fn get(&mut self, key: &str) -> &mut Pipeline {
+        self.commands.push(Get::new(key).into_frame());
+        self
+    }
+
+    /// Queue a `SET`, `value` must convert to a single argument otherwise
+    /// the pipeline fails when executed
+    pub fn set<V: ToArgs>(&mut self, key: &str, value: V) -> &mut Pipeline {
+        let frame = match single_arg(value) {
+            Ok(value) => Set::new(key, value).into_frame(),
+            Err(err) => {
+                self.error.get_or_insert(err);
+                return self;
+            }
+        };
+
+        self.commands.push(frame);
+        self
+    }
+
+    /// Queue an arbitrary command, its arguments are added with `arg`
+    pub fn cmd(&mut self, name: &str) -> &mut Pipeline {
+        self.commands.push(Cmd::new(name).into_frame());
+        self
+    }
+
+    /// Append the arguments `arg` converts to, to the last queued command
+    pub fn arg<A: ToArgs>(&mut self, arg: A) -> &mut Pipeline {
+        match self.commands.last_mut() {
+            Some(Frame::Array(frame)) => {
+                frame.extend(arg.to_args().into_iter().map(Frame::Bulk));
+            }
+            _ => {
+                self.error
+                    .get_or_insert_with(|| "no command to add arguments to".into());
+            }
+        }
+        self
+    }
+
+    /// Queue a command built on its own
+    pub fn add(&mut self, cmd: Cmd) -> &mut Pipeline {
+        self.commands.push(cmd.into_frame());
+        self
+    }
+
+    /// Number of queued commands
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Send every queued command and convert their replies, taken as an
+    /// array, to `T`. Tuples give each reply its own type. Unlike
+    /// `execute`, an error reply fails the whole batch.
+    pub async fn query<T: FromFrame>(&self, client: &mut Client) -> crate::Result<T> {
+        let replies = self
+            .execute(client)
+            .await?
+            .into_iter()
+            .collect::<crate::Result<Vec<Frame>>>()?;
+
+        T::from_frame(Frame::Array(replies))
+    }
+
+    /// Send every queued command and read their replies. Each command gets
+    /// its own result, an error reply fails only that entry and the rest of
+    /// the batch still runs. The outer error is for a failed connection or
+    /// an aborted transaction.
+    pub async fn execute(&self, client: &mut Client) -> crate::Result<Vec<crate::Result<Frame>>> {
+        if let Some(err) = &self.error {
+            return Err(err.to_string().into());
+        }
+
+        if !self.atomic {
+            let frames: Vec<&Frame> = self.commands.iter().collect();
+
+            let replies = client.send_batch(&frames).await?;
+
+            return Ok(replies.into_iter().map(into_result).collect());
+        }
+
+        let multi = Multi::new().into_frame();
+        let exec = Exec::new().into_frame();
+
+        let frames: Vec<&Frame> = std::iter::once(&multi)
+            .chain(&self.commands)
+            .chain(std::iter::once(&exec))
+            .collect();
+
+        let mut replies = client.send_batch(&frames).await?;
+
+        let exec = replies.pop().unwrap();
+        let mut replies = replies.into_iter();
+
+        into_result(replies.next().unwrap())?;
+
+        // a command that could not be queued aborts the transaction
+        for queued in replies {
+            into_result(queued)?;
+        }
+
+        match exec {
+            Frame::Array(replies) => Ok(replies.into_iter().map(into_result).collect()),
+            Frame::Null => Err("transaction aborted".into()),
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Err(frame.to_error()),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::debug;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{self, Instant};
+
+use crate::client::{Client, Options};
+
+/// Settings of a `Pool`
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Connections opened up front and kept even when idle for long
+    pub min_size: usize,
+    /// Most connections open at once, checked out or idle
+    pub max_size: usize,
+    /// How long `Pool::get` waits for a connection before failing
+    pub checkout_timeout: Duration,
+    /// Idle connections beyond `min_size` are closed after this long
+    pub idle_timeout: Option<Duration>,
+    /// Send a `PING` before handing out an idle connection, replacing it if
+    /// the reply doesn't come back
+    pub health_check: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> PoolConfig {
+        PoolConfig {
+            min_size: 0,
+            max_size: 16,
+            checkout_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
+            health_check: true,
+        }
+    }
+}
+
+/// Pool of `Client` connections to one server. Cloning the pool is cheap,
+/// every clone hands out connections from the same pool.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    addr: String,
+    options: Options,
+    config: PoolConfig,
+
+    /// Idle connections, the most recently returned last
+    idle: Mutex<VecDeque<Idle>>,
+
+    /// One permit per connection that may be open, bounds the pool size
+    permits: Arc<Semaphore>,
+}
+
+#[derive(Debug)]
+struct Idle {
+    client: Client,
+    since: Instant,
+}
+
+/// A connection checked out of a `Pool`, it goes back to the pool when
+/// dropped
+#[derive(Debug)]
+pub struct PooledClient {
+    // always `Some` until dropped
+    client: Option<Client>,
+    shared: Arc<Shared>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Pool {
+    /// Create a pool of connections to `addr`, opening `min_size` of them.
+    /// Use `ClientBuilder::connect_pool` to authenticate or use TLS.
+    pub async fn new(addr: impl ToString, config: PoolConfig) -> crate::Result<Pool> {
+        Pool::connect_with(addr.to_string(), Options::default(), config).await
+    }
+
+    /// Create a pool whose connections use the options set on a
+    /// `ClientBuilder`
+    pub(crate) async fn connect_with(
+        addr: String,
+        options: Options,
+        config: PoolConfig,
+    ) -> crate::Result<Pool> {
+        let mut idle = VecDeque::with_capacity(config.max_size);
+        for _ in 0..config.min_size.min(config.max_size) {
+            idle.push_back(Idle {
+                client: Client::connect_with(addr.clone(), options.clone()).await?,
+                since: Instant::now(),
+            });
+        }
+
+        let shared = Arc::new(Shared {
+            addr,
+            options,
+            permits: Arc::new(Semaphore::new(config.max_size)),
+            config,
+            idle: Mutex::new(idle),
+        });
+
+        Ok(Pool { shared })
+    }
+
+    /// Check out a connection, reusing an idle one when possible. Waits up
+    /// to `checkout_timeout` when `max_size` connections are in use.
+    pub async fn get(&self) -> crate::Result<PooledClient> {
+        match time::timeout(self.shared.config.checkout_timeout, self.checkout()).await {
+            Ok(client) => client,
+            Err(_) => Err("timed out waiting for a pooled connection".into()),
+        }
+    }
+
+    /// Number of idle connections
+    pub fn idle(&self) -> usize {
+        self.shared.idle.lock().unwrap().len()
+    }
+
+    async fn checkout(&self) -> crate::Result<PooledClient> {
+        let permit = self.shared.permits.clone().acquire_owned().await?;
+
+        while let Some(mut client) = self.take_idle() {
+            if self.shared.config.health_check && !healthy(&mut client).await {
+                debug!("replacing broken pooled connection");
+                continue;
+            }
+
+            return Ok(self.guard(client, permit));
+        }
+
+        let shared = &self.shared;
+        let client = Client::connect_with(shared.addr.clone(), shared.options.clone()).await?;
+
+        Ok(self.guard(client, permit))
+    }
+
+    /// Pop the most recently used idle connection, closing the ones idle for
+    /// too long on the way
+    fn take_idle(&self) -> Option<Client> {
+        let mut idle = self.shared.idle.lock().unwrap();
+
+        if let Some(timeout) = self.shared.config.idle_timeout {
+            while idle.len() > self.shared.config.min_size
+                && idle.front().is_some_and(|c| c.since.elapsed() > timeout)
+            {
+                idle.pop_front();
+            }
+        }
+
+        idle.pop_back().map(|idle| idle.client)
+    }
+
+    fn guard(&self, client: Client, permit: OwnedSemaphorePermit) -> PooledClient {
+        PooledClient {
+            client: Some(client),
+            shared: self.shared.clone(),
+            _permit: permit,
+        }
+    }
+}
+
+/// A connection is usable if it answers `PING` with `PONG`, any other
+/// reply means it is out of sync with the server
+async fn healthy(client: &mut Client) -> bool {
+    matches!(client.ping(None).await, Ok(pong) if pong == "PONG")
+}
+
+impl PooledClient {
+    /// Close the connection instead of returning it to the pool, for when it
+    /// is known to be unusable
+    pub fn discard(mut self) {
+        self.client.take();
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        // A broken connection may still have replies on their way, it is
+        // closed rather than handed to the next user
+        if let Some(client) = self.client.take()
+            && !client.is_broken()
+        {
+            self.shared.idle.lock().unwrap().push_back(Idle {
+                client,
+                since: Instant::now(),
+            });
+        }
+    }
+}
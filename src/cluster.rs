@@ -0,0 +1,774 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use log::info;
+
+use crate::client::{Client, ClientBuilder, Cmd};
+use crate::cmd::Auth;
+use crate::replication::new_id;
+
+/// Number of hash slots the keyspace is split into
+pub(crate) const SLOTS: u16 = 16384;
+
+const NO_SLOT: &str = "ERR Invalid or out of range slot";
+
+/// Handle to the cluster state: which node serves each hash slot and the
+/// slots being moved between nodes. Like `Db` carries the selected database,
+/// every handle carries whether its connection sent `ASKING`.
+#[derive(Debug, Clone)]
+pub(crate) struct Cluster {
+    shared: Arc<Shared>,
+
+    /// The next command may run on a slot this node is importing
+    asking: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// Without cluster mode every key is served and `CLUSTER` is refused
+    enabled: bool,
+
+    state: Mutex<State>,
+
+    /// Where the state is saved on every change, in the `CLUSTER NODES`
+    /// format
+    file: Option<PathBuf>,
+
+    /// Credentials to authenticate to the other nodes with
+    auth: Option<Auth>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// ID of this node
+    myself: String,
+
+    /// Bumped whenever a node takes over slots
+    current_epoch: u64,
+
+    /// Every known node, this one included
+    nodes: HashMap<String, Node>,
+
+    /// ID of the node serving each slot
+    slots: Vec<Option<String>>,
+
+    /// Slots this node is moving out, to the node with this ID
+    migrating: HashMap<u16, String>,
+
+    /// Slots this node is moving in, from the node with this ID
+    importing: HashMap<u16, String>,
+}
+
+/// A node as the other nodes and clients reach it
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Node {
+    pub(crate) id: String,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+
+    /// Epoch the node last took over slots in
+    epoch: u64,
+}
+
+/// Change to the state of one slot, see `CLUSTER SETSLOT`
+#[derive(Debug)]
+pub(crate) enum SetSlot {
+    Importing(String),
+    Migrating(String),
+    Node(String),
+    Stable,
+}
+
+/// A line of `CLUSTER NODES`
+struct Entry {
+    node: Node,
+    myself: bool,
+    slots: Vec<u16>,
+    migrating: Vec<(u16, String)>,
+    importing: Vec<(u16, String)>,
+}
+
+impl Cluster {
+    /// This node accepts clients on `host:port`. The state is loaded from
+    /// `file` if it exists, otherwise the node starts alone with a new ID.
+    pub(crate) fn new(
+        enabled: bool,
+        host: String,
+        port: u16,
+        file: Option<PathBuf>,
+        auth: Option<Auth>,
+    ) -> crate::Result<Cluster> {
+        let myself = Node {
+            id: new_id(),
+            host,
+            port,
+            epoch: 0,
+        };
+
+        let mut state = State {
+            myself: myself.id.clone(),
+            current_epoch: 0,
+            nodes: HashMap::from([(myself.id.clone(), myself)]),
+            slots: vec![None; SLOTS as usize],
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+        };
+
+        if enabled
+            && let Some(file) = &file
+            && file.exists()
+        {
+            let contents = std::fs::read_to_string(file)
+                .map_err(|err| format!("cannot read {}: {}", file.display(), err))?;
+            state.load(&contents)?;
+
+            info!(
+                "cluster state loaded from {}, node ID {}",
+                file.display(),
+                state.myself
+            );
+        }
+
+        let cluster = Cluster {
+            shared: Arc::new(Shared {
+                enabled,
+                state: Mutex::new(state),
+                file,
+                auth,
+            }),
+            asking: false,
+        };
+
+        if enabled {
+            cluster.save()?;
+        }
+
+        Ok(cluster)
+    }
+
+    /// A handle for a new connection
+    pub(crate) fn session(&self) -> Cluster {
+        Cluster {
+            shared: self.shared.clone(),
+            asking: false,
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.shared.enabled
+    }
+
+    /// Let the next command of this connection run on a slot being imported
+    pub(crate) fn set_asking(&mut self) {
+        self.asking = true;
+    }
+
+    /// Check that a command accessing `keys` can run on this node. Returns
+    /// the error to reply with otherwise: a `MOVED` or `ASK` redirection to
+    /// the node serving the keys, or `CROSSSLOT`. `exists` tells whether a
+    /// key is still here, missing keys of a slot being migrated are looked
+    /// up on the target.
+    pub(crate) fn redirect(
+        &mut self,
+        keys: &[&str],
+        exists: impl Fn(&str) -> bool,
+    ) -> Option<String> {
+        let asking = std::mem::take(&mut self.asking);
+
+        if !self.shared.enabled || keys.is_empty() {
+            return None;
+        }
+
+        let slot = key_slot(keys[0]);
+        if keys[1..].iter().any(|key| key_slot(key) != slot) {
+            return Some("CROSSSLOT Keys in request don't hash to the same slot".to_string());
+        }
+
+        let state = self.shared.state.lock().unwrap();
+
+        match &state.slots[slot as usize] {
+            Some(owner) if *owner == state.myself => match state.migrating.get(&slot) {
+                Some(target) if keys.iter().any(|key| !exists(key)) => {
+                    Some(format!("ASK {} {}", slot, state.addr(target)))
+                }
+                _ => None,
+            },
+            _ if asking && state.importing.contains_key(&slot) => None,
+            Some(owner) => Some(format!("MOVED {} {}", slot, state.addr(owner))),
+            None => Some("CLUSTERDOWN Hash slot not served".to_string()),
+        }
+    }
+
+    pub(crate) fn myid(&self) -> String {
+        self.shared.state.lock().unwrap().myself.clone()
+    }
+
+    /// The text of `CLUSTER INFO`
+    pub(crate) fn info(&self) -> String {
+        let state = self.shared.state.lock().unwrap();
+
+        let assigned = state.slots.iter().filter(|owner| owner.is_some()).count();
+        let size = state.slots.iter().flatten().collect::<HashSet<_>>().len();
+
+        format!(
+            "cluster_enabled:1\r\n\
+             cluster_state:{}\r\n\
+             cluster_slots_assigned:{}\r\n\
+             cluster_slots_ok:{}\r\n\
+             cluster_slots_pfail:0\r\n\
+             cluster_slots_fail:0\r\n\
+             cluster_known_nodes:{}\r\n\
+             cluster_size:{}\r\n\
+             cluster_current_epoch:{}\r\n\
+             cluster_my_epoch:{}\r\n",
+            if assigned == SLOTS as usize {
+                "ok"
+            } else {
+                "fail"
+            },
+            assigned,
+            assigned,
+            state.nodes.len(),
+            size,
+            state.current_epoch,
+            state.nodes[&state.myself].epoch,
+        )
+    }
+
+    /// The text of `CLUSTER NODES`, one line per node
+    pub(crate) fn nodes(&self) -> String {
+        self.shared.state.lock().unwrap().describe()
+    }
+
+    /// Ranges of consecutive slots served by the same node, in slot order
+    pub(crate) fn slot_ranges(&self) -> Vec<(u16, u16, Node)> {
+        let state = self.shared.state.lock().unwrap();
+
+        state
+            .ranges()
+            .into_iter()
+            .map(|(start, end, id)| (start, end, state.nodes[id].clone()))
+            .collect()
+    }
+
+    /// Every node with the slot ranges it serves
+    pub(crate) fn shards(&self) -> Vec<(Node, Vec<(u16, u16)>)> {
+        let state = self.shared.state.lock().unwrap();
+
+        let mut shards: Vec<(Node, Vec<(u16, u16)>)> = state
+            .nodes
+            .values()
+            .map(|node| (node.clone(), vec![]))
+            .collect();
+        shards.sort_by(|(a, _), (b, _)| a.id.cmp(&b.id));
+
+        for (start, end, id) in state.ranges() {
+            if let Some((_, ranges)) = shards.iter_mut().find(|(node, _)| node.id == *id) {
+                ranges.push((start, end));
+            }
+        }
+
+        shards
+    }
+
+    /// Serve `slots` from this node
+    pub(crate) fn add_slots(&self, slots: &[u16]) -> crate::Result<()> {
+        self.update(|state| {
+            if let Some(slot) = slots
+                .iter()
+                .find(|&&slot| state.slots[slot as usize].is_some())
+            {
+                return Err(format!("ERR Slot {} is already busy", slot).into());
+            }
+
+            for &slot in slots {
+                state.slots[slot as usize] = Some(state.myself.clone());
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Forget which node serves `slots`
+    pub(crate) fn del_slots(&self, slots: &[u16]) -> crate::Result<()> {
+        self.update(|state| {
+            if let Some(slot) = slots
+                .iter()
+                .find(|&&slot| state.slots[slot as usize].is_none())
+            {
+                return Err(format!("ERR Slot {} is already unassigned", slot).into());
+            }
+
+            for &slot in slots {
+                state.slots[slot as usize] = None;
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Change the state of `slot`, to move it between nodes
+    pub(crate) fn set_slot(&self, slot: u16, action: SetSlot) -> crate::Result<()> {
+        self.update(|state| {
+            let owner = state.slots[slot as usize].clone();
+            let mine = owner.as_deref() == Some(state.myself.as_str());
+
+            match action {
+                SetSlot::Migrating(id) => {
+                    state.check_node(&id)?;
+                    if !mine {
+                        return Err(format!("ERR I'm not the owner of hash slot {}", slot).into());
+                    }
+                    state.migrating.insert(slot, id);
+                }
+                SetSlot::Importing(id) => {
+                    state.check_node(&id)?;
+                    if mine {
+                        return Err(
+                            format!("ERR I'm already the owner of hash slot {}", slot).into()
+                        );
+                    }
+                    state.importing.insert(slot, id);
+                }
+                SetSlot::Node(id) => {
+                    state.check_node(&id)?;
+
+                    state.migrating.remove(&slot);
+                    state.importing.remove(&slot);
+
+                    if owner.as_deref() != Some(id.as_str()) {
+                        state.current_epoch += 1;
+                        let epoch = state.current_epoch;
+                        state.nodes.get_mut(&id).unwrap().epoch = epoch;
+                    }
+                    state.slots[slot as usize] = Some(id);
+                }
+                SetSlot::Stable => {
+                    state.migrating.remove(&slot);
+                    state.importing.remove(&slot);
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Remove the node `id` from the table, with the slots it served
+    pub(crate) fn forget(&self, id: &str) -> crate::Result<()> {
+        self.update(|state| {
+            if id == state.myself {
+                return Err("ERR I tried hard but I can't forget myself...".into());
+            }
+            if state.nodes.remove(id).is_none() {
+                return Err(format!("ERR Unknown node {}", id).into());
+            }
+
+            for owner in &mut state.slots {
+                if owner.as_deref() == Some(id) {
+                    *owner = None;
+                }
+            }
+            state.migrating.retain(|_, target| target != id);
+            state.importing.retain(|_, source| source != id);
+
+            Ok(())
+        })
+    }
+
+    /// Join the node at `host:port` and the nodes it knows, each learns
+    /// about the others and the slots they serve. There is no gossip between
+    /// nodes afterwards, later changes are applied to every node by hand.
+    pub(crate) async fn meet(&self, host: String, port: u16) -> crate::Result<()> {
+        let (myself, my_host, my_port) = {
+            let state = self.shared.state.lock().unwrap();
+            let node = &state.nodes[&state.myself];
+            (node.id.clone(), node.host.clone(), node.port)
+        };
+
+        let mut pending = vec![(host, port)];
+        let mut met = HashSet::new();
+
+        while let Some((host, port)) = pending.pop() {
+            if (host.as_str(), port) == (my_host.as_str(), my_port)
+                || !met.insert((host.clone(), port))
+            {
+                continue;
+            }
+
+            let mut client = self.connect(&host, port).await?;
+
+            let nodes: String = client.query(Cmd::new("CLUSTER").arg("NODES")).await?;
+            let entries = parse_nodes(&nodes)?;
+
+            let knows_me = entries.iter().any(|entry| entry.node.id == myself);
+
+            let learned = self.update(|state| Ok(state.learn(entries)))?;
+
+            // Learn about us too, the other node asks for our table in turn
+            if !knows_me {
+                client
+                    .query::<()>(
+                        Cmd::new("CLUSTER")
+                            .arg("MEET")
+                            .arg(my_host.as_str())
+                            .arg(my_port),
+                    )
+                    .await?;
+            }
+
+            for node in learned {
+                info!(
+                    "met cluster node {} at {}:{}",
+                    node.id, node.host, node.port
+                );
+                pending.push((node.host, node.port));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Connect to another node with the credentials configured for it
+    pub(crate) async fn connect(&self, host: &str, port: u16) -> crate::Result<Client> {
+        let builder = ClientBuilder::new(format!("{}:{}", host, port));
+
+        let builder = match &self.shared.auth {
+            Some(auth) => {
+                let password = Bytes::copy_from_slice(auth.password());
+                match auth.username() {
+                    Some(username) => builder.user(username, password),
+                    None => builder.password(password),
+                }
+            }
+            None => builder,
+        };
+
+        builder.connect().await
+    }
+
+    /// Run `f` on the state and save it if it succeeds
+    fn update<T>(&self, f: impl FnOnce(&mut State) -> crate::Result<T>) -> crate::Result<T> {
+        let result = {
+            let mut state = self.shared.state.lock().unwrap();
+            f(&mut state)?
+        };
+
+        self.save()?;
+
+        Ok(result)
+    }
+
+    /// Write the state to the file, if there is one, through a temporary
+    /// file so a crash never leaves it half written
+    fn save(&self) -> crate::Result<()> {
+        let Some(file) = &self.shared.file else {
+            return Ok(());
+        };
+
+        let contents = {
+            let state = self.shared.state.lock().unwrap();
+            let mut contents = state.describe();
+            let _ = writeln!(contents, "vars currentEpoch {}", state.current_epoch);
+            contents
+        };
+
+        let tmp = file.with_extension("tmp");
+        std::fs::write(&tmp, contents)
+            .and_then(|()| std::fs::rename(&tmp, file))
+            .map_err(|err| format!("ERR cannot save {}: {}", file.display(), err).into())
+    }
+}
+
+impl State {
+    /// `host:port` of the node `id`
+    fn addr(&self, id: &str) -> String {
+        match self.nodes.get(id) {
+            Some(node) => format!("{}:{}", node.host, node.port),
+            None => ":0".to_string(),
+        }
+    }
+
+    fn check_node(&self, id: &str) -> crate::Result<()> {
+        if self.nodes.contains_key(id) {
+            Ok(())
+        } else {
+            Err(format!("ERR I don't know about node {}", id).into())
+        }
+    }
+
+    /// Ranges of consecutive slots served by the same node
+    fn ranges(&self) -> Vec<(u16, u16, &String)> {
+        let mut ranges: Vec<(u16, u16, &String)> = vec![];
+
+        for (slot, owner) in self.slots.iter().enumerate() {
+            let Some(owner) = owner else {
+                continue;
+            };
+            let slot = slot as u16;
+
+            match ranges.last_mut() {
+                Some((_, end, id)) if *end + 1 == slot && *id == owner => *end = slot,
+                _ => ranges.push((slot, slot, owner)),
+            }
+        }
+
+        ranges
+    }
+
+    /// One line per node, the way redis lists them
+    fn describe(&self) -> String {
+        let mut nodes: Vec<_> = self.nodes.values().collect();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let ranges = self.ranges();
+        let mut out = String::new();
+
+        for node in nodes {
+            let myself = node.id == self.myself;
+
+            let _ = write!(
+                out,
+                "{} {}:{}@{} {} - 0 0 {} connected",
+                node.id,
+                node.host,
+                node.port,
+                node.port as u32 + 10000,
+                if myself { "myself,master" } else { "master" },
+                node.epoch,
+            );
+
+            for (start, end, _) in ranges.iter().filter(|(_, _, id)| **id == node.id) {
+                if start == end {
+                    let _ = write!(out, " {}", start);
+                } else {
+                    let _ = write!(out, " {}-{}", start, end);
+                }
+            }
+
+            if myself {
+                let mut migrating: Vec<_> = self.migrating.iter().collect();
+                migrating.sort();
+                for (slot, target) in migrating {
+                    let _ = write!(out, " [{}->-{}]", slot, target);
+                }
+
+                let mut importing: Vec<_> = self.importing.iter().collect();
+                importing.sort();
+                for (slot, source) in importing {
+                    let _ = write!(out, " [{}-<-{}]", slot, source);
+                }
+            }
+
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Replace the state with the one saved in `contents`
+    fn load(&mut self, contents: &str) -> crate::Result<()> {
+        let mut myself = None;
+
+        self.nodes.clear();
+        self.slots = vec![None; SLOTS as usize];
+
+        for line in contents.lines() {
+            if let Some(epoch) = line.strip_prefix("vars currentEpoch ") {
+                self.current_epoch = epoch.trim().parse().map_err(|_| "invalid current epoch")?;
+            }
+        }
+
+        for entry in parse_nodes(contents)? {
+            for &slot in &entry.slots {
+                self.slots[slot as usize] = Some(entry.node.id.clone());
+            }
+
+            if entry.myself {
+                myself = Some(entry.node.id.clone());
+                self.migrating.extend(entry.migrating);
+                self.importing.extend(entry.importing);
+            }
+
+            self.nodes.insert(entry.node.id.clone(), entry.node);
+        }
+
+        self.myself = myself.ok_or("no node is marked myself in the cluster state")?;
+
+        Ok(())
+    }
+
+    /// Add the nodes of another node's `CLUSTER NODES` this one doesn't
+    /// know yet. A node is trusted about the slots it serves itself, the
+    /// others' claims are only taken for slots nobody serves here. Returns
+    /// the nodes added.
+    fn learn(&mut self, entries: Vec<Entry>) -> Vec<Node> {
+        let mut learned = vec![];
+
+        for entry in entries {
+            if entry.node.id == self.myself {
+                continue;
+            }
+
+            for &slot in &entry.slots {
+                let owner = &mut self.slots[slot as usize];
+                if entry.myself || owner.is_none() {
+                    *owner = Some(entry.node.id.clone());
+                }
+            }
+
+            self.current_epoch = self.current_epoch.max(entry.node.epoch);
+
+            if !self.nodes.contains_key(&entry.node.id) {
+                learned.push(entry.node.clone());
+                self.nodes.insert(entry.node.id.clone(), entry.node);
+            }
+        }
+
+        learned
+    }
+}
+
+/// Parse the output of `CLUSTER NODES`
+fn parse_nodes(text: &str) -> crate::Result<Vec<Entry>> {
+    let mut entries = vec![];
+
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        if fields.is_empty() || fields[0] == "vars" {
+            continue;
+        }
+        if fields.len() < 8 {
+            return Err(format!("invalid cluster node line: {}", line).into());
+        }
+
+        // `host:port@cport`, possibly followed by `,hostname`
+        let addr = fields[1].split(['@', ',']).next().unwrap_or_default();
+        let (host, port) = addr
+            .rsplit_once(':')
+            .ok_or_else(|| format!("invalid node address: {}", fields[1]))?;
+
+        let mut entry = Entry {
+            node: Node {
+                id: fields[0].to_string(),
+                host: host.to_string(),
+                port: port.parse().map_err(|_| "invalid node port")?,
+                epoch: fields[6].parse().map_err(|_| "invalid node epoch")?,
+            },
+            myself: fields[2].split(',').any(|flag| flag == "myself"),
+            slots: vec![],
+            migrating: vec![],
+            importing: vec![],
+        };
+
+        for field in &fields[8..] {
+            if let Some(state) = field.strip_prefix('[').and_then(|f| f.strip_suffix(']')) {
+                if let Some((slot, target)) = state.split_once("->-") {
+                    entry
+                        .migrating
+                        .push((parse_slot(slot)?, target.to_string()));
+                } else if let Some((slot, source)) = state.split_once("-<-") {
+                    entry
+                        .importing
+                        .push((parse_slot(slot)?, source.to_string()));
+                }
+                continue;
+            }
+
+            let (start, end) = match field.split_once('-') {
+                Some((start, end)) => (parse_slot(start)?, parse_slot(end)?),
+                None => (parse_slot(field)?, parse_slot(field)?),
+            };
+            entry.slots.extend(start..=end);
+        }
+
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Parse a slot number, refusing the ones out of range
+pub(crate) fn parse_slot(s: &str) -> crate::Result<u16> {
+    match s.parse() {
+        Ok(slot) if slot < SLOTS => Ok(slot),
+        _ => Err(NO_SLOT.into()),
+    }
+}
+
+/// Slot of `key`. When the key has a non-empty `{hashtag}`, only the tag is
+/// hashed, so related keys can be kept on the same node.
+pub(crate) fn key_slot(key: impl AsRef<[u8]>) -> u16 {
+    let key = key.as_ref();
+
+    let tagged = key.iter().position(|&b| b == b'{').and_then(|open| {
+        let close = key[open + 1..].iter().position(|&b| b == b'}')?;
+        (close > 0).then(|| &key[open + 1..open + 1 + close])
+    });
+
+    crc16(tagged.unwrap_or(key)) % SLOTS
+}
+
+/// CRC16-CCITT (XMODEM), the checksum redis derives slots from
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_match_redis() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(key_slot("foo"), 12182);
+        assert_eq!(key_slot("{user1000}.following"), key_slot("user1000"));
+        assert_eq!(key_slot("{user1000}.followers"), key_slot("user1000"));
+
+        // An empty or unclosed tag hashes the whole key
+        assert_eq!(key_slot("{}foo"), crc16(b"{}foo") % SLOTS);
+        assert_eq!(key_slot("{foo"), crc16(b"{foo") % SLOTS);
+        assert_eq!(key_slot("a{b}{c}"), key_slot("b"));
+    }
+
+    #[test]
+    fn slots_out_of_range_are_refused() {
+        assert_eq!(parse_slot("16383").unwrap(), 16383);
+        assert!(parse_slot("16384").is_err());
+        assert!(parse_slot("-1").is_err());
+    }
+
+    #[test]
+    fn the_state_survives_a_restart() {
+        let file = std::env::temp_dir().join(format!("tiny-redis-nodes-{}.conf", new_id()));
+
+        let cluster =
+            Cluster::new(true, "127.0.0.1".into(), 7000, Some(file.clone()), None).unwrap();
+        cluster.add_slots(&[0, 1, 2, 100]).unwrap();
+
+        let restarted =
+            Cluster::new(true, "127.0.0.1".into(), 7000, Some(file.clone()), None).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(restarted.myid(), cluster.myid());
+        assert_eq!(restarted.nodes(), cluster.nodes());
+    }
+}
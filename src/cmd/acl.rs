@@ -0,0 +1,197 @@
+use bytes::Bytes;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::acl::{self, Users};
+use crate::{Connection, Frame, Parse, ParseError};
+
+/// Inspect and change users and their permissions
+#[derive(Debug)]
+pub struct Acl {
+    subcommand: Subcommand,
+}
+
+#[derive(Debug)]
+enum Subcommand {
+    SetUser { name: String, rules: Vec<String> },
+    GetUser(String),
+    DelUser(Vec<String>),
+    List,
+    Users,
+    WhoAmI,
+    Cat(Option<String>),
+    Log(LogAction),
+    Load,
+    Save,
+}
+
+#[derive(Debug)]
+enum LogAction {
+    Show(usize),
+    Reset,
+}
+
+/// Entries `ACL LOG` shows without a count
+const LOG_DEFAULT_COUNT: usize = 10;
+
+impl Acl {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Acl> {
+        let name = parse.next_string()?.to_lowercase();
+
+        let subcommand = match name.as_str() {
+            "setuser" => Subcommand::SetUser {
+                name: parse.next_string()?,
+                rules: rest(parse)?,
+            },
+            "getuser" => Subcommand::GetUser(parse.next_string()?),
+            "deluser" => {
+                let mut names = vec![parse.next_string()?];
+                names.extend(rest(parse)?);
+                Subcommand::DelUser(names)
+            }
+            "list" => Subcommand::List,
+            "users" => Subcommand::Users,
+            "whoami" => Subcommand::WhoAmI,
+            "cat" => match parse.next_string() {
+                Ok(category) => Subcommand::Cat(Some(category.to_lowercase())),
+                Err(ParseError::EndOfStream) => Subcommand::Cat(None),
+                Err(e) => return Err(e.into()),
+            },
+            "log" => match parse.next_string() {
+                Ok(arg) if arg.eq_ignore_ascii_case("reset") => Subcommand::Log(LogAction::Reset),
+                Ok(arg) => {
+                    let count = arg
+                        .parse()
+                        .map_err(|_| "ERR value is out of range, must be positive")?;
+                    Subcommand::Log(LogAction::Show(count))
+                }
+                Err(ParseError::EndOfStream) => Subcommand::Log(LogAction::Show(LOG_DEFAULT_COUNT)),
+                Err(e) => return Err(e.into()),
+            },
+            "load" => Subcommand::Load,
+            "save" => Subcommand::Save,
+            _ => {
+                return Err(format!("ERR unknown subcommand '{}'. Try ACL HELP.", name).into());
+            }
+        };
+
+        Ok(Acl { subcommand })
+    }
+
+    /// Name of the subcommand as used in ACL rules, like `acl|setuser`
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self.subcommand {
+            Subcommand::SetUser { .. } => "acl|setuser",
+            Subcommand::GetUser(_) => "acl|getuser",
+            Subcommand::DelUser(_) => "acl|deluser",
+            Subcommand::List => "acl|list",
+            Subcommand::Users => "acl|users",
+            Subcommand::WhoAmI => "acl|whoami",
+            Subcommand::Cat(_) => "acl|cat",
+            Subcommand::Log(_) => "acl|log",
+            Subcommand::Load => "acl|load",
+            Subcommand::Save => "acl|save",
+        }
+    }
+
+    pub(crate) async fn apply(self, users: &Users, dst: &mut Connection) -> crate::Result<()> {
+        let response = match self.subcommand {
+            Subcommand::SetUser { name, rules } => ok(users.set_user(&name, &rules)),
+            Subcommand::GetUser(name) => match users.get_user(&name) {
+                Some(user) => Frame::Map(vec![
+                    (bulk("flags"), bulk_list(user.flags)),
+                    (bulk("passwords"), bulk_list(user.passwords)),
+                    (bulk("commands"), bulk(user.commands)),
+                    (bulk("keys"), bulk(user.keys)),
+                    (bulk("channels"), bulk(user.channels)),
+                ]),
+                None => Frame::Null,
+            },
+            Subcommand::DelUser(names) => match users.del_users(&names) {
+                Ok(deleted) => Frame::Integer(deleted as i64),
+                Err(err) => Frame::Error(err.to_string()),
+            },
+            Subcommand::List => bulk_list(users.list()),
+            Subcommand::Users => bulk_list(users.usernames()),
+            Subcommand::WhoAmI => match users.whoami() {
+                Some(name) => bulk(name),
+                None => Frame::Null,
+            },
+            Subcommand::Cat(None) => bulk_list(acl::categories_list().to_vec()),
+            Subcommand::Cat(Some(category)) => match acl::category_commands(&category) {
+                Some(commands) => bulk_list(commands),
+                None => Frame::Error(format!("ERR Unknown category '{}'", category)),
+            },
+            Subcommand::Log(LogAction::Reset) => {
+                users.reset_log();
+                Frame::Simple("OK".to_string())
+            }
+            Subcommand::Log(LogAction::Show(count)) => {
+                let now = SystemTime::now();
+
+                let entries = users.log(count).into_iter().map(|entry| {
+                    let age = now.duration_since(entry.created).unwrap_or_default();
+
+                    Frame::Map(vec![
+                        (bulk("count"), Frame::Integer(entry.count as i64)),
+                        (bulk("reason"), bulk(entry.reason)),
+                        (bulk("context"), bulk("toplevel")),
+                        (bulk("object"), bulk(entry.object)),
+                        (bulk("username"), bulk(entry.username)),
+                        (bulk("age-seconds"), Frame::Double(age.as_secs_f64())),
+                        (bulk("entry-id"), Frame::Integer(entry.entry_id as i64)),
+                        (
+                            bulk("timestamp-created"),
+                            Frame::Integer(millis(entry.created)),
+                        ),
+                        (
+                            bulk("timestamp-last-updated"),
+                            Frame::Integer(millis(entry.updated)),
+                        ),
+                    ])
+                });
+
+                Frame::Array(entries.collect())
+            }
+            Subcommand::Load => ok(users.load()),
+            Subcommand::Save => ok(users.save()),
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+/// Every remaining argument
+fn rest(parse: &mut Parse) -> crate::Result<Vec<String>> {
+    let mut args = vec![];
+
+    loop {
+        match parse.next_string() {
+            Ok(arg) => args.push(arg),
+            Err(ParseError::EndOfStream) => return Ok(args),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn ok(result: crate::Result<()>) -> Frame {
+    match result {
+        Ok(()) => Frame::Simple("OK".to_string()),
+        Err(err) => Frame::Error(err.to_string()),
+    }
+}
+
+fn bulk(s: impl Into<String>) -> Frame {
+    Frame::Bulk(Bytes::from(s.into()))
+}
+
+fn bulk_list<S: Into<String>>(items: Vec<S>) -> Frame {
+    Frame::Array(items.into_iter().map(bulk).collect())
+}
+
+fn millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
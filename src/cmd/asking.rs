@@ -0,0 +1,12 @@
+use crate::Parse;
+
+/// Let the next command run on a slot this node is importing, sent by a
+/// client following an `ASK` redirection
+#[derive(Debug, Default)]
+pub struct Asking;
+
+impl Asking {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Asking> {
+        Ok(Asking)
+    }
+}
@@ -0,0 +1,52 @@
+use bytes::Bytes;
+
+use crate::{Frame, Parse, ParseError};
+
+/// Authenticate the connection, as `default` when no user name is given
+#[derive(Debug, Clone)]
+pub struct Auth {
+    username: Option<String>,
+    password: Bytes,
+}
+
+// Only the connection handler knows whether the connection is authenticated,
+// so `AUTH` is applied there instead of through `Command::apply`
+
+impl Auth {
+    pub fn new(username: Option<String>, password: Bytes) -> Auth {
+        Auth { username, password }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Auth> {
+        let first = parse.next_bytes()?;
+
+        match parse.next_bytes() {
+            Ok(password) => {
+                let username = String::from_utf8(first.to_vec())
+                    .map_err(|_| "protocol error; invalid user name")?;
+                Ok(Auth::new(Some(username), password))
+            }
+            Err(ParseError::EndOfStream) => Ok(Auth::new(None, first)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The user name, `None` for the password only form
+    pub(crate) fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    pub(crate) fn password(&self) -> &[u8] {
+        &self.password
+    }
+
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("auth".as_bytes()));
+        if let Some(username) = self.username {
+            frame.push_bulk(Bytes::from(username.into_bytes()));
+        }
+        frame.push_bulk(self.password);
+        frame
+    }
+}
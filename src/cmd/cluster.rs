@@ -0,0 +1,238 @@
+use bytes::Bytes;
+
+use crate::cluster::{self, Node, SetSlot};
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+/// Inspect and change the hash slot table of a cluster node
+#[derive(Debug)]
+pub struct Cluster {
+    subcommand: Subcommand,
+}
+
+#[derive(Debug)]
+enum Subcommand {
+    Info,
+    MyId,
+    Nodes,
+    Slots,
+    Shards,
+    KeySlot(String),
+    CountKeysInSlot(u16),
+    GetKeysInSlot(u16, u64),
+    Meet(String, u16),
+    AddSlots(Vec<u16>),
+    AddSlotsRange(Vec<u16>),
+    DelSlots(Vec<u16>),
+    DelSlotsRange(Vec<u16>),
+    SetSlot(u16, SetSlot),
+    Forget(String),
+}
+
+impl Cluster {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Cluster> {
+        let name = parse.next_string()?.to_lowercase();
+
+        let subcommand = match name.as_str() {
+            "info" => Subcommand::Info,
+            "myid" => Subcommand::MyId,
+            "nodes" => Subcommand::Nodes,
+            "slots" => Subcommand::Slots,
+            "shards" => Subcommand::Shards,
+            "keyslot" => Subcommand::KeySlot(parse.next_string()?),
+            "countkeysinslot" => Subcommand::CountKeysInSlot(next_slot(parse)?),
+            "getkeysinslot" => Subcommand::GetKeysInSlot(next_slot(parse)?, parse.next_int()?),
+            "meet" => {
+                let host = parse.next_string()?;
+                let port = parse.next_int()?;
+                let port = u16::try_from(port).map_err(|_| "ERR Invalid node port")?;
+                Subcommand::Meet(host, port)
+            }
+            "addslots" => Subcommand::AddSlots(slots(parse)?),
+            "addslotsrange" => Subcommand::AddSlotsRange(slot_ranges(parse, &name)?),
+            "delslots" => Subcommand::DelSlots(slots(parse)?),
+            "delslotsrange" => Subcommand::DelSlotsRange(slot_ranges(parse, &name)?),
+            "setslot" => {
+                let slot = next_slot(parse)?;
+                let action = parse.next_string()?.to_lowercase();
+                let action = match action.as_str() {
+                    "importing" => SetSlot::Importing(parse.next_string()?),
+                    "migrating" => SetSlot::Migrating(parse.next_string()?),
+                    "node" => SetSlot::Node(parse.next_string()?),
+                    "stable" => SetSlot::Stable,
+                    _ => return Err("ERR Invalid CLUSTER SETSLOT action or number of arguments. Try CLUSTER HELP".into()),
+                };
+                Subcommand::SetSlot(slot, action)
+            }
+            "forget" => Subcommand::Forget(parse.next_string()?),
+            _ => {
+                return Err(format!("ERR unknown subcommand '{}'. Try CLUSTER HELP.", name).into());
+            }
+        };
+
+        Ok(Cluster { subcommand })
+    }
+
+    /// Name of the subcommand as used in ACL rules, like `cluster|slots`
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self.subcommand {
+            Subcommand::Info => "cluster|info",
+            Subcommand::MyId => "cluster|myid",
+            Subcommand::Nodes => "cluster|nodes",
+            Subcommand::Slots => "cluster|slots",
+            Subcommand::Shards => "cluster|shards",
+            Subcommand::KeySlot(_) => "cluster|keyslot",
+            Subcommand::CountKeysInSlot(_) => "cluster|countkeysinslot",
+            Subcommand::GetKeysInSlot(..) => "cluster|getkeysinslot",
+            Subcommand::Meet(..) => "cluster|meet",
+            Subcommand::AddSlots(_) => "cluster|addslots",
+            Subcommand::AddSlotsRange(_) => "cluster|addslotsrange",
+            Subcommand::DelSlots(_) => "cluster|delslots",
+            Subcommand::DelSlotsRange(_) => "cluster|delslotsrange",
+            Subcommand::SetSlot(..) => "cluster|setslot",
+            Subcommand::Forget(_) => "cluster|forget",
+        }
+    }
+
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        cluster: &cluster::Cluster,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        if !cluster.enabled() {
+            let response = Frame::Error("ERR This instance has cluster support disabled".into());
+            dst.feed_frame(&response).await?;
+            return Ok(());
+        }
+
+        let response = match self.subcommand {
+            Subcommand::Info => bulk(cluster.info()),
+            Subcommand::MyId => bulk(cluster.myid()),
+            Subcommand::Nodes => bulk(cluster.nodes()),
+            Subcommand::Slots => Frame::Array(
+                cluster
+                    .slot_ranges()
+                    .into_iter()
+                    .map(|(start, end, node)| {
+                        Frame::Array(vec![
+                            Frame::Integer(start as i64),
+                            Frame::Integer(end as i64),
+                            Frame::Array(vec![
+                                bulk(node.host),
+                                Frame::Integer(node.port as i64),
+                                bulk(node.id),
+                            ]),
+                        ])
+                    })
+                    .collect(),
+            ),
+            Subcommand::Shards => Frame::Array(
+                cluster
+                    .shards()
+                    .into_iter()
+                    .map(|(node, ranges)| {
+                        let slots = ranges
+                            .into_iter()
+                            .flat_map(|(start, end)| [start, end])
+                            .map(|slot| Frame::Integer(slot as i64))
+                            .collect();
+
+                        Frame::Map(vec![
+                            (bulk("slots"), Frame::Array(slots)),
+                            (bulk("nodes"), Frame::Array(vec![shard_node(node)])),
+                        ])
+                    })
+                    .collect(),
+            ),
+            Subcommand::KeySlot(key) => Frame::Integer(cluster::key_slot(&key) as i64),
+            Subcommand::CountKeysInSlot(slot) => {
+                let keys = db.keys(|key| cluster::key_slot(key) == slot, usize::MAX);
+                Frame::Integer(keys.len() as i64)
+            }
+            Subcommand::GetKeysInSlot(slot, count) => {
+                let keys = db.keys(|key| cluster::key_slot(key) == slot, count as usize);
+                Frame::Array(keys.into_iter().map(bulk).collect())
+            }
+            Subcommand::Meet(host, port) => ok(cluster.meet(host, port).await),
+            Subcommand::AddSlots(slots) | Subcommand::AddSlotsRange(slots) => {
+                ok(cluster.add_slots(&slots))
+            }
+            Subcommand::DelSlots(slots) | Subcommand::DelSlotsRange(slots) => {
+                ok(cluster.del_slots(&slots))
+            }
+            Subcommand::SetSlot(slot, action) => ok(cluster.set_slot(slot, action)),
+            Subcommand::Forget(id) => ok(cluster.forget(&id)),
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+fn next_slot(parse: &mut Parse) -> crate::Result<u16> {
+    cluster::parse_slot(&parse.next_string()?)
+}
+
+/// One slot or more, until the end of the command
+fn slots(parse: &mut Parse) -> crate::Result<Vec<u16>> {
+    let mut slots = vec![next_slot(parse)?];
+
+    loop {
+        match parse.next_string() {
+            Ok(slot) => slots.push(cluster::parse_slot(&slot)?),
+            Err(ParseError::EndOfStream) => return Ok(slots),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Pairs of a first and a last slot, expanded to every slot in between
+fn slot_ranges(parse: &mut Parse, name: &str) -> crate::Result<Vec<u16>> {
+    let bounds = slots(parse)?;
+
+    if bounds.len() % 2 != 0 {
+        return Err(format!(
+            "ERR wrong number of arguments for 'cluster|{}' command",
+            name
+        )
+        .into());
+    }
+
+    let mut slots = vec![];
+    for range in bounds.chunks(2) {
+        if range[0] > range[1] {
+            return Err(format!(
+                "ERR start slot number {} is greater than end slot number {}",
+                range[0], range[1]
+            )
+            .into());
+        }
+        slots.extend(range[0]..=range[1]);
+    }
+
+    Ok(slots)
+}
+
+fn shard_node(node: Node) -> Frame {
+    Frame::Map(vec![
+        (bulk("id"), bulk(node.id)),
+        (bulk("port"), Frame::Integer(node.port as i64)),
+        (bulk("ip"), bulk(node.host.clone())),
+        (bulk("endpoint"), bulk(node.host)),
+        (bulk("role"), bulk("master")),
+        (bulk("replication-offset"), Frame::Integer(0)),
+        (bulk("health"), bulk("online")),
+    ])
+}
+
+fn ok(result: crate::Result<()>) -> Frame {
+    match result {
+        Ok(()) => Frame::Simple("OK".to_string()),
+        Err(err) => Frame::Error(err.to_string()),
+    }
+}
+
+fn bulk(s: impl Into<String>) -> Frame {
+    Frame::Bulk(Bytes::from(s.into()))
+}
@@ -0,0 +1,19 @@
+use crate::{Connection, Db, Frame, Parse};
+
+/// Return the number of keys in the selected database
+#[derive(Debug, Default)]
+pub struct DbSize;
+
+impl DbSize {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<DbSize> {
+        Ok(DbSize)
+    }
+
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = Frame::Integer(db.size() as i64);
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
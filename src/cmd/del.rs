@@ -0,0 +1,57 @@
+use bytes::Bytes;
+
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+/// Remove keys, replies with how many existed
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<String>,
+}
+
+impl Del {
+    pub fn new<K: ToString>(keys: &[K]) -> Del {
+        Del {
+            keys: keys.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Del> {
+        Ok(Del {
+            keys: parse_keys(parse)?,
+        })
+    }
+
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let deleted = self.keys.iter().filter(|key| db.delete(key)).count();
+
+        dst.feed_frame(&Frame::Integer(deleted as i64)).await?;
+
+        Ok(())
+    }
+
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("del".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+}
+
+/// One key or more, until the end of the command
+pub(crate) fn parse_keys(parse: &mut Parse) -> crate::Result<Vec<String>> {
+    let mut keys = vec![parse.next_string()?];
+
+    loop {
+        match parse.next_string() {
+            Ok(key) => keys.push(key),
+            Err(ParseError::EndOfStream) => return Ok(keys),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
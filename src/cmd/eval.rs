@@ -0,0 +1,108 @@
+use bytes::Bytes;
+
+use crate::script::{Caller, Scripts};
+use crate::{Connection, Frame, Parse, ParseError};
+
+/// Run a Lua script, with no other command running until it ends
+#[derive(Debug)]
+pub struct Eval {
+    script: String,
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+}
+
+/// Run a script loaded before, by the SHA1 digest of its body
+#[derive(Debug)]
+pub struct EvalSha {
+    sha: String,
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+}
+
+impl Eval {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Eval> {
+        let script = parse.next_string()?;
+        let (keys, args) = parse_keys_and_args(parse)?;
+
+        Ok(Eval { script, keys, args })
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub(crate) async fn apply(
+        self,
+        caller: &Caller<'_>,
+        scripts: &Scripts,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = match scripts.load(&self.script) {
+            Ok(_) => {
+                scripts
+                    .eval(caller, self.script, self.keys, self.args)
+                    .await
+            }
+            Err(err) => Frame::Error(err.to_string()),
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+impl EvalSha {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<EvalSha> {
+        let sha = parse.next_string()?;
+        let (keys, args) = parse_keys_and_args(parse)?;
+
+        Ok(EvalSha { sha, keys, args })
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub(crate) async fn apply(
+        self,
+        caller: &Caller<'_>,
+        scripts: &Scripts,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = match scripts.get(&self.sha) {
+            Some(script) => scripts.eval(caller, script, self.keys, self.args).await,
+            None => Frame::Error("NOSCRIPT No matching script. Please use EVAL.".to_string()),
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+/// `numkeys key [key ...] arg [arg ...]`, the arguments after the keys
+/// are passed as they are
+pub(crate) fn parse_keys_and_args(parse: &mut Parse) -> crate::Result<(Vec<String>, Vec<Bytes>)> {
+    let numkeys = parse.next_int()?;
+
+    let mut keys = vec![];
+    for _ in 0..numkeys {
+        match parse.next_string() {
+            Ok(key) => keys.push(key),
+            Err(ParseError::EndOfStream) => {
+                return Err("ERR Number of keys can't be greater than number of args".into());
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let mut args = vec![];
+    loop {
+        match parse.next_bytes() {
+            Ok(arg) => args.push(arg),
+            Err(ParseError::EndOfStream) => return Ok((keys, args)),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use bytes::Bytes;
+
+use crate::cmd::eval::parse_keys_and_args;
+use crate::script::{Caller, Scripts};
+use crate::{Connection, Parse};
+
+/// Run a function of a library loaded with `FUNCTION LOAD`. `FCALL_RO`
+/// only runs functions flagged `no-writes`.
+#[derive(Debug)]
+pub struct FCall {
+    function: String,
+    keys: Vec<String>,
+    args: Vec<Bytes>,
+    read_only: bool,
+}
+
+impl FCall {
+    pub(crate) fn parse_frames(parse: &mut Parse, read_only: bool) -> crate::Result<FCall> {
+        let function = parse.next_string()?;
+        let (keys, args) = parse_keys_and_args(parse)?;
+
+        Ok(FCall {
+            function,
+            keys,
+            args,
+            read_only,
+        })
+    }
+
+    pub(crate) fn get_name(&self) -> &'static str {
+        if self.read_only { "fcall_ro" } else { "fcall" }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub(crate) async fn apply(
+        self,
+        caller: &Caller<'_>,
+        scripts: &Scripts,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = scripts
+            .fcall(caller, self.function, self.keys, self.args, self.read_only)
+            .await;
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+/// Remove all keys of the selected database
+#[derive(Debug, Default)]
+pub struct FlushDb {
+    lazy: bool,
+}
+
+/// Remove all keys of every database
+#[derive(Debug, Default)]
+pub struct FlushAll {
+    lazy: bool,
+}
+
+impl FlushDb {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<FlushDb> {
+        let lazy = parse_mode(parse)?;
+
+        Ok(FlushDb { lazy })
+    }
+
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        db.flush(self.lazy);
+
+        dst.feed_frame(&Frame::Simple("OK".to_string())).await?;
+
+        Ok(())
+    }
+}
+
+impl FlushAll {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<FlushAll> {
+        let lazy = parse_mode(parse)?;
+
+        Ok(FlushAll { lazy })
+    }
+
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        db.flush_all(self.lazy);
+
+        dst.feed_frame(&Frame::Simple("OK".to_string())).await?;
+
+        Ok(())
+    }
+}
+
+/// Parse the optional `ASYNC`/`SYNC` modifier, returns whether the flush is lazy
+fn parse_mode(parse: &mut Parse) -> crate::Result<bool> {
+    match parse.next_string() {
+        Ok(s) if s.eq_ignore_ascii_case("async") => Ok(true),
+        Ok(s) if s.eq_ignore_ascii_case("sync") => Ok(false),
+        Ok(s) => Err(format!("ERR syntax error, unexpected `{}`", s).into()),
+        Err(ParseError::EndOfStream) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,201 @@
+use bytes::Bytes;
+
+use crate::acl;
+use crate::script::{RestorePolicy, Scripts};
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+/// Manage the libraries of functions run with `FCALL`
+#[derive(Debug)]
+pub struct Function {
+    subcommand: Subcommand,
+}
+
+#[derive(Debug)]
+enum Subcommand {
+    Load {
+        code: String,
+        replace: bool,
+    },
+    List {
+        pattern: Option<String>,
+        with_code: bool,
+    },
+    Delete(String),
+    Dump,
+    Restore {
+        payload: Bytes,
+        policy: RestorePolicy,
+    },
+    Flush,
+}
+
+impl Function {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Function> {
+        let name = parse.next_string()?.to_lowercase();
+
+        let subcommand = match name.as_str() {
+            "load" => {
+                let first = parse.next_string()?;
+                if first.eq_ignore_ascii_case("replace") {
+                    Subcommand::Load {
+                        code: parse.next_string()?,
+                        replace: true,
+                    }
+                } else {
+                    Subcommand::Load {
+                        code: first,
+                        replace: false,
+                    }
+                }
+            }
+            "list" => {
+                let mut pattern = None;
+                let mut with_code = false;
+                loop {
+                    match parse.next_string() {
+                        Ok(arg) if arg.eq_ignore_ascii_case("withcode") => with_code = true,
+                        Ok(arg) if arg.eq_ignore_ascii_case("libraryname") => {
+                            pattern = Some(parse.next_string()?);
+                        }
+                        Ok(arg) => return Err(format!("ERR Unknown argument {}", arg).into()),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Subcommand::List { pattern, with_code }
+            }
+            "delete" => Subcommand::Delete(parse.next_string()?),
+            "dump" => Subcommand::Dump,
+            "restore" => {
+                let payload = parse.next_bytes()?;
+                let policy = match parse.next_string() {
+                    Ok(policy) if policy.eq_ignore_ascii_case("append") => RestorePolicy::Append,
+                    Ok(policy) if policy.eq_ignore_ascii_case("replace") => RestorePolicy::Replace,
+                    Ok(policy) if policy.eq_ignore_ascii_case("flush") => RestorePolicy::Flush,
+                    Ok(_) => {
+                        return Err("ERR Wrong restore policy given, value should be either FLUSH, APPEND or REPLACE.".into());
+                    }
+                    Err(ParseError::EndOfStream) => RestorePolicy::Append,
+                    Err(e) => return Err(e.into()),
+                };
+                Subcommand::Restore { payload, policy }
+            }
+            "flush" => {
+                // Libraries are always dropped at once, `ASYNC` changes nothing
+                match parse.next_string() {
+                    Ok(mode) if mode.eq_ignore_ascii_case("async") => {}
+                    Ok(mode) if mode.eq_ignore_ascii_case("sync") => {}
+                    Ok(_) => {
+                        return Err("ERR FUNCTION FLUSH only supports SYNC|ASYNC option".into());
+                    }
+                    Err(ParseError::EndOfStream) => {}
+                    Err(e) => return Err(e.into()),
+                }
+                Subcommand::Flush
+            }
+            _ => {
+                return Err(
+                    format!("ERR unknown subcommand '{}'. Try FUNCTION HELP.", name).into(),
+                );
+            }
+        };
+
+        Ok(Function { subcommand })
+    }
+
+    /// Name of the subcommand as used in ACL rules, like `function|load`
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self.subcommand {
+            Subcommand::Load { .. } => "function|load",
+            Subcommand::List { .. } => "function|list",
+            Subcommand::Delete(_) => "function|delete",
+            Subcommand::Dump => "function|dump",
+            Subcommand::Restore { .. } => "function|restore",
+            Subcommand::Flush => "function|flush",
+        }
+    }
+
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        scripts: &Scripts,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let ok = |result: crate::Result<()>| match result {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(err.to_string()),
+        };
+
+        let response = match self.subcommand {
+            Subcommand::Load { code, replace } => match scripts.load_library(db, &code, replace) {
+                Ok(name) => Frame::Bulk(Bytes::from(name)),
+                Err(err) => Frame::Error(err.to_string()),
+            },
+            Subcommand::List { pattern, with_code } => {
+                let libraries = scripts
+                    .libraries()
+                    .into_iter()
+                    .filter(|library| match &pattern {
+                        Some(pattern) => acl::matches(pattern.as_bytes(), library.name.as_bytes()),
+                        None => true,
+                    })
+                    .map(|library| {
+                        let functions = library
+                            .functions
+                            .into_iter()
+                            .map(|function| {
+                                Frame::Map(vec![
+                                    (bulk("name"), Frame::Bulk(Bytes::from(function.name))),
+                                    (
+                                        bulk("description"),
+                                        function
+                                            .description
+                                            .map_or(Frame::Null, |d| Frame::Bulk(Bytes::from(d))),
+                                    ),
+                                    (
+                                        bulk("flags"),
+                                        Frame::Set(
+                                            function
+                                                .flags
+                                                .into_iter()
+                                                .map(|flag| Frame::Bulk(Bytes::from(flag)))
+                                                .collect(),
+                                        ),
+                                    ),
+                                ])
+                            })
+                            .collect();
+
+                        let mut fields = vec![
+                            (bulk("library_name"), Frame::Bulk(Bytes::from(library.name))),
+                            (bulk("engine"), bulk("LUA")),
+                            (bulk("functions"), Frame::Array(functions)),
+                        ];
+                        if with_code {
+                            fields.push((
+                                bulk("library_code"),
+                                Frame::Bulk(Bytes::from(library.code)),
+                            ));
+                        }
+
+                        Frame::Map(fields)
+                    })
+                    .collect();
+
+                Frame::Array(libraries)
+            }
+            Subcommand::Delete(name) => ok(scripts.delete_library(db, &name)),
+            Subcommand::Dump => Frame::Bulk(scripts.dump()),
+            Subcommand::Restore { payload, policy } => ok(scripts.restore(db, &payload, policy)),
+            Subcommand::Flush => ok(scripts.flush_libraries(db)),
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+fn bulk(s: &'static str) -> Frame {
+    Frame::Bulk(Bytes::from_static(s.as_bytes()))
+}
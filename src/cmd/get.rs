@@ -1,7 +1,7 @@
 use bytes::Bytes;
 
+use crate::Parse;
 use crate::{Connection, Db, Frame};
-use crate::{Parse, ParseError};
 
 #[derive(Debug)]
 pub struct Get {
@@ -33,7 +33,7 @@ impl Get {
             Frame::Null
         };
 
-        dst.write_frame(&response).await?;
+        dst.feed_frame(&response).await?;
 
         Ok(())
     }
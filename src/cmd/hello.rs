@@ -0,0 +1,118 @@
+use bytes::Bytes;
+
+use crate::cmd::Auth;
+use crate::{Connection, Frame, Parse, ParseError};
+
+/// Handshake with the server, optionally switching the RESP version and
+/// authenticating
+#[derive(Debug, Default)]
+pub struct Hello {
+    // requested protocol version, keep the current one if `None`
+    protocol: Option<u64>,
+
+    // credentials given with the `AUTH` option, checked by the connection
+    // handler before the command is applied
+    auth: Option<Auth>,
+}
+
+impl Hello {
+    pub fn new(protocol: Option<u64>) -> Hello {
+        Hello {
+            protocol,
+            auth: None,
+        }
+    }
+
+    /// Authenticate as `username` in the same round trip, this needs a
+    /// protocol version
+    pub fn auth(mut self, username: String, password: Bytes) -> Hello {
+        self.auth = Some(Auth::new(Some(username), password));
+        self
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Hello> {
+        let protocol = match parse.next_int() {
+            Ok(protocol) => Some(protocol),
+            Err(ParseError::EndOfStream) => return Ok(Hello::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut auth = None;
+
+        // `SETNAME` is accepted for compatibility with existing clients, the
+        // server has no client names
+        loop {
+            match parse.next_string() {
+                Ok(opt) if opt.eq_ignore_ascii_case("auth") => {
+                    let username = parse.next_string()?;
+                    let password = parse.next_bytes()?;
+                    auth = Some(Auth::new(Some(username), password));
+                }
+                Ok(opt) if opt.eq_ignore_ascii_case("setname") => {
+                    parse.next_bytes()?;
+                }
+                Ok(opt) => return Err(format!("ERR syntax error in HELLO option '{}'", opt).into()),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(Hello { protocol, auth })
+    }
+
+    pub(crate) fn credentials(&self) -> Option<&Auth> {
+        self.auth.as_ref()
+    }
+
+    /// `mode` tells whether the server runs as a cluster node, `role` is its
+    /// replication role
+    pub(crate) async fn apply(
+        self,
+        mode: &'static str,
+        role: &'static str,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        match self.protocol {
+            Some(protocol @ (2 | 3)) => dst.set_protocol(protocol as u8),
+            Some(_) => {
+                let response = Frame::Error("NOPROTO unsupported protocol version".to_string());
+                dst.feed_frame(&response).await?;
+                return Ok(());
+            }
+            None => {}
+        }
+
+        // The reply is a map, a RESP2 connection receives it as a flat array
+        let response = Frame::Map(vec![
+            (bulk("server"), bulk("tiny-redis")),
+            (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+            (bulk("proto"), Frame::Integer(dst.protocol() as i64)),
+            (bulk("mode"), bulk(mode)),
+            (bulk("role"), bulk(role)),
+            (bulk("modules"), Frame::array()),
+        ]);
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hello".as_bytes()));
+        if let Some(protocol) = self.protocol {
+            frame.push_bulk(Bytes::from(protocol.to_string()));
+        }
+        if let Some(auth) = self.auth {
+            frame.push_bulk(Bytes::from("auth".as_bytes()));
+            let username = auth.username().unwrap_or("default");
+            frame.push_bulk(Bytes::from(username.to_string()));
+            frame.push_bulk(Bytes::copy_from_slice(auth.password()));
+        }
+        frame
+    }
+}
+
+fn bulk(s: &'static str) -> Frame {
+    Frame::Bulk(Bytes::from_static(s.as_bytes()))
+}
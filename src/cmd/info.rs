@@ -0,0 +1,41 @@
+use bytes::Bytes;
+
+use crate::replication::Replication;
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+/// Report the state of the server, only the replication section exists
+#[derive(Debug, Default)]
+pub struct Info {
+    section: Option<String>,
+}
+
+impl Info {
+    pub fn new(section: Option<String>) -> Info {
+        Info { section }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Info> {
+        match parse.next_string() {
+            Ok(section) => Ok(Info::new(Some(section.to_lowercase()))),
+            Err(ParseError::EndOfStream) => Ok(Info::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        replication: &Replication,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let info = match self.section.as_deref() {
+            None | Some("all" | "default" | "everything" | "replication") => replication.info(db),
+            // Unknown sections are empty, like redis
+            Some(_) => String::new(),
+        };
+
+        dst.feed_frame(&Frame::Bulk(Bytes::from(info))).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use bytes::Bytes;
+
+use crate::cmd::del::parse_keys;
+use crate::{Connection, Db, Frame, Parse};
+
+/// Get the values of several keys, null for the missing ones
+#[derive(Debug)]
+pub struct MGet {
+    keys: Vec<String>,
+}
+
+impl MGet {
+    pub fn new<K: ToString>(keys: &[K]) -> MGet {
+        MGet {
+            keys: keys.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<MGet> {
+        Ok(MGet {
+            keys: parse_keys(parse)?,
+        })
+    }
+
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let values = self
+            .keys
+            .iter()
+            .map(|key| db.get(key).map_or(Frame::Null, Frame::Bulk))
+            .collect();
+
+        dst.feed_frame(&Frame::Array(values)).await?;
+
+        Ok(())
+    }
+
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("mget".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(Bytes::from(key.into_bytes()));
+        }
+        frame
+    }
+}
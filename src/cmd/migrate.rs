@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::client::{ClientBuilder, Pipeline};
+use crate::cluster::Cluster;
+use crate::{Connection, Db, Frame, Parse, ParseError};
+
+/// Move keys to another node, used to move a hash slot. The keys are
+/// written on the target and then removed here, unless `COPY` is given.
+#[derive(Debug)]
+pub struct Migrate {
+    host: String,
+    port: u16,
+    keys: Vec<String>,
+    db: u64,
+    timeout: Duration,
+    copy: bool,
+
+    // `AUTH` or `AUTH2` option, credentials for the target
+    username: Option<String>,
+    password: Option<Bytes>,
+}
+
+impl Migrate {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Migrate> {
+        let host = parse.next_string()?;
+        let port = u16::try_from(parse.next_int()?).map_err(|_| "ERR Invalid port")?;
+        let key = parse.next_string()?;
+        let db = parse.next_int()?;
+        let timeout = Duration::from_millis(parse.next_int()?);
+
+        let mut migrate = Migrate {
+            host,
+            port,
+            keys: vec![],
+            db,
+            timeout,
+            copy: false,
+            username: None,
+            password: None,
+        };
+
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_lowercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+
+            match option.as_str() {
+                "copy" => migrate.copy = true,
+                // Keys are always replaced on the target, `SET` is sent
+                "replace" => {}
+                "auth" => migrate.password = Some(parse.next_bytes()?),
+                "auth2" => {
+                    migrate.username = Some(parse.next_string()?);
+                    migrate.password = Some(parse.next_bytes()?);
+                }
+                "keys" => {
+                    if !key.is_empty() {
+                        return Err("ERR When using MIGRATE KEYS option, the key argument must be set to the empty string".into());
+                    }
+                    loop {
+                        match parse.next_string() {
+                            Ok(key) => migrate.keys.push(key),
+                            Err(ParseError::EndOfStream) => break,
+                            Err(e) => return Err(e.into()),
+                        }
+                    }
+                }
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+
+        if !key.is_empty() {
+            migrate.keys.push(key);
+        }
+
+        Ok(migrate)
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        cluster: &Cluster,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = match self.migrate(db, cluster).await {
+            Ok(0) => Frame::Simple("NOKEY".to_string()),
+            Ok(_) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(format!("IOERR error or timeout migrating keys: {}", err)),
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Returns the number of keys migrated
+    async fn migrate(&self, db: &Db, cluster: &Cluster) -> crate::Result<usize> {
+        let entries: Vec<(&str, Bytes)> = self
+            .keys
+            .iter()
+            .filter_map(|key| db.get(key).map(|value| (key.as_str(), value)))
+            .collect();
+
+        if entries.is_empty() {
+            return Ok(0);
+        }
+
+        let builder = ClientBuilder::new(format!("{}:{}", self.host, self.port))
+            .connect_timeout(self.timeout)
+            .request_timeout(self.timeout);
+        let builder = match (&self.username, &self.password) {
+            (Some(username), Some(password)) => builder.user(username, password.clone()),
+            (None, Some(password)) => builder.password(password.clone()),
+            _ => builder,
+        };
+
+        let mut client = builder.connect().await?;
+
+        let mut pipeline = Pipeline::new();
+        if self.db != 0 {
+            pipeline.cmd("SELECT").arg(self.db);
+        }
+        for (key, value) in &entries {
+            // The slot is still being imported on the target
+            if cluster.enabled() {
+                pipeline.cmd("ASKING");
+            }
+            pipeline.set(key, value.clone());
+        }
+
+        pipeline.query::<Vec<Frame>>(&mut client).await?;
+
+        if !self.copy {
+            for (key, _) in &entries {
+                db.delete(key);
+            }
+        }
+
+        Ok(entries.len())
+    }
+}
@@ -7,14 +7,127 @@ pub use set::Set;
 mod get;
 pub use get::Get;
 
-use crate::{Connection, Db, Frame, Parse};
+mod del;
+pub use del::Del;
+
+mod mget;
+pub use mget::MGet;
+
+mod select;
+pub use select::Select;
+
+mod move_key;
+pub use move_key::Move;
+
+mod swapdb;
+pub use swapdb::SwapDb;
+
+mod dbsize;
+pub use dbsize::DbSize;
+
+mod flush;
+pub use flush::{FlushAll, FlushDb};
+
+mod hello;
+pub use hello::Hello;
+
+mod auth;
+pub use auth::Auth;
+
+mod quit;
+pub use quit::Quit;
+
+mod acl;
+pub use acl::Acl;
+
+mod info;
+pub use info::Info;
+
+mod replicaof;
+pub use replicaof::ReplicaOf;
+
+mod psync;
+pub use psync::Psync;
+
+mod replconf;
+pub use replconf::ReplConf;
+
+mod cluster;
+pub use cluster::Cluster;
+
+mod asking;
+pub use asking::Asking;
+
+mod migrate;
+pub use migrate::Migrate;
+
+mod eval;
+pub use eval::{Eval, EvalSha};
+
+mod script;
+pub use script::Script;
+
+mod function;
+pub use function::Function;
+
+mod fcall;
+pub use fcall::FCall;
+
+mod subscribe;
+pub use subscribe::{PSubscribe, PUnsubscribe, Subscribe, Unsubscribe};
+
+mod publish;
+pub use publish::Publish;
+
+mod transaction;
+pub use transaction::{Discard, Exec, Multi};
+
+mod unknown;
+pub use unknown::Unknown;
+
+use crate::acl::Users;
+use crate::replication::Replication;
+use crate::script::{Caller, Scripts};
+use crate::{Connection, Db, Frame, Parse, ParseError};
 
 #[derive(Debug)]
 pub enum Command {
     Ping(Ping),
     Set(Set),
     Get(Get),
-    Unknown(String),
+    Del(Del),
+    MGet(MGet),
+    Select(Select),
+    Move(Move),
+    SwapDb(SwapDb),
+    DbSize(DbSize),
+    FlushDb(FlushDb),
+    FlushAll(FlushAll),
+    Hello(Hello),
+    Auth(Auth),
+    Quit(Quit),
+    Acl(Acl),
+    Info(Info),
+    ReplicaOf(ReplicaOf),
+    Psync(Psync),
+    ReplConf(ReplConf),
+    Cluster(Cluster),
+    Asking(Asking),
+    Migrate(Migrate),
+    Eval(Eval),
+    EvalSha(EvalSha),
+    Script(Script),
+    Function(Function),
+    FCall(FCall),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    PSubscribe(PSubscribe),
+    PUnsubscribe(PUnsubscribe),
+    Publish(Publish),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Unknown(Unknown),
 }
 
 impl Command {
@@ -27,7 +140,44 @@ impl Command {
             "ping" => Command::Ping(Ping::parse_frames(&mut parse)?),
             "set" => Command::Set(Set::parse_frames(&mut parse)?),
             "get" => Command::Get(Get::parse_frames(&mut parse)?),
-            _ => unimplemented!(),
+            "del" => Command::Del(Del::parse_frames(&mut parse)?),
+            "mget" => Command::MGet(MGet::parse_frames(&mut parse)?),
+            "select" => Command::Select(Select::parse_frames(&mut parse)?),
+            "move" => Command::Move(Move::parse_frames(&mut parse)?),
+            "swapdb" => Command::SwapDb(SwapDb::parse_frames(&mut parse)?),
+            "dbsize" => Command::DbSize(DbSize::parse_frames(&mut parse)?),
+            "flushdb" => Command::FlushDb(FlushDb::parse_frames(&mut parse)?),
+            "flushall" => Command::FlushAll(FlushAll::parse_frames(&mut parse)?),
+            "hello" => Command::Hello(Hello::parse_frames(&mut parse)?),
+            "auth" => Command::Auth(Auth::parse_frames(&mut parse)?),
+            "quit" => Command::Quit(Quit::parse_frames(&mut parse)?),
+            "acl" => Command::Acl(Acl::parse_frames(&mut parse)?),
+            "info" => Command::Info(Info::parse_frames(&mut parse)?),
+            "replicaof" | "slaveof" => Command::ReplicaOf(ReplicaOf::parse_frames(&mut parse)?),
+            "psync" => Command::Psync(Psync::parse_frames(&mut parse)?),
+            "replconf" => Command::ReplConf(ReplConf::parse_frames(&mut parse)?),
+            "cluster" => Command::Cluster(Cluster::parse_frames(&mut parse)?),
+            "asking" => Command::Asking(Asking::parse_frames(&mut parse)?),
+            "migrate" => Command::Migrate(Migrate::parse_frames(&mut parse)?),
+            "eval" => Command::Eval(Eval::parse_frames(&mut parse)?),
+            "evalsha" => Command::EvalSha(EvalSha::parse_frames(&mut parse)?),
+            "script" => Command::Script(Script::parse_frames(&mut parse)?),
+            "function" => Command::Function(Function::parse_frames(&mut parse)?),
+            "fcall" => Command::FCall(FCall::parse_frames(&mut parse, false)?),
+            "fcall_ro" => Command::FCall(FCall::parse_frames(&mut parse, true)?),
+            "subscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parse)?),
+            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(&mut parse)?),
+            "psubscribe" => Command::PSubscribe(PSubscribe::parse_frames(&mut parse)?),
+            "punsubscribe" => Command::PUnsubscribe(PUnsubscribe::parse_frames(&mut parse)?),
+            "publish" => Command::Publish(Publish::parse_frames(&mut parse)?),
+            "multi" => Command::Multi(Multi::parse_frames(&mut parse)?),
+            "exec" => Command::Exec(Exec::parse_frames(&mut parse)?),
+            "discard" => Command::Discard(Discard::parse_frames(&mut parse)?),
+            _ => {
+                // The remaining arguments are not consumed, so skip the
+                // `finish` check below
+                return Ok(Command::Unknown(Unknown::new(command_name)));
+            }
         };
 
         parse.finish()?;
@@ -35,12 +185,123 @@ impl Command {
         Ok(command)
     }
 
-    pub(crate) async fn apply(self, db: &mut Db, dst: &mut Connection) -> crate::Result<()> {
+    /// Run the command if the connection's user is allowed to, otherwise
+    /// reply with the reason it is not
+    pub(crate) async fn apply(
+        self,
+        db: &mut Db,
+        users: &Users,
+        replication: &Replication,
+        cluster: &mut crate::cluster::Cluster,
+        scripts: &Scripts,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        // An unknown command is refused as such whoever runs it
+        if !matches!(self, Command::Unknown(_))
+            && let Err(err) = users.check(self.get_acl_name(), &self.keys(), &self.channels())
+        {
+            dst.feed_frame(&Frame::Error(err.to_string())).await?;
+            return Ok(());
+        }
+
+        if let Command::Asking(_) = self {
+            cluster.set_asking();
+            dst.feed_frame(&Frame::Simple("OK".to_string())).await?;
+            return Ok(());
+        }
+
+        // Keys served by another node are redirected there. `MIGRATE` runs
+        // where its keys are, whatever state their slot is in.
+        let redirect = match &self {
+            Command::Migrate(_) => cluster.redirect(&[], |_| true),
+            command => cluster.redirect(&command.keys(), |key| db.exists(key)),
+        };
+        if let Some(err) = redirect {
+            dst.feed_frame(&Frame::Error(err)).await?;
+            return Ok(());
+        }
+
+        if let Command::Select(cmd) = &self
+            && cluster.enabled()
+            && cmd.index() != 0
+        {
+            let response = Frame::Error("ERR SELECT is not allowed in cluster mode".to_string());
+            dst.feed_frame(&response).await?;
+            return Ok(());
+        }
+
         match self {
             Command::Ping(cmd) => cmd.apply(dst).await,
-            Command::Set(cmd) => cmd.apply(&db, dst).await,
-            Command::Get(cmd) => cmd.apply(&db, dst).await,
-            _ => unimplemented!(),
+            Command::Set(cmd) => cmd.apply(db, dst).await,
+            Command::Get(cmd) => cmd.apply(db, dst).await,
+            Command::Del(cmd) => cmd.apply(db, dst).await,
+            Command::MGet(cmd) => cmd.apply(db, dst).await,
+            Command::Select(cmd) => cmd.apply(db, dst).await,
+            Command::Move(cmd) => cmd.apply(db, dst).await,
+            Command::SwapDb(cmd) => cmd.apply(db, dst).await,
+            Command::DbSize(cmd) => cmd.apply(db, dst).await,
+            Command::FlushDb(cmd) => cmd.apply(db, dst).await,
+            Command::FlushAll(cmd) => cmd.apply(db, dst).await,
+            Command::Hello(cmd) => {
+                let mode = if cluster.enabled() {
+                    "cluster"
+                } else {
+                    "standalone"
+                };
+                cmd.apply(mode, replication.role(), dst).await
+            }
+            Command::Acl(cmd) => cmd.apply(users, dst).await,
+            Command::Info(cmd) => cmd.apply(db, replication, dst).await,
+            Command::ReplicaOf(cmd) => cmd.apply(db, replication, scripts, dst).await,
+            Command::Cluster(cmd) => cmd.apply(db, cluster, dst).await,
+            Command::Migrate(cmd) => cmd.apply(db, cluster, dst).await,
+            Command::Eval(cmd) => {
+                let caller = Caller {
+                    db,
+                    users,
+                    replication,
+                    cluster,
+                };
+                cmd.apply(&caller, scripts, dst).await
+            }
+            Command::EvalSha(cmd) => {
+                let caller = Caller {
+                    db,
+                    users,
+                    replication,
+                    cluster,
+                };
+                cmd.apply(&caller, scripts, dst).await
+            }
+            Command::Script(cmd) => cmd.apply(scripts, dst).await,
+            Command::Function(cmd) => cmd.apply(db, scripts, dst).await,
+            Command::Publish(cmd) => cmd.apply(db, dst).await,
+            Command::FCall(cmd) => {
+                let caller = Caller {
+                    db,
+                    users,
+                    replication,
+                    cluster,
+                };
+                cmd.apply(&caller, scripts, dst).await
+            }
+            // Applied above, it only sets a flag of the connection
+            Command::Asking(_) => Ok(()),
+            // The transaction queue is kept by the connection handler
+            Command::Multi(_) | Command::Exec(_) | Command::Discard(_) => {
+                Err("transaction commands are applied by the connection handler".into())
+            }
+            Command::Auth(_)
+            | Command::Quit(_)
+            | Command::Psync(_)
+            | Command::ReplConf(_)
+            | Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PSubscribe(_)
+            | Command::PUnsubscribe(_) => {
+                Err("connection commands are applied by the connection handler".into())
+            }
+            Command::Unknown(cmd) => cmd.apply(dst).await,
         }
     }
 
@@ -50,7 +311,98 @@ impl Command {
             Command::Ping(_) => "ping",
             Command::Set(_) => "set",
             Command::Get(_) => "get",
-            _ => unimplemented!(),
+            Command::Del(_) => "del",
+            Command::MGet(_) => "mget",
+            Command::Select(_) => "select",
+            Command::Move(_) => "move",
+            Command::SwapDb(_) => "swapdb",
+            Command::DbSize(_) => "dbsize",
+            Command::FlushDb(_) => "flushdb",
+            Command::FlushAll(_) => "flushall",
+            Command::Hello(_) => "hello",
+            Command::Auth(_) => "auth",
+            Command::Quit(_) => "quit",
+            Command::Acl(_) => "acl",
+            Command::Info(_) => "info",
+            Command::ReplicaOf(_) => "replicaof",
+            Command::Psync(_) => "psync",
+            Command::ReplConf(_) => "replconf",
+            Command::Cluster(_) => "cluster",
+            Command::Asking(_) => "asking",
+            Command::Migrate(_) => "migrate",
+            Command::Eval(_) => "eval",
+            Command::EvalSha(_) => "evalsha",
+            Command::Script(_) => "script",
+            Command::Function(_) => "function",
+            Command::FCall(cmd) => cmd.get_name(),
+            Command::Subscribe(_) => "subscribe",
+            Command::Unsubscribe(_) => "unsubscribe",
+            Command::PSubscribe(_) => "psubscribe",
+            Command::PUnsubscribe(_) => "punsubscribe",
+            Command::Publish(_) => "publish",
+            Command::Multi(_) => "multi",
+            Command::Exec(_) => "exec",
+            Command::Discard(_) => "discard",
+            Command::Unknown(cmd) => cmd.get_name(),
         }
     }
+
+    /// Returns the name ACL rules know the command by, subcommands of a
+    /// container command are named like `acl|setuser`
+    pub(crate) fn get_acl_name(&self) -> &str {
+        match self {
+            Command::Acl(cmd) => cmd.get_name(),
+            Command::Cluster(cmd) => cmd.get_name(),
+            Command::Script(cmd) => cmd.get_name(),
+            Command::Function(cmd) => cmd.get_name(),
+            command => command.get_name(),
+        }
+    }
+
+    /// Returns the keys the command accesses
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        match self {
+            Command::Set(cmd) => vec![cmd.key()],
+            Command::Get(cmd) => vec![cmd.key()],
+            Command::Move(cmd) => vec![cmd.key()],
+            Command::Del(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::MGet(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::Migrate(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::Eval(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::EvalSha(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            Command::FCall(cmd) => cmd.keys().iter().map(String::as_str).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns the channels the command accesses, patterns for `PSUBSCRIBE`
+    pub(crate) fn channels(&self) -> Vec<&[u8]> {
+        match self {
+            Command::Subscribe(cmd) => cmd.channels().iter().map(|c| &c[..]).collect(),
+            Command::PSubscribe(cmd) => cmd.patterns().iter().map(|p| &p[..]).collect(),
+            Command::Publish(cmd) => vec![cmd.channel()],
+            _ => vec![],
+        }
+    }
+}
+
+/// The error reply to the command `name` that `Command::from_frame` refused.
+/// A missing or extra argument is reported the way redis does.
+pub(crate) fn parse_error_reply(name: &str, err: &crate::Error) -> String {
+    match err.downcast_ref::<ParseError>() {
+        Some(ParseError::EndOfStream | ParseError::Trailing) => {
+            format!("ERR wrong number of arguments for '{}' command", name)
+        }
+        _ => with_code(err.to_string()),
+    }
+}
+
+/// Replies start with an upper case error code, like `ERR` or `NOPERM`
+pub(crate) fn with_code(msg: String) -> String {
+    let code = msg.split(' ').next().unwrap_or_default();
+    if !code.is_empty() && code.bytes().all(|b| b.is_ascii_uppercase()) {
+        msg
+    } else {
+        format!("ERR {}", msg)
+    }
 }
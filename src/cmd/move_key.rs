@@ -0,0 +1,32 @@
+use crate::{Connection, Db, Frame, Parse};
+
+/// Move a key from the selected database to another one
+#[derive(Debug)]
+pub struct Move {
+    key: String,
+    db: u64,
+}
+
+impl Move {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Move> {
+        let key = parse.next_string()?;
+        let db = parse.next_int()?;
+
+        Ok(Move { key, db })
+    }
+
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match db.move_key(&self.key, self.db as usize) {
+            Ok(moved) => Frame::Integer(moved as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
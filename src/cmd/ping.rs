@@ -1,5 +1,5 @@
 use bytes::Bytes;
-use log::{debug, info};
+use log::debug;
 
 use crate::connection::Connection;
 use crate::frame::Frame;
@@ -33,7 +33,7 @@ impl Ping {
 
         debug!("response {}", response);
 
-        dst.write_frame(&response).await?;
+        dst.feed_frame(&response).await?;
 
         Ok(())
     }
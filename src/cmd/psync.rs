@@ -0,0 +1,31 @@
+use crate::Parse;
+
+/// Ask for the replication stream, from `offset` of the stream `replid` if
+/// the leader still has it, otherwise from a full copy of the keyspace
+#[derive(Debug)]
+pub struct Psync {
+    replid: String,
+
+    // `None` for `PSYNC ? -1`, a follower that never synced
+    offset: Option<u64>,
+}
+
+// Serving the stream takes over the connection, so `PSYNC` is applied by
+// the connection handler instead of through `Command::apply`
+
+impl Psync {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Psync> {
+        let replid = parse.next_string()?;
+        let offset = parse.next_string()?.parse().ok();
+
+        Ok(Psync { replid, offset })
+    }
+
+    pub(crate) fn replid(&self) -> &str {
+        &self.replid
+    }
+
+    pub(crate) fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+}
@@ -0,0 +1,32 @@
+use bytes::Bytes;
+
+use crate::{Connection, Db, Frame, Parse};
+
+/// Send a message to the subscribers of a channel, replies with how many
+/// received it
+#[derive(Debug)]
+pub struct Publish {
+    channel: Bytes,
+    message: Bytes,
+}
+
+impl Publish {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Publish> {
+        let channel = parse.next_bytes()?;
+        let message = parse.next_bytes()?;
+
+        Ok(Publish { channel, message })
+    }
+
+    pub(crate) fn channel(&self) -> &[u8] {
+        &self.channel
+    }
+
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let received = db.pubsub().publish(&self.channel, &self.message);
+
+        dst.feed_frame(&Frame::Integer(received as i64)).await?;
+
+        Ok(())
+    }
+}
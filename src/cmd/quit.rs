@@ -0,0 +1,14 @@
+use crate::Parse;
+
+/// Ask the server to close the connection once it replied
+#[derive(Debug, Default)]
+pub struct Quit;
+
+// Closing the connection is up to the connection handler, so `QUIT` is
+// applied there instead of through `Command::apply`
+
+impl Quit {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Quit> {
+        Ok(Quit)
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{Parse, ParseError};
+
+/// Options a follower tells its leader, the port it accepts clients on and
+/// how much of the stream it applied
+#[derive(Debug, Default)]
+pub struct ReplConf {
+    listening_port: Option<u16>,
+    ack: Option<u64>,
+}
+
+// The listening port belongs to the connection, so `REPLCONF` is applied by
+// the connection handler instead of through `Command::apply`
+
+impl ReplConf {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ReplConf> {
+        let mut replconf = ReplConf::default();
+
+        // Options this server has no use for, like `capa`, are accepted and
+        // ignored
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            let value = parse.next_string()?;
+
+            if option.eq_ignore_ascii_case("listening-port") {
+                let port = value.parse().map_err(|_| "ERR invalid listening port")?;
+                replconf.listening_port = Some(port);
+            } else if option.eq_ignore_ascii_case("ack") {
+                let offset = value
+                    .parse()
+                    .map_err(|_| "ERR invalid replication offset")?;
+                replconf.ack = Some(offset);
+            }
+        }
+
+        Ok(replconf)
+    }
+
+    pub(crate) fn listening_port(&self) -> Option<u16> {
+        self.listening_port
+    }
+
+    pub(crate) fn ack(&self) -> Option<u64> {
+        self.ack
+    }
+}
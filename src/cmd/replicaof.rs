@@ -0,0 +1,53 @@
+use crate::replication::Replication;
+use crate::script::Scripts;
+use crate::{Connection, Db, Frame, Parse};
+
+/// Follow another server, or stop following with `REPLICAOF NO ONE`
+#[derive(Debug)]
+pub struct ReplicaOf {
+    // `None` to stop following
+    leader: Option<(String, u16)>,
+}
+
+impl ReplicaOf {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ReplicaOf> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?;
+
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            return Ok(ReplicaOf { leader: None });
+        }
+
+        let port = port.parse().map_err(|_| "ERR Invalid master port")?;
+
+        Ok(ReplicaOf {
+            leader: Some((host, port)),
+        })
+    }
+
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        replication: &Replication,
+        scripts: &Scripts,
+        dst: &mut Connection,
+    ) -> crate::Result<()> {
+        let response = match self.leader {
+            Some((host, port)) => {
+                if replication.replicate(db, scripts, host, port) {
+                    Frame::Simple("OK".to_string())
+                } else {
+                    Frame::Simple("OK Already connected to specified master".to_string())
+                }
+            }
+            None => {
+                replication.promote(db);
+                Frame::Simple("OK".to_string())
+            }
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
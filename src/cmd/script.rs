@@ -0,0 +1,97 @@
+use bytes::Bytes;
+
+use crate::script::Scripts;
+use crate::{Connection, Frame, Parse, ParseError};
+
+/// Manage the cache of Lua scripts and stop the one running
+#[derive(Debug)]
+pub struct Script {
+    subcommand: Subcommand,
+}
+
+#[derive(Debug)]
+enum Subcommand {
+    Load(String),
+    Exists(Vec<String>),
+    Flush,
+    Kill,
+}
+
+impl Script {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Script> {
+        let name = parse.next_string()?.to_lowercase();
+
+        let subcommand = match name.as_str() {
+            "load" => Subcommand::Load(parse.next_string()?),
+            "exists" => {
+                let mut shas = vec![parse.next_string()?];
+                loop {
+                    match parse.next_string() {
+                        Ok(sha) => shas.push(sha),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Subcommand::Exists(shas)
+            }
+            "flush" => {
+                // Scripts are always dropped at once, `ASYNC` changes nothing
+                match parse.next_string() {
+                    Ok(mode) if mode.eq_ignore_ascii_case("async") => {}
+                    Ok(mode) if mode.eq_ignore_ascii_case("sync") => {}
+                    Ok(_) => return Err("ERR SCRIPT FLUSH only support SYNC|ASYNC option".into()),
+                    Err(ParseError::EndOfStream) => {}
+                    Err(e) => return Err(e.into()),
+                }
+                Subcommand::Flush
+            }
+            "kill" => Subcommand::Kill,
+            _ => {
+                return Err(format!("ERR unknown subcommand '{}'. Try SCRIPT HELP.", name).into());
+            }
+        };
+
+        Ok(Script { subcommand })
+    }
+
+    /// Name of the subcommand as used in ACL rules, like `script|load`
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self.subcommand {
+            Subcommand::Load(_) => "script|load",
+            Subcommand::Exists(_) => "script|exists",
+            Subcommand::Flush => "script|flush",
+            Subcommand::Kill => "script|kill",
+        }
+    }
+
+    /// `SCRIPT KILL` is the one command answered while a script is busy
+    pub(crate) fn is_kill(&self) -> bool {
+        matches!(self.subcommand, Subcommand::Kill)
+    }
+
+    pub(crate) async fn apply(self, scripts: &Scripts, dst: &mut Connection) -> crate::Result<()> {
+        let response = match self.subcommand {
+            Subcommand::Load(body) => match scripts.load(&body) {
+                Ok(sha) => Frame::Bulk(Bytes::from(sha)),
+                Err(err) => Frame::Error(err.to_string()),
+            },
+            Subcommand::Exists(shas) => Frame::Array(
+                shas.iter()
+                    .map(|sha| Frame::Integer(scripts.exists(sha) as i64))
+                    .collect(),
+            ),
+            Subcommand::Flush => {
+                scripts.flush();
+                Frame::Simple("OK".to_string())
+            }
+            Subcommand::Kill => match scripts.kill() {
+                Ok(()) => Frame::Simple("OK".to_string()),
+                Err(err) => Frame::Error(err.to_string()),
+            },
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
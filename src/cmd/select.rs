@@ -0,0 +1,43 @@
+use bytes::Bytes;
+
+use crate::{Connection, Db, Frame, Parse};
+
+/// Change the logical database of the current connection
+#[derive(Debug)]
+pub struct Select {
+    index: u64,
+}
+
+impl Select {
+    pub fn new(index: u64) -> Select {
+        Select { index }
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Select> {
+        let index = parse.next_int()?;
+
+        Ok(Select { index })
+    }
+
+    pub(crate) async fn apply(self, db: &mut Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match db.select(self.index as usize) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("select".as_bytes()));
+        frame.push_bulk(Bytes::from(self.index.to_string().into_bytes()));
+        frame
+    }
+}
@@ -1,7 +1,7 @@
 use bytes::Bytes;
 
+use crate::Parse;
 use crate::{Connection, Db, Frame};
-use crate::{Parse, ParseError};
 
 #[derive(Debug)]
 pub struct Set {
@@ -22,14 +22,7 @@ impl Set {
         &self.key
     }
 
-    /// Get the value
-    pub fn value(&self) -> &Bytes {
-        &self.value
-    }
-
     pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Set> {
-        use ParseError::EndOfStream;
-
         let key = parse.next_string()?;
 
         let value = parse.next_bytes()?;
@@ -41,7 +34,7 @@ impl Set {
         db.set(self.key, self.value);
 
         let response = Frame::Simple("OK".to_string());
-        dst.write_frame(&response).await?;
+        dst.feed_frame(&response).await?;
 
         Ok(())
     }
@@ -0,0 +1,101 @@
+use bytes::Bytes;
+
+use crate::{Parse, ParseError};
+
+/// Receive the messages published to channels
+#[derive(Debug)]
+pub struct Subscribe {
+    channels: Vec<Bytes>,
+}
+
+/// Stop receiving the messages of channels, of every one if none is given
+#[derive(Debug)]
+pub struct Unsubscribe {
+    channels: Vec<Bytes>,
+}
+
+/// Receive the messages published to the channels matching glob patterns
+#[derive(Debug)]
+pub struct PSubscribe {
+    patterns: Vec<Bytes>,
+}
+
+/// Stop receiving the messages of patterns, of every one if none is given
+#[derive(Debug)]
+pub struct PUnsubscribe {
+    patterns: Vec<Bytes>,
+}
+
+// The subscriptions live in the connection handler, so these commands are
+// applied there instead of through `Command::apply`
+
+impl Subscribe {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Subscribe> {
+        let mut channels = vec![parse.next_bytes()?];
+        channels.extend(parse_rest(parse)?);
+
+        Ok(Subscribe { channels })
+    }
+
+    pub(crate) fn channels(&self) -> &[Bytes] {
+        &self.channels
+    }
+
+    pub(crate) fn into_channels(self) -> Vec<Bytes> {
+        self.channels
+    }
+}
+
+impl Unsubscribe {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Unsubscribe> {
+        Ok(Unsubscribe {
+            channels: parse_rest(parse)?,
+        })
+    }
+
+    pub(crate) fn into_channels(self) -> Vec<Bytes> {
+        self.channels
+    }
+}
+
+impl PSubscribe {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<PSubscribe> {
+        let mut patterns = vec![parse.next_bytes()?];
+        patterns.extend(parse_rest(parse)?);
+
+        Ok(PSubscribe { patterns })
+    }
+
+    pub(crate) fn patterns(&self) -> &[Bytes] {
+        &self.patterns
+    }
+
+    pub(crate) fn into_patterns(self) -> Vec<Bytes> {
+        self.patterns
+    }
+}
+
+impl PUnsubscribe {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<PUnsubscribe> {
+        Ok(PUnsubscribe {
+            patterns: parse_rest(parse)?,
+        })
+    }
+
+    pub(crate) fn into_patterns(self) -> Vec<Bytes> {
+        self.patterns
+    }
+}
+
+/// The channels or patterns until the end of the command, maybe none
+fn parse_rest(parse: &mut Parse) -> crate::Result<Vec<Bytes>> {
+    let mut names = vec![];
+
+    loop {
+        match parse.next_bytes() {
+            Ok(name) => names.push(name),
+            Err(ParseError::EndOfStream) => return Ok(names),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use crate::{Connection, Db, Frame, Parse};
+
+/// Swap two logical databases
+#[derive(Debug)]
+pub struct SwapDb {
+    first: u64,
+    second: u64,
+}
+
+impl SwapDb {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SwapDb> {
+        let first = parse.next_int()?;
+        let second = parse.next_int()?;
+
+        Ok(SwapDb { first, second })
+    }
+
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = match db.swap(self.first as usize, self.second as usize) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
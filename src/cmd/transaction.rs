@@ -0,0 +1,56 @@
+use bytes::Bytes;
+
+use crate::{Frame, Parse};
+
+/// Start a transaction, the following commands are queued until `EXEC`
+#[derive(Debug, Default)]
+pub struct Multi;
+
+/// Run the commands queued since `MULTI` atomically
+#[derive(Debug, Default)]
+pub struct Exec;
+
+/// Drop the commands queued since `MULTI`
+#[derive(Debug, Default)]
+pub struct Discard;
+
+// The queue lives in the connection handler, so these commands are applied
+// there instead of through `Command::apply`
+
+impl Multi {
+    pub fn new() -> Multi {
+        Multi
+    }
+
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Multi> {
+        Ok(Multi)
+    }
+
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("multi".as_bytes()));
+        frame
+    }
+}
+
+impl Exec {
+    pub fn new() -> Exec {
+        Exec
+    }
+
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Exec> {
+        Ok(Exec)
+    }
+
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("exec".as_bytes()));
+        frame
+    }
+}
+
+impl Discard {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Discard> {
+        Ok(Discard)
+    }
+}
@@ -0,0 +1,33 @@
+use crate::{Connection, Frame};
+
+/// A command tiny-redis does not support
+#[derive(Debug)]
+pub struct Unknown {
+    command_name: String,
+}
+
+impl Unknown {
+    pub(crate) fn new(key: impl ToString) -> Unknown {
+        Unknown {
+            command_name: key.to_string(),
+        }
+    }
+
+    pub(crate) fn get_name(&self) -> &str {
+        &self.command_name
+    }
+
+    /// The error the command is refused with
+    pub(crate) fn error(&self) -> String {
+        format!("ERR unknown command '{}'", self.command_name)
+    }
+
+    /// Reply with an error, the connection stays usable
+    pub(crate) async fn apply(self, dst: &mut Connection) -> crate::Result<()> {
+        let response = Frame::Error(self.error());
+
+        dst.feed_frame(&response).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use bytes::{Buf, BytesMut};
+use log::{debug, error};
+use std::io::Cursor;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::frame::{Error, Frame, Limits};
+
+/// Codec turning a byte stream into `Frame`s and back, usable with
+/// `tokio_util::codec::Framed`.
+///
+/// Decoding accepts both RESP frames and inline commands, and enforces
+/// `Limits`. Encoding downgrades RESP3 only types unless the codec has been
+/// switched to RESP3.
+#[derive(Debug, Clone)]
+pub struct FrameCodec {
+    // RESP version used to encode frames
+    protocol: u8,
+
+    // bounds on what the peer may send
+    limits: Limits,
+}
+
+impl FrameCodec {
+    /// Create a RESP2 codec with the default limits
+    pub fn new() -> FrameCodec {
+        FrameCodec {
+            protocol: 2,
+            limits: Limits::default(),
+        }
+    }
+
+    /// RESP version used to encode frames, either 2 or 3
+    pub fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Switch the RESP version used to encode frames
+    pub fn set_protocol(&mut self, protocol: u8) {
+        self.protocol = protocol;
+    }
+
+    /// Replace the decoding limits
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Decoding limits
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Parse one newline terminated inline command from `src`
+    fn decode_inline(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        let end = match src.iter().position(|&b| b == b'\n') {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+
+        let line = src.split_to(end + 1);
+
+        let frame = Frame::from_inline(&line)?;
+
+        debug!("inline frame is {:?}", frame);
+
+        Ok(Some(frame))
+    }
+
+    // Only an incomplete frame is left in `src`, refuse to buffer more of it
+    // than allowed
+    fn incomplete(&self, src: &BytesMut) -> Result<Option<Frame>, Error> {
+        if src.len() >= self.limits.query_buffer_limit {
+            return Err("protocol error; query buffer limit exceeded".into());
+        }
+
+        Ok(None)
+    }
+}
+
+impl Default for FrameCodec {
+    fn default() -> FrameCodec {
+        FrameCodec::new()
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        // Anything not starting with a RESP type byte is an inline command,
+        // as typed in a telnet session
+        while let Some(&first) = src.first() {
+            if Frame::is_type_byte(first) {
+                break;
+            }
+
+            match self.decode_inline(src)? {
+                // blank lines are skipped
+                Some(Frame::Array(args)) if args.is_empty() => continue,
+                Some(frame) => return Ok(Some(frame)),
+                None => return self.incomplete(src),
+            }
+        }
+
+        /* create a slice for buffer */
+        let mut buf = Cursor::new(&src[..]);
+
+        match Frame::check(&mut buf, &self.limits) {
+            Ok(_) => {
+                debug!("frame check ok");
+
+                // The check function has advanced the cursor to the end of the frame.
+                let len = buf.position() as usize;
+
+                buf.set_position(0);
+
+                let frame = Frame::parse(&mut buf, &self.limits)?;
+
+                debug!("frame is {:?}, len: {}", frame, len);
+
+                /* discard the parsed frame from the buffer */
+                src.advance(len);
+
+                Ok(Some(frame))
+            }
+            Err(Error::Incomplete) => self.incomplete(src),
+            Err(e) => {
+                error!("frame check error");
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Encoder<&Frame> for FrameCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: &Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        frame.encode_as(dst, self.protocol);
+        Ok(())
+    }
+}
+
+impl Encoder<Frame> for FrameCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        frame.encode_as(dst, self.protocol);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_split_across_reads_are_decoded_once_complete() {
+        let mut codec = FrameCodec::new();
+        let mut src = BytesMut::from(&b"*2\r\n$3\r\nGET\r\n$1"[..]);
+
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert_eq!(src.len(), 15);
+
+        src.extend_from_slice(b"\r\nk\r\n:1\r\n");
+        assert!(matches!(codec.decode(&mut src).unwrap(), Some(Frame::Array(a)) if a.len() == 2));
+        assert!(matches!(
+            codec.decode(&mut src).unwrap(),
+            Some(Frame::Integer(1))
+        ));
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn encoding_follows_the_protocol() {
+        let mut codec = FrameCodec::new();
+        let mut dst = BytesMut::new();
+
+        codec.encode(Frame::Null, &mut dst).unwrap();
+        codec.set_protocol(3);
+        codec.encode(&Frame::Null, &mut dst).unwrap();
+
+        assert_eq!(&dst[..], b"$-1\r\n_\r\n");
+    }
+
+    #[test]
+    fn incomplete_frames_are_bounded() {
+        let mut codec = FrameCodec::new();
+        codec.set_limits(Limits {
+            query_buffer_limit: 8,
+            ..Limits::default()
+        });
+
+        let mut src = BytesMut::from(&b"$4\r\nab"[..]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+
+        src.extend_from_slice(b"cd");
+        assert!(codec.decode(&mut src).is_err());
+
+        let mut src = BytesMut::from(&b"PING PING"[..]);
+        assert!(codec.decode(&mut src).is_err());
+    }
+}
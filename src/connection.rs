@@ -1,34 +1,81 @@
-use log::{debug, error, info};
-use std::io::{self, Cursor};
+use log::debug;
+use std::{fmt, io};
 
-use bytes::BytesMut;
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
-use tokio::net::TcpStream;
+use bytes::{BufMut, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio_util::codec::Decoder;
 
-use crate::frame::{self, Frame};
+use crate::codec::FrameCodec;
+use crate::frame::{self, Frame, Limits};
 
+/// A byte stream frames can be exchanged over, such as a `TcpStream` or a
+/// TLS stream
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug> AsyncStream for T {}
+
+/// Frames read from and written to a stream. By default the stream type is
+/// erased, so plain and TLS connections are handled alike.
 #[derive(Debug)]
-pub struct Connection {
+pub struct Connection<S = Box<dyn AsyncStream>> {
     // tokio buffer provide write buffer, can speed up writing
-    stream: BufWriter<TcpStream>,
+    stream: BufWriter<S>,
 
     // for reading frame
     buffer: BytesMut,
+
+    // decodes `buffer` and encodes replies, it knows the RESP version spoken
+    // on this connection and the bounds on what the peer may send
+    codec: FrameCodec,
+
+    // scratch space a frame is encoded into before it is written
+    out: BytesMut,
 }
 
 impl Connection {
-    pub fn new(socket: TcpStream) -> Connection {
+    /// Create a connection over `stream`, erasing its type
+    pub fn boxed(stream: impl AsyncStream + 'static) -> Connection {
+        let stream: Box<dyn AsyncStream> = Box::new(stream);
+        Connection::new(stream)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(stream: S) -> Connection<S> {
         Connection {
-            stream: BufWriter::new(socket),
+            stream: BufWriter::new(stream),
             // default 4KB
             buffer: BytesMut::with_capacity(4 * 1024),
+            codec: FrameCodec::new(),
+            out: BytesMut::with_capacity(4 * 1024),
         }
     }
 
+    /// RESP version used to encode frames, either 2 or 3
+    pub fn protocol(&self) -> u8 {
+        self.codec.protocol()
+    }
+
+    /// Switch the RESP version used to encode frames, `HELLO` does this
+    pub fn set_protocol(&mut self, protocol: u8) {
+        self.codec.set_protocol(protocol);
+    }
+
+    /// Replace the decoding limits of this connection
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.codec.set_limits(limits);
+    }
+
+    /// Parse a frame that is already buffered, without reading the socket.
+    /// Returns `None` if the buffer holds no complete frame.
+    pub fn read_buffered_frame(&mut self) -> crate::Result<Option<Frame>> {
+        Ok(self.codec.decode(&mut self.buffer)?)
+    }
+
     /// Read from socket and fullfill a frame
     pub async fn read_frame(&mut self) -> crate::Result<Option<Frame>> {
         loop {
-            if let Some(frame) = self.parse_frame()? {
+            if let Some(frame) = self.codec.decode(&mut self.buffer)? {
                 return Ok(Some(frame));
             }
 
@@ -42,97 +89,50 @@ impl Connection {
         }
     }
 
-    fn parse_frame(&mut self) -> crate::Result<Option<Frame>> {
-        /* create a slice for buffer */
-        let mut buf = Cursor::new(&self.buffer[..]);
-
-        match Frame::check(&mut buf) {
-            Ok(_) => {
-                debug!("frame check ok");
-
-                // The check function has advanced the cursor to the end of the frame.
-                let len = buf.position() as usize;
-
-                buf.set_position(0);
-
-                let frame = Frame::parse(&mut buf)?;
-
-                debug!("frame is {:?}, len: {}", frame, len);
-
-                /* discard the parsed frame from the buffer */
-                let _ = self.buffer.split_to(len);
+    /// Write a frame to the stream and flush it, see `Frame::encode_as` for
+    /// how RESP3 only types are sent to a RESP2 connection
+    pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.feed_frame(frame).await?;
 
-                Ok(Some(frame))
-            }
-            Err(frame::Error::Incomplete) => Ok(None),
-            Err(e) => {
-                error!("frame check error");
-                Err(e.into())
-            }
-        }
+        self.flush().await
     }
 
-    pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+    /// Write a frame to the stream without flushing it, so several frames can
+    /// go out in one write. Only the write buffer is held in memory, once it
+    /// is full it is written to the socket, waiting for the peer to read.
+    pub async fn feed_frame(&mut self, frame: &Frame) -> io::Result<()> {
         debug!("write frame {:?}", frame);
 
-        match frame {
-            Frame::Array(val) => {
-                // Encode the frame type prefix, for array it's `*`
-                self.stream.write_u8(b'*').await?;
-
-                // Encode the length of the array
-                self.write_decimal(val.len() as u64).await?;
-
-                for entry in val {
-                    self.write_value(entry).await?;
-                }
-            }
-            _ => self.write_value(frame).await?,
-        }
+        self.out.clear();
+        frame.encode_as(&mut self.out, self.codec.protocol());
 
-        // Flush the stream ensure the encoded frame is written to the socket
-        self.stream.flush().await
+        self.stream.write_all(&self.out).await
     }
 
-    async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
-        // Convert the decimal to a string
-        let s = val.to_string();
+    /// Write the header of an array whose `len` entries are fed one by one
+    /// afterwards, used to stream the replies of a transaction
+    pub(crate) async fn feed_array_len(&mut self, len: usize) -> io::Result<()> {
+        self.out.clear();
+        self.out.put_u8(b'*');
+        frame::put_decimal(&mut self.out, len);
 
-        self.stream.write_all(s.as_bytes()).await?;
-        self.stream.write_all(b"\r\n").await?;
+        self.stream.write_all(&self.out).await
+    }
 
-        Ok(())
+    /// Write bytes that are already encoded, used to relay the replication
+    /// stream as it was recorded
+    pub(crate) async fn feed_raw(&mut self, src: &[u8]) -> io::Result<()> {
+        self.stream.write_all(src).await
     }
 
-    // Write a frame literal to the stream
-    async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
-        match frame {
-            Frame::Simple(val) => {
-                self.stream.write_u8(b'+').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(b"\r\n").await?;
-            }
-            Frame::Error(val) => {
-                self.stream.write_u8(b'-').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(b"\r\n").await?;
-            }
-            Frame::Integer(val) => {
-                self.stream.write_u8(b':').await?;
-                self.write_decimal(*val).await?;
-            }
-            Frame::Null => {
-                self.stream.write_all(b"$-1\r\n").await?;
-            }
-            Frame::Bulk(val) => {
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(val.len() as u64).await?;
-                self.stream.write_all(val).await?;
-                self.stream.write_all(b"\r\n").await?;
-            }
-            _ => unimplemented!(),
-        }
+    /// Flush the stream ensure every fed frame is written to the socket
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush().await
+    }
 
-        Ok(())
+    /// Give back the stream, with the bytes read from it but not decoded
+    /// yet. Frames fed but not flushed are lost.
+    pub fn into_inner(self) -> (S, BytesMut) {
+        (self.stream.into_inner(), self.buffer)
     }
 }
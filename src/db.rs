@@ -2,25 +2,50 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 use bytes::Bytes;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+use crate::pubsub::{KeyspaceEvents, PubSub};
+use crate::replication::{Backlog, Snapshot};
+
+/// Number of logical databases when none is configured
+pub const DEFAULT_DATABASES: usize = 16;
 
 #[derive(Debug)]
 pub(crate) struct DbDropGuard {
     db: Db,
 }
 
+/// Handle to the shared keyspace. Every handle carries the index of the
+/// logical database it currently operates on, so each connection can select
+/// its own database while sharing the same storage.
 #[derive(Debug, Clone)]
 pub(crate) struct Db {
     shared: Arc<Shared>,
+
+    /// Index of the selected logical database
+    index: usize,
 }
 
 #[derive(Debug)]
 struct Shared {
     state: Mutex<State>,
+
+    /// Commands run holding it shared, a transaction holds it exclusively so
+    /// no other command runs in between its commands
+    access: Arc<RwLock<()>>,
+
+    /// Channels subscribed to, keyspace events are published there
+    pubsub: PubSub,
 }
 
 #[derive(Debug)]
 struct State {
-    entries: HashMap<String, Entry>,
+    /// One map per logical database
+    databases: Vec<HashMap<String, Entry>>,
+
+    /// Replication stream, kept under the same lock as the data so writes
+    /// are appended in the order they are applied
+    backlog: Backlog,
 }
 
 #[derive(Debug)]
@@ -29,8 +54,14 @@ struct Entry {
 }
 
 impl DbDropGuard {
-    pub(crate) fn new() -> DbDropGuard {
-        DbDropGuard { db: Db::new() }
+    pub(crate) fn new(
+        databases: usize,
+        backlog_size: usize,
+        events: KeyspaceEvents,
+    ) -> DbDropGuard {
+        DbDropGuard {
+            db: Db::new(databases, backlog_size, events),
+        }
     }
 
     /// Get the shared database, Internally, this is an Arc so a clone only
@@ -41,15 +72,40 @@ impl DbDropGuard {
 }
 
 impl Db {
-    /// Create a new empty Db instance
-    pub(crate) fn new() -> Db {
+    /// Create a new instance with `databases` empty logical databases,
+    /// database 0 is selected. The last `backlog_size` bytes of the
+    /// replication stream are kept. The keyspace events of the `events`
+    /// classes are published.
+    pub(crate) fn new(databases: usize, backlog_size: usize, events: KeyspaceEvents) -> Db {
         let shared = Arc::new(Shared {
             state: Mutex::new(State {
-                entries: HashMap::new(),
+                databases: (0..databases.max(1)).map(|_| HashMap::new()).collect(),
+                backlog: Backlog::new(backlog_size),
             }),
+            access: Arc::new(RwLock::new(())),
+            pubsub: PubSub::new(events),
         });
 
-        Db { shared }
+        Db { shared, index: 0 }
+    }
+
+    /// Wait for any exclusive access to end and hold shared access, for
+    /// running a single command
+    pub(crate) async fn shared_access(&self) -> OwnedRwLockReadGuard<()> {
+        self.shared.access.clone().read_owned().await
+    }
+
+    /// Wait for every other command to end and hold exclusive access, for
+    /// running several commands atomically
+    pub(crate) async fn exclusive_access(&self) -> OwnedRwLockWriteGuard<()> {
+        self.shared.access.clone().write_owned().await
+    }
+
+    /// Select the logical database used by this handle
+    pub(crate) fn select(&mut self, index: usize) -> crate::Result<()> {
+        self.check_index(index)?;
+        self.index = index;
+        Ok(())
     }
 
     /// Get the value associated with a key
@@ -58,13 +114,200 @@ impl Db {
 
         // get the clone of data, because the data is `Bytes`,
         // `Bytes`` itself is fat pointer, so a clone is a shallow clone
-        state.entries.get(key).map(|entry| entry.data.clone())
+        state.databases[self.index]
+            .get(key)
+            .map(|entry| entry.data.clone())
     }
 
     pub(crate) fn set(&self, key: String, entry: Bytes) {
         let mut state = self.shared.state.lock().unwrap();
 
-        // why the key need deep clone?
-        let _ = state.entries.insert(key.clone(), Entry { data: entry });
+        state
+            .backlog
+            .append(Some(self.index), &[b"set", key.as_bytes(), &entry]);
+
+        let _ = state.databases[self.index].insert(key.clone(), Entry { data: entry });
+
+        // Subscribers are sent events once the keyspace is unlocked
+        drop(state);
+
+        self.shared
+            .pubsub
+            .notify(KeyspaceEvents::STRING, "set", self.index, &key);
+    }
+
+    /// Whether `key` exists in the selected database
+    pub(crate) fn exists(&self, key: &str) -> bool {
+        let state = self.shared.state.lock().unwrap();
+
+        state.databases[self.index].contains_key(key)
+    }
+
+    /// Remove `key` from the selected database, returns whether it existed
+    pub(crate) fn delete(&self, key: &str) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if state.databases[self.index].remove(key).is_none() {
+            return false;
+        }
+
+        state
+            .backlog
+            .append(Some(self.index), &[b"del", key.as_bytes()]);
+
+        drop(state);
+
+        self.shared
+            .pubsub
+            .notify(KeyspaceEvents::GENERIC, "del", self.index, key);
+
+        true
+    }
+
+    /// Up to `count` keys of the selected database `filter` accepts
+    pub(crate) fn keys(&self, filter: impl Fn(&str) -> bool, count: usize) -> Vec<String> {
+        let state = self.shared.state.lock().unwrap();
+
+        state.databases[self.index]
+            .keys()
+            .filter(|key| filter(key))
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
+    /// Number of keys in the selected database
+    pub(crate) fn size(&self) -> usize {
+        let state = self.shared.state.lock().unwrap();
+
+        state.databases[self.index].len()
+    }
+
+    /// Move `key` from the selected database to database `dst`. Returns
+    /// `false` if the key does not exist or already exists in `dst`.
+    pub(crate) fn move_key(&self, key: &str, dst: usize) -> crate::Result<bool> {
+        self.check_index(dst)?;
+
+        if dst == self.index {
+            return Err("ERR source and destination objects are the same".into());
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        if state.databases[dst].contains_key(key) {
+            return Ok(false);
+        }
+
+        match state.databases[self.index].remove_entry(key) {
+            Some((key, entry)) => {
+                let dst_arg = dst.to_string();
+                state.backlog.append(
+                    Some(self.index),
+                    &[b"move", key.as_bytes(), dst_arg.as_bytes()],
+                );
+
+                state.databases[dst].insert(key.clone(), entry);
+                drop(state);
+
+                let pubsub = &self.shared.pubsub;
+                pubsub.notify(KeyspaceEvents::GENERIC, "move_from", self.index, &key);
+                pubsub.notify(KeyspaceEvents::GENERIC, "move_to", dst, &key);
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Swap the contents of two databases, visible to every connection at once
+    pub(crate) fn swap(&self, first: usize, second: usize) -> crate::Result<()> {
+        self.check_index(first)?;
+        self.check_index(second)?;
+
+        let mut state = self.shared.state.lock().unwrap();
+        state.databases.swap(first, second);
+
+        let (first, second) = (first.to_string(), second.to_string());
+        state
+            .backlog
+            .append(None, &[b"swapdb", first.as_bytes(), second.as_bytes()]);
+
+        Ok(())
+    }
+
+    /// Remove every key of the selected database. With `lazy` the removed
+    /// entries are freed on a blocking thread instead of the caller's.
+    pub(crate) fn flush(&self, lazy: bool) {
+        let entries = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.backlog.append(Some(self.index), &[b"flushdb"]);
+            std::mem::take(&mut state.databases[self.index])
+        };
+
+        release(vec![entries], lazy);
+    }
+
+    /// Remove every key of every database
+    pub(crate) fn flush_all(&self, lazy: bool) {
+        let entries = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.backlog.append(None, &[b"flushall"]);
+            state.databases.iter_mut().map(std::mem::take).collect()
+        };
+
+        release(entries, lazy);
+    }
+
+    /// Channels clients subscribed to
+    pub(crate) fn pubsub(&self) -> &PubSub {
+        &self.shared.pubsub
+    }
+
+    /// Run `f` on the replication backlog
+    pub(crate) fn backlog<T>(&self, f: impl FnOnce(&mut Backlog) -> T) -> T {
+        let mut state = self.shared.state.lock().unwrap();
+        f(&mut state.backlog)
+    }
+
+    /// Copy every database along with the point of the replication stream
+    /// the copy matches, for a follower doing a full resync
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        let state = self.shared.state.lock().unwrap();
+
+        let databases = state
+            .databases
+            .iter()
+            .map(|db| {
+                db.iter()
+                    .map(|(key, entry)| (key.clone(), entry.data.clone()))
+                    .collect()
+            })
+            .collect();
+
+        Snapshot {
+            replid: state.backlog.replid().to_string(),
+            offset: state.backlog.offset(),
+            selected: state.backlog.selected(),
+            databases,
+            libraries: Vec::new(),
+        }
+    }
+
+    fn check_index(&self, index: usize) -> crate::Result<()> {
+        let state = self.shared.state.lock().unwrap();
+
+        if index < state.databases.len() {
+            Ok(())
+        } else {
+            Err("ERR DB index is out of range".into())
+        }
+    }
+}
+
+/// Drop flushed keyspaces, a big map can take a while to free so the lazy
+/// variant hands it off to tokio's blocking pool
+fn release(entries: Vec<HashMap<String, Entry>>, lazy: bool) {
+    if lazy && entries.iter().any(|db| !db.is_empty()) {
+        tokio::task::spawn_blocking(move || drop(entries));
     }
 }
@@ -1,20 +1,69 @@
 use std::fmt;
+use std::fmt::Write as _;
 use std::io::Cursor;
 use std::io::prelude::*;
 use std::num::TryFromIntError;
+use std::str;
 use std::string::FromUtf8Error;
 
 use bytes::Buf;
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 
 #[derive(Clone, Debug)]
 pub enum Frame {
     Simple(String),
     Error(String),
-    Integer(u64),
+    Integer(i64),
     Bulk(Bytes),
     Null,
     Array(Vec<Frame>),
+
+    /* RESP3 only types, see `Frame::encode_as` for how they are sent
+     * to a RESP2 connection */
+    Map(Vec<(Frame, Frame)>),
+    Set(Vec<Frame>),
+    Double(f64),
+    Boolean(bool),
+    /// Arbitrary precision integer, kept as its decimal representation
+    BigNumber(String),
+    /// Bulk string with a three bytes format hint, like `txt` or `mkd`
+    Verbatim {
+        format: String,
+        data: Bytes,
+    },
+    /// Out of band information attached to the reply in `data`
+    Attribute {
+        attrs: Vec<(Frame, Frame)>,
+        data: Box<Frame>,
+    },
+    /// Out of band message pushed by the server
+    Push(Vec<Frame>),
+}
+
+/// Bounds applied while decoding frames, so a single peer cannot exhaust
+/// memory or overflow the stack
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Longest accepted bulk string, `proto-max-bulk-len` in redis
+    pub max_bulk_len: u64,
+    /// Most elements accepted in an aggregate frame
+    pub max_multibulk_len: u64,
+    /// Deepest accepted nesting of aggregate frames
+    pub max_depth: usize,
+    /// Most unparsed bytes buffered per connection,
+    /// `client-query-buffer-limit` in redis
+    pub query_buffer_limit: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_multibulk_len: i32::MAX as u64,
+            max_depth: 32,
+            query_buffer_limit: 1024 * 1024 * 1024,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -37,6 +86,12 @@ impl From<&str> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(src: std::io::Error) -> Self {
+        Error::Other(src.into())
+    }
+}
+
 impl From<FromUtf8Error> for Error {
     fn from(_src: FromUtf8Error) -> Error {
         "protocol error; invalid frame format".into()
@@ -81,8 +136,217 @@ impl Frame {
         format!("unexpected frame: {}", self).into()
     }
 
-    /// Check if an entire message can be decoded from `src`
-    pub(crate) fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+    /// Encode the frame as RESP2 into `dst`, see `encode_as`
+    pub fn encode(&self, dst: &mut BytesMut) {
+        self.encode_as(dst, 2);
+    }
+
+    /// Encode the frame for a peer speaking RESP `protocol` into `dst`.
+    /// RESP3 only types are downgraded for a RESP2 peer: maps become flat
+    /// arrays of key value pairs, sets and pushes become arrays, attributes
+    /// are dropped and the other scalars are sent as the closest RESP2 type.
+    pub fn encode_as(&self, dst: &mut BytesMut, protocol: u8) {
+        let resp3 = protocol >= 3;
+
+        match self {
+            Frame::Simple(val) => {
+                dst.put_u8(b'+');
+                dst.put_slice(val.as_bytes());
+                dst.put_slice(b"\r\n");
+            }
+            Frame::Error(val) => {
+                dst.put_u8(b'-');
+                dst.put_slice(val.as_bytes());
+                dst.put_slice(b"\r\n");
+            }
+            Frame::Integer(val) => {
+                dst.put_u8(b':');
+                put_decimal(dst, *val);
+            }
+            Frame::Null if resp3 => dst.put_slice(b"_\r\n"),
+            Frame::Null => dst.put_slice(b"$-1\r\n"),
+            Frame::Bulk(val) => put_bulk(dst, b'$', val),
+            Frame::Array(val) => put_list(dst, b'*', val, protocol),
+            Frame::Set(val) => put_list(dst, if resp3 { b'~' } else { b'*' }, val, protocol),
+            Frame::Push(val) => put_list(dst, if resp3 { b'>' } else { b'*' }, val, protocol),
+            Frame::Map(pairs) => {
+                if resp3 {
+                    dst.put_u8(b'%');
+                    put_decimal(dst, pairs.len());
+                } else {
+                    dst.put_u8(b'*');
+                    put_decimal(dst, pairs.len() * 2);
+                }
+
+                put_pairs(dst, pairs, protocol);
+            }
+            Frame::Attribute { attrs, data } => {
+                // RESP2 has no way to carry attributes, only send the data
+                if resp3 {
+                    dst.put_u8(b'|');
+                    put_decimal(dst, attrs.len());
+                    put_pairs(dst, attrs, protocol);
+                }
+
+                data.encode_as(dst, protocol);
+            }
+            Frame::Double(val) => {
+                if resp3 {
+                    dst.put_u8(b',');
+                    let _ = write_double(dst, *val);
+                    dst.put_slice(b"\r\n");
+                } else {
+                    // Measure the number first, so the bulk length can be
+                    // written without formatting into a temporary string
+                    let mut len = Length(0);
+                    let _ = write_double(&mut len, *val);
+
+                    dst.put_u8(b'$');
+                    put_decimal(dst, len.0);
+                    let _ = write_double(dst, *val);
+                    dst.put_slice(b"\r\n");
+                }
+            }
+            Frame::Boolean(val) => {
+                if resp3 {
+                    dst.put_slice(if *val { b"#t\r\n" } else { b"#f\r\n" });
+                } else {
+                    dst.put_u8(b':');
+                    put_decimal(dst, *val as u8);
+                }
+            }
+            Frame::BigNumber(val) => {
+                if resp3 {
+                    dst.put_u8(b'(');
+                    dst.put_slice(val.as_bytes());
+                    dst.put_slice(b"\r\n");
+                } else {
+                    put_bulk(dst, b'$', val.as_bytes());
+                }
+            }
+            Frame::Verbatim { format, data } => {
+                if resp3 {
+                    dst.put_u8(b'=');
+                    put_decimal(dst, data.len() + 4);
+                    dst.put_slice(format.as_bytes());
+                    dst.put_u8(b':');
+                    dst.put_slice(data);
+                    dst.put_slice(b"\r\n");
+                } else {
+                    put_bulk(dst, b'$', data);
+                }
+            }
+        }
+    }
+
+    /// Whether `byte` starts a RESP frame, anything else is read as an inline
+    /// command
+    pub(crate) fn is_type_byte(byte: u8) -> bool {
+        matches!(
+            byte,
+            b'+' | b'-'
+                | b':'
+                | b'$'
+                | b'*'
+                | b'%'
+                | b'~'
+                | b'>'
+                | b'|'
+                | b'='
+                | b','
+                | b'#'
+                | b'('
+                | b'_'
+        )
+    }
+
+    /// Build an array of bulk strings from an inline command such as
+    /// `SET key "hello world"`, the same way redis splits its arguments.
+    /// Double quoted arguments support `\n`, `\r`, `\t`, `\b`, `\a`, `\\`,
+    /// `\"` and `\xHH` escapes, single quoted ones only `\'`. The trailing
+    /// line ending is ignored.
+    pub fn from_inline(line: &[u8]) -> Result<Frame, Error> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        let mut args = vec![];
+        let mut pos = 0;
+
+        loop {
+            while pos < line.len() && line[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+
+            if pos == line.len() {
+                return Ok(Frame::Array(args));
+            }
+
+            let mut arg = Vec::new();
+            let mut quote = None;
+
+            while pos < line.len() {
+                let c = line[pos];
+
+                match quote {
+                    None if c.is_ascii_whitespace() => break,
+                    None if c == b'"' || c == b'\'' => quote = Some(c),
+                    None => arg.push(c),
+                    Some(b'"') if c == b'\\' && pos + 1 < line.len() => {
+                        pos += 1;
+                        match line[pos] {
+                            b'n' => arg.push(b'\n'),
+                            b'r' => arg.push(b'\r'),
+                            b't' => arg.push(b'\t'),
+                            b'b' => arg.push(0x08),
+                            b'a' => arg.push(0x07),
+                            b'x' if pos + 2 < line.len() => {
+                                match str::from_utf8(&line[pos + 1..pos + 3])
+                                    .ok()
+                                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                                {
+                                    Some(byte) => {
+                                        arg.push(byte);
+                                        pos += 2;
+                                    }
+                                    None => arg.push(b'x'),
+                                }
+                            }
+                            other => arg.push(other),
+                        }
+                    }
+                    Some(b'\'') if c == b'\\' && line.get(pos + 1) == Some(&b'\'') => {
+                        pos += 1;
+                        arg.push(b'\'');
+                    }
+                    Some(q) if c == q => {
+                        // The closing quote must be followed by a space or
+                        // the end of the line
+                        if line.get(pos + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                            return Err("protocol error; unbalanced quotes in request".into());
+                        }
+                        quote = None;
+                    }
+                    Some(_) => arg.push(c),
+                }
+
+                pos += 1;
+            }
+
+            if quote.is_some() {
+                return Err("protocol error; unbalanced quotes in request".into());
+            }
+
+            args.push(Frame::Bulk(Bytes::from(arg)));
+        }
+    }
+
+    /// Check if an entire message can be decoded from `src` without breaking
+    /// any of `limits`
+    pub(crate) fn check(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<(), Error> {
+        Frame::check_nested(src, limits, 0)
+    }
+
+    fn check_nested(src: &mut Cursor<&[u8]>, limits: &Limits, depth: usize) -> Result<(), Error> {
         match get_u8(src)? {
             b'+' => {
                 // String
@@ -104,26 +368,67 @@ impl Frame {
                 if b'-' == peek_u8(src)? {
                     skip(src, 4)
                 } else {
-                    let len = get_decimal(src)?;
+                    let len = get_bulk_len(src, limits)?;
 
-                    // skip that number of bytes + 2 (\r\n).
-                    skip(src, len + 2)
+                    // skip that number of bytes + \r\n
+                    skip_data(src, len)
                 }
             }
-            b'*' => {
-                // Array
-                let len = get_decimal(src)?;
+            b'*' | b'~' | b'>' => {
+                // Array, Set, Push
+                let len = get_multibulk_len(src, limits, depth)?;
                 for _ in 0..len {
-                    Frame::check(src)?;
+                    Frame::check_nested(src, limits, depth + 1)?;
                 }
 
                 Ok(())
             }
-            actual => Err(format!("protocal error; invalid frame type byte: {}", actual).into()),
+            b'%' => {
+                // Map
+                let len = get_multibulk_len(src, limits, depth)?;
+                for _ in 0..len * 2 {
+                    Frame::check_nested(src, limits, depth + 1)?;
+                }
+
+                Ok(())
+            }
+            b'|' => {
+                // Attribute, followed by the frame it is attached to. That
+                // frame counts as nested, or a chain of attributes would
+                // recurse without bound.
+                let len = get_multibulk_len(src, limits, depth)?;
+                for _ in 0..len * 2 {
+                    Frame::check_nested(src, limits, depth + 1)?;
+                }
+
+                Frame::check_nested(src, limits, depth + 1)
+            }
+            b'=' => {
+                // Verbatim
+                let len = get_bulk_len(src, limits)?;
+                skip_data(src, len)
+            }
+            b',' | b'#' | b'(' | b'_' => {
+                // Double, Boolean, BigNumber, Null
+                get_line(src)?;
+                Ok(())
+            }
+            actual => Err(format!("protocol error; invalid frame type byte: {}", actual).into()),
         }
     }
 
-    pub(crate) fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+    /// Parse a message `check` accepted. The nesting and element count
+    /// limits are enforced again, so a frame that was not checked can't
+    /// overflow the stack either.
+    pub(crate) fn parse(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<Frame, Error> {
+        Frame::parse_nested(src, limits, 0)
+    }
+
+    fn parse_nested(
+        src: &mut Cursor<&[u8]>,
+        limits: &Limits,
+        depth: usize,
+    ) -> Result<Frame, Error> {
         match get_u8(src)? {
             b'+' => {
                 let line = get_line(src)?.to_vec();
@@ -140,8 +445,8 @@ impl Frame {
                 Ok(Frame::Error(string))
             }
             b':' => {
-                let len = get_decimal(src)?;
-                Ok(Frame::Integer(len))
+                let val = get_decimal(src)?;
+                Ok(Frame::Integer(val))
             }
             b'$' => {
                 if peek_u8(src)? == b'-' {
@@ -153,38 +458,73 @@ impl Frame {
 
                     Ok(Frame::Null)
                 } else {
-                    let len = get_decimal(src)?.try_into()?;
-                    let n = (len + 2) as u64;
+                    let len = get_length(src)?.try_into()?;
 
                     /* copy data without \r\n */
-                    let data = Bytes::copy_from_slice(&src.chunk()[..len]);
-
-                    /* skip the number of bytes + 2 (\r\n) */
-                    skip(src, n)?;
+                    let data = Bytes::copy_from_slice(get_data(src, len)?);
 
                     Ok(Frame::Bulk(data))
                 }
             }
-            b'*' => {
-                let len = get_decimal(src)?.try_into()?;
-
-                let mut out = Vec::<Frame>::with_capacity(len);
+            b'*' => Ok(Frame::Array(parse_list(src, limits, depth)?)),
+            b'~' => Ok(Frame::Set(parse_list(src, limits, depth)?)),
+            b'>' => Ok(Frame::Push(parse_list(src, limits, depth)?)),
+            b'%' => Ok(Frame::Map(parse_pairs(src, limits, depth)?)),
+            b'|' => {
+                let attrs = parse_pairs(src, limits, depth)?;
+                let data = Box::new(Frame::parse_nested(src, limits, depth + 1)?);
+
+                Ok(Frame::Attribute { attrs, data })
+            }
+            b'=' => {
+                let len = get_length(src)?.try_into()?;
 
-                for _ in 0..len {
-                    out.push(Frame::parse(src)?);
+                /* the payload is `fmt:data`, the format is always 3 bytes */
+                let payload = get_data(src, len)?;
+                if len < 4 || payload[3] != b':' {
+                    return Err("protocol error; invalid verbatim string".into());
                 }
 
-                Ok(Frame::Array(out))
+                let format = String::from_utf8(payload[..3].to_vec())?;
+                let data = Bytes::copy_from_slice(&payload[4..]);
+
+                Ok(Frame::Verbatim { format, data })
+            }
+            b',' => {
+                let line =
+                    str::from_utf8(get_line(src)?).map_err(|_| "protocol error; invalid double")?;
+
+                let value = match line {
+                    "inf" => f64::INFINITY,
+                    "-inf" => f64::NEG_INFINITY,
+                    line => line
+                        .parse::<f64>()
+                        .map_err(|_| "protocol error; invalid double")?,
+                };
+
+                Ok(Frame::Double(value))
+            }
+            b'#' => match get_line(src)? {
+                b"t" => Ok(Frame::Boolean(true)),
+                b"f" => Ok(Frame::Boolean(false)),
+                _ => Err("protocol error; invalid boolean".into()),
+            },
+            b'(' => {
+                let line = get_line(src)?.to_vec();
+
+                Ok(Frame::BigNumber(String::from_utf8(line)?))
+            }
+            b'_' => {
+                get_line(src)?;
+                Ok(Frame::Null)
             }
-            _ => unimplemented!(),
+            actual => Err(format!("protocol error; invalid frame type byte: {}", actual).into()),
         }
     }
 }
 
 impl fmt::Display for Frame {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        use std::str;
-
         match self {
             Frame::Simple(s) => s.fmt(fmt),
             Frame::Error(s) => write!(fmt, "error: {}", s),
@@ -197,7 +537,7 @@ impl fmt::Display for Frame {
                 }
             }
             Frame::Null => write!(fmt, "null"),
-            Frame::Array(parts) => {
+            Frame::Array(parts) | Frame::Set(parts) | Frame::Push(parts) => {
                 for (i, part) in parts.iter().enumerate() {
                     if i > 0 {
                         write!(fmt, " ")?;
@@ -207,15 +547,170 @@ impl fmt::Display for Frame {
 
                 Ok(())
             }
+            Frame::Map(pairs) => {
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+                    write!(fmt, "{} {}", key, value)?;
+                }
+
+                Ok(())
+            }
+            Frame::Double(n) => n.fmt(fmt),
+            Frame::Boolean(b) => b.fmt(fmt),
+            Frame::BigNumber(n) => n.fmt(fmt),
+            Frame::Verbatim { data, .. } => Frame::Bulk(data.clone()).fmt(fmt),
+            Frame::Attribute { data, .. } => data.fmt(fmt),
         }
     }
 }
 
+pub(crate) fn put_decimal(dst: &mut BytesMut, val: impl fmt::Display) {
+    // Formatting into the buffer directly avoids a temporary `String`
+    let _ = write!(dst, "{}\r\n", val);
+}
+
+fn write_double(dst: &mut impl fmt::Write, val: f64) -> fmt::Result {
+    if val.is_nan() {
+        dst.write_str("nan")
+    } else if val.is_infinite() {
+        dst.write_str(if val > 0.0 { "inf" } else { "-inf" })
+    } else {
+        write!(dst, "{}", val)
+    }
+}
+
+/// `fmt::Write` sink that only counts the bytes written to it
+struct Length(usize);
+
+impl fmt::Write for Length {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+fn put_bulk(dst: &mut BytesMut, prefix: u8, val: &[u8]) {
+    dst.put_u8(prefix);
+    put_decimal(dst, val.len());
+    dst.put_slice(val);
+    dst.put_slice(b"\r\n");
+}
+
+fn put_list(dst: &mut BytesMut, prefix: u8, val: &[Frame], protocol: u8) {
+    dst.put_u8(prefix);
+    put_decimal(dst, val.len());
+
+    for entry in val {
+        entry.encode_as(dst, protocol);
+    }
+}
+
+fn put_pairs(dst: &mut BytesMut, pairs: &[(Frame, Frame)], protocol: u8) {
+    for (key, value) in pairs {
+        key.encode_as(dst, protocol);
+        value.encode_as(dst, protocol);
+    }
+}
+
+/// The elements of an aggregate frame nested at `depth`
+fn parse_list(src: &mut Cursor<&[u8]>, limits: &Limits, depth: usize) -> Result<Vec<Frame>, Error> {
+    let len: usize = get_multibulk_len(src, limits, depth)?.try_into()?;
+
+    // Every element takes three bytes at least, don't trust `len` further
+    let mut out = Vec::<Frame>::with_capacity(len.min(src.remaining() / 3));
+
+    for _ in 0..len {
+        out.push(Frame::parse_nested(src, limits, depth + 1)?);
+    }
+
+    Ok(out)
+}
+
+fn parse_pairs(
+    src: &mut Cursor<&[u8]>,
+    limits: &Limits,
+    depth: usize,
+) -> Result<Vec<(Frame, Frame)>, Error> {
+    let len: usize = get_multibulk_len(src, limits, depth)?.try_into()?;
+
+    let mut out = Vec::with_capacity(len.min(src.remaining() / 6));
+
+    for _ in 0..len {
+        let key = Frame::parse_nested(src, limits, depth + 1)?;
+        let value = Frame::parse_nested(src, limits, depth + 1)?;
+        out.push((key, value));
+    }
+
+    Ok(out)
+}
+
 fn skip(src: &mut Cursor<&[u8]>, n: u64) -> Result<(), Error> {
+    if (src.remaining() as u64) < n {
+        return Err(Error::Incomplete);
+    }
+
     src.set_position(src.position() + n);
     Ok(())
 }
 
+/// Skip `len` bytes of payload followed by `\r\n`
+fn skip_data(src: &mut Cursor<&[u8]>, len: u64) -> Result<(), Error> {
+    let len = len.try_into()?;
+
+    get_data(src, len)?;
+    Ok(())
+}
+
+/// Return `len` bytes of payload and advance past the `\r\n` that ends it
+fn get_data<'a>(src: &mut Cursor<&'a [u8]>, len: usize) -> Result<&'a [u8], Error> {
+    let start = src.position() as usize;
+    let end = start
+        .checked_add(len)
+        .ok_or("protocol error; invalid bulk length")?;
+
+    let buf = *src.get_ref();
+    let data = buf.get(start..end).ok_or(Error::Incomplete)?;
+
+    match buf.get(end..end + 2) {
+        Some(b"\r\n") => {}
+        Some(_) => return Err("protocol error; expected \\r\\n after bulk data".into()),
+        None => return Err(Error::Incomplete),
+    }
+
+    src.set_position((end + 2) as u64);
+
+    Ok(data)
+}
+
+/// Read the length of a bulk or verbatim string and enforce the bulk limit
+fn get_bulk_len(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<u64, Error> {
+    let len = get_length(src)?;
+
+    if len > limits.max_bulk_len {
+        return Err("protocol error; invalid bulk length".into());
+    }
+
+    Ok(len)
+}
+
+/// Read the length of an aggregate frame nested at `depth` and enforce the
+/// element count and nesting limits
+fn get_multibulk_len(src: &mut Cursor<&[u8]>, limits: &Limits, depth: usize) -> Result<u64, Error> {
+    if depth >= limits.max_depth {
+        return Err("protocol error; too many nested aggregates".into());
+    }
+
+    let len = get_length(src)?;
+
+    if len > limits.max_multibulk_len {
+        return Err("protocol error; invalid multibulk length".into());
+    }
+
+    Ok(len)
+}
+
 fn peek_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
     if src.remaining() == 0 {
         return Err(Error::Incomplete);
@@ -253,10 +748,150 @@ fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
     Err(Error::Incomplete)
 }
 
-fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
+fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
     use atoi::atoi;
 
     let line = get_line(src)?;
 
-    atoi::<u64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
+    atoi::<i64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
+/// Read a length, which unlike an integer can't be negative
+fn get_length(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
+    u64::try_from(get_decimal(src)?).map_err(|_| "protocol error; invalid frame format".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(src: &[u8], limits: &Limits) -> Result<(), Error> {
+        Frame::check(&mut Cursor::new(src), limits)
+    }
+
+    fn parse(src: &[u8]) -> Result<Frame, Error> {
+        Frame::parse(&mut Cursor::new(src), &Limits::default())
+    }
+
+    fn is_incomplete(result: Result<(), Error>) -> bool {
+        matches!(result, Err(Error::Incomplete))
+    }
+
+    #[test]
+    fn chained_attributes_count_toward_the_nesting_limit() {
+        let chain = b"|0\r\n".repeat(300_000);
+
+        let err = check(&chain, &Limits::default()).unwrap_err();
+        assert!(err.to_string().contains("too many nested aggregates"));
+
+        let err = parse(&chain).unwrap_err();
+        assert!(err.to_string().contains("too many nested aggregates"));
+    }
+
+    #[test]
+    fn attributes_within_the_limit_are_accepted() {
+        let src = b"|1\r\n+key\r\n+value\r\n:1\r\n";
+        check(src, &Limits::default()).unwrap();
+
+        match parse(src).unwrap() {
+            Frame::Attribute { attrs, data } => {
+                assert_eq!(attrs.len(), 1);
+                assert!(matches!(*data, Frame::Integer(1)));
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn nested_arrays_are_limited() {
+        let limits = Limits {
+            max_depth: 3,
+            ..Limits::default()
+        };
+
+        check(b"*1\r\n*1\r\n*1\r\n:1\r\n", &limits).unwrap();
+        assert!(check(b"*1\r\n*1\r\n*1\r\n*1\r\n:1\r\n", &limits).is_err());
+    }
+
+    #[test]
+    fn parse_enforces_the_nesting_limit_on_unchecked_input() {
+        let deep = b"*1\r\n".repeat(100_000);
+        assert!(parse(&deep).is_err());
+    }
+
+    #[test]
+    fn lengths_are_limited() {
+        let limits = Limits {
+            max_bulk_len: 4,
+            max_multibulk_len: 2,
+            ..Limits::default()
+        };
+
+        check(b"$4\r\nabcd\r\n", &limits).unwrap();
+        assert!(check(b"$5\r\nabcde\r\n", &limits).is_err());
+        assert!(check(b"*3\r\n", &limits).is_err());
+        assert!(check(b"%2\r\n", &limits).is_err());
+    }
+
+    #[test]
+    fn partial_frames_are_incomplete() {
+        let limits = Limits::default();
+
+        assert!(is_incomplete(check(b"$5\r\nab", &limits)));
+        assert!(is_incomplete(check(b"*2\r\n:1\r\n", &limits)));
+        assert!(is_incomplete(check(b"+OK", &limits)));
+    }
+
+    #[test]
+    fn bulk_data_must_end_with_crlf() {
+        assert!(check(b"$2\r\nabcd\r\n", &Limits::default()).is_err());
+    }
+
+    #[test]
+    fn integers_are_signed() {
+        for n in [-1, 0, i64::MIN, i64::MAX] {
+            let mut dst = BytesMut::new();
+            Frame::Integer(n).encode(&mut dst);
+            assert_eq!(dst, format!(":{}\r\n", n).as_bytes());
+            assert!(matches!(parse(&dst), Ok(Frame::Integer(v)) if v == n));
+        }
+
+        assert!(parse(b":9223372036854775808\r\n").is_err());
+    }
+
+    #[test]
+    fn lengths_cannot_be_negative() {
+        assert!(parse(b"*-2\r\n").is_err());
+        assert!(parse(b"$-2\r\n").is_err());
+        assert!(check(b"%-1\r\n", &Limits::default()).is_err());
+    }
+
+    #[test]
+    fn resp3_types_round_trip() {
+        let frame = Frame::Map(vec![
+            (Frame::Simple("a".into()), Frame::Double(1.5)),
+            (Frame::Boolean(true), Frame::Set(vec![Frame::Null])),
+        ]);
+
+        let mut dst = BytesMut::new();
+        frame.encode_as(&mut dst, 3);
+        assert_eq!(&dst[..], b"%2\r\n+a\r\n,1.5\r\n#t\r\n~1\r\n_\r\n");
+
+        let mut dst = BytesMut::new();
+        frame.encode_as(&mut dst, 2);
+        assert_eq!(&dst[..], b"*4\r\n+a\r\n$3\r\n1.5\r\n:1\r\n*1\r\n$-1\r\n");
+    }
+
+    #[test]
+    fn inline_commands_split_like_redis() {
+        let frame = Frame::from_inline(b"SET k \"a b\\x41\" 'c\\'d'\r\n").unwrap();
+        let args: Vec<String> = match frame {
+            Frame::Array(args) => args.iter().map(ToString::to_string).collect(),
+            frame => panic!("unexpected frame {:?}", frame),
+        };
+        assert_eq!(args, ["SET", "k", "a bA", "c'd"]);
+
+        assert!(Frame::from_inline(b"GET \"k\n").is_err());
+        assert!(Frame::from_inline(b"GET \"k\"x\n").is_err());
+    }
 }
@@ -6,8 +6,15 @@ pub mod server;
 pub mod frame;
 use frame::Frame;
 
+pub mod types;
+pub use types::{FromFrame, ToArgs};
+
+pub mod codec;
+
 pub mod client;
 
+pub mod blocking;
+
 mod parse;
 use parse::Parse;
 use parse::ParseError;
@@ -15,7 +22,23 @@ use parse::ParseError;
 mod cmd;
 use cmd::Command;
 
+mod acl;
+
+mod tls;
+
+mod replication;
+pub use replication::DEFAULT_BACKLOG_SIZE;
+
+mod cluster;
+
+mod script;
+pub use script::DEFAULT_BUSY_REPLY_THRESHOLD;
+
+mod pubsub;
+pub use pubsub::KeyspaceEvents;
+
 mod db;
+pub use db::DEFAULT_DATABASES;
 use db::Db;
 use db::DbDropGuard;
 
@@ -14,6 +14,9 @@ pub(crate) struct Parse {
 pub(crate) enum ParseError {
     EndOfStream,
 
+    /// Entries were left after the last one expected
+    Trailing,
+
     Other(crate::Error),
 }
 
@@ -33,6 +36,9 @@ impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ParseError::EndOfStream => "protocol error; unexpected end of stream".fmt(f),
+            ParseError::Trailing => {
+                "protocol error; expect end of frame, but there was more".fmt(f)
+            }
             ParseError::Other(err) => err.fmt(f),
         }
     }
@@ -86,12 +92,26 @@ impl Parse {
         }
     }
 
+    /// Return the next entry as an integer
+    pub(crate) fn next_int(&mut self) -> Result<u64, ParseError> {
+        use atoi::atoi;
+
+        const MSG: &str = "ERR value is not an integer or out of range";
+
+        match self.next()? {
+            Frame::Integer(v) => u64::try_from(v).map_err(|_| MSG.into()),
+            Frame::Simple(data) => atoi::<u64>(data.as_bytes()).ok_or_else(|| MSG.into()),
+            Frame::Bulk(data) => atoi::<u64>(&data).ok_or_else(|| MSG.into()),
+            frame => Err(format!("protocol error; expected int frame but got {:?}", frame).into()),
+        }
+    }
+
     /// Check if we have handled all entry
     pub(crate) fn finish(&mut self) -> Result<(), ParseError> {
         if self.parts.next().is_none() {
             Ok(())
         } else {
-            Err("protocol error; expect end of frame, but there was more".into())
+            Err(ParseError::Trailing)
         }
     }
 }
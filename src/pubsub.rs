@@ -0,0 +1,358 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use log::warn;
+use tokio::sync::mpsc;
+
+use crate::Frame;
+use crate::acl;
+
+/// Messages published to a subscriber that hasn't read them yet. One that
+/// falls further behind is disconnected rather than buffering without end.
+const SUBSCRIBER_QUEUE: usize = 1024;
+
+/// Handle to the channels clients subscribed to, by name or by pattern
+#[derive(Debug, Clone)]
+pub(crate) struct PubSub {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+
+    /// Keyspace events published, set with `notify-keyspace-events`
+    events: KeyspaceEvents,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: u64,
+
+    /// Where the messages of each subscriber go, by its ID
+    subscribers: HashMap<u64, mpsc::Sender<Message>>,
+
+    /// IDs of the subscribers of each channel
+    channels: HashMap<Bytes, HashSet<u64>>,
+
+    /// IDs of the subscribers of each pattern
+    patterns: HashMap<Bytes, HashSet<u64>>,
+}
+
+/// A message published to a channel, `pattern` is the one it matched for a
+/// pattern subscriber
+#[derive(Debug)]
+pub(crate) struct Message {
+    pattern: Option<Bytes>,
+    channel: Bytes,
+    payload: Bytes,
+}
+
+/// Channels and patterns a connection subscribed to. They are dropped from
+/// the registry along with it.
+#[derive(Debug)]
+pub(crate) struct Subscription {
+    pubsub: PubSub,
+    id: u64,
+    receiver: mpsc::Receiver<Message>,
+    channels: BTreeSet<Bytes>,
+    patterns: BTreeSet<Bytes>,
+}
+
+/// Classes of keyspace events to publish, parsed from a flag string like
+/// redis' `notify-keyspace-events`:
+///
+/// - `K` keyspace events, on `__keyspace@<db>__:<key>`
+/// - `E` keyevent events, on `__keyevent@<db>__:<event>`
+/// - `g` generic commands like `DEL` and `MOVE`
+/// - `$` string commands
+/// - `l`, `s`, `h`, `z`, `t`, `d`, `m`, `n`, `x`, `e` list, set, hash,
+///   sorted set, stream, module, key miss, new key, expired and evicted
+///   events
+/// - `A` alias for `g$lshzxetd`
+///
+/// Nothing is published unless `K` or `E` is given along with a class.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyspaceEvents(u16);
+
+impl KeyspaceEvents {
+    pub(crate) const KEYSPACE: KeyspaceEvents = KeyspaceEvents(1 << 0);
+    pub(crate) const KEYEVENT: KeyspaceEvents = KeyspaceEvents(1 << 1);
+    pub(crate) const GENERIC: KeyspaceEvents = KeyspaceEvents(1 << 2);
+    pub(crate) const STRING: KeyspaceEvents = KeyspaceEvents(1 << 3);
+    const LIST: KeyspaceEvents = KeyspaceEvents(1 << 4);
+    const SET: KeyspaceEvents = KeyspaceEvents(1 << 5);
+    const HASH: KeyspaceEvents = KeyspaceEvents(1 << 6);
+    const ZSET: KeyspaceEvents = KeyspaceEvents(1 << 7);
+    const EXPIRED: KeyspaceEvents = KeyspaceEvents(1 << 8);
+    const EVICTED: KeyspaceEvents = KeyspaceEvents(1 << 9);
+    const STREAM: KeyspaceEvents = KeyspaceEvents(1 << 10);
+    const MODULE: KeyspaceEvents = KeyspaceEvents(1 << 11);
+    const KEY_MISS: KeyspaceEvents = KeyspaceEvents(1 << 12);
+    const NEW_KEY: KeyspaceEvents = KeyspaceEvents(1 << 13);
+
+    /// The classes `A` stands for
+    const ALL: KeyspaceEvents = KeyspaceEvents(
+        KeyspaceEvents::GENERIC.0
+            | KeyspaceEvents::STRING.0
+            | KeyspaceEvents::LIST.0
+            | KeyspaceEvents::SET.0
+            | KeyspaceEvents::HASH.0
+            | KeyspaceEvents::ZSET.0
+            | KeyspaceEvents::EXPIRED.0
+            | KeyspaceEvents::EVICTED.0
+            | KeyspaceEvents::STREAM.0
+            | KeyspaceEvents::MODULE.0,
+    );
+
+    /// Parse a flag string, like `KEA` or `Kg$`
+    pub fn parse(flags: &str) -> crate::Result<KeyspaceEvents> {
+        let mut events = KeyspaceEvents::default();
+
+        for flag in flags.chars() {
+            let class = match flag {
+                'K' => KeyspaceEvents::KEYSPACE,
+                'E' => KeyspaceEvents::KEYEVENT,
+                'g' => KeyspaceEvents::GENERIC,
+                '$' => KeyspaceEvents::STRING,
+                'l' => KeyspaceEvents::LIST,
+                's' => KeyspaceEvents::SET,
+                'h' => KeyspaceEvents::HASH,
+                'z' => KeyspaceEvents::ZSET,
+                'x' => KeyspaceEvents::EXPIRED,
+                'e' => KeyspaceEvents::EVICTED,
+                't' => KeyspaceEvents::STREAM,
+                'd' => KeyspaceEvents::MODULE,
+                'm' => KeyspaceEvents::KEY_MISS,
+                'n' => KeyspaceEvents::NEW_KEY,
+                'A' => KeyspaceEvents::ALL,
+                _ => return Err(format!("invalid notify-keyspace-events flag '{}'", flag).into()),
+            };
+            events.0 |= class.0;
+        }
+
+        Ok(events)
+    }
+
+    fn contains(self, other: KeyspaceEvents) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl PubSub {
+    pub(crate) fn new(events: KeyspaceEvents) -> PubSub {
+        PubSub {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::default()),
+                events,
+            }),
+        }
+    }
+
+    /// A subscription of no channel yet, it receives what is published to
+    /// the channels added to it
+    pub(crate) fn subscription(&self) -> Subscription {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_QUEUE);
+
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.subscribers.insert(id, sender);
+
+        Subscription {
+            pubsub: self.clone(),
+            id,
+            receiver,
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+        }
+    }
+
+    /// Send `payload` to the subscribers of `channel` and of the patterns
+    /// matching it, returns how many received it
+    pub(crate) fn publish(&self, channel: &[u8], payload: &[u8]) -> usize {
+        let mut state = self.shared.state.lock().unwrap();
+        let channel = Bytes::copy_from_slice(channel);
+        let payload = Bytes::copy_from_slice(payload);
+
+        let mut deliveries = vec![];
+        if let Some(ids) = state.channels.get(&channel) {
+            deliveries.extend(ids.iter().map(|&id| (id, None)));
+        }
+        for (pattern, ids) in &state.patterns {
+            if acl::matches(pattern, &channel) {
+                deliveries.extend(ids.iter().map(|&id| (id, Some(pattern.clone()))));
+            }
+        }
+
+        let mut received = 0;
+        for (id, pattern) in deliveries {
+            let Some(sender) = state.subscribers.get(&id) else {
+                continue;
+            };
+
+            let message = Message {
+                pattern,
+                channel: channel.clone(),
+                payload: payload.clone(),
+            };
+            match sender.try_send(message) {
+                Ok(()) => received += 1,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    // Its connection ends once it read what is queued
+                    warn!("subscriber {} fell behind, disconnecting it", id);
+                    state.remove(id);
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => state.remove(id),
+            }
+        }
+
+        received
+    }
+
+    /// Publish the keyspace event `event` of class `class` on `key` of
+    /// database `db`, if `notify-keyspace-events` selects it
+    pub(crate) fn notify(&self, class: KeyspaceEvents, event: &str, db: usize, key: &str) {
+        let events = self.shared.events;
+        if !events.contains(class) {
+            return;
+        }
+
+        if events.contains(KeyspaceEvents::KEYSPACE) {
+            let channel = format!("__keyspace@{}__:{}", db, key);
+            self.publish(channel.as_bytes(), event.as_bytes());
+        }
+        if events.contains(KeyspaceEvents::KEYEVENT) {
+            let channel = format!("__keyevent@{}__:{}", db, event);
+            self.publish(channel.as_bytes(), key.as_bytes());
+        }
+    }
+}
+
+impl State {
+    /// Drop subscriber `id` from every channel and pattern
+    fn remove(&mut self, id: u64) {
+        self.subscribers.remove(&id);
+
+        for ids in [&mut self.channels, &mut self.patterns] {
+            ids.retain(|_, subscribers| {
+                subscribers.remove(&id);
+                !subscribers.is_empty()
+            });
+        }
+    }
+}
+
+impl Subscription {
+    /// Add `channel`, returns the reply confirming it
+    pub(crate) fn subscribe(&mut self, channel: Bytes) -> Frame {
+        if self.channels.insert(channel.clone()) {
+            let mut state = self.pubsub.shared.state.lock().unwrap();
+            state
+                .channels
+                .entry(channel.clone())
+                .or_default()
+                .insert(self.id);
+        }
+
+        self.reply("subscribe", Some(channel))
+    }
+
+    /// Drop `channel`, returns the reply confirming it
+    pub(crate) fn unsubscribe(&mut self, channel: Bytes) -> Frame {
+        if self.channels.remove(&channel) {
+            let mut state = self.pubsub.shared.state.lock().unwrap();
+            remove(&mut state.channels, &channel, self.id);
+        }
+
+        self.reply("unsubscribe", Some(channel))
+    }
+
+    pub(crate) fn psubscribe(&mut self, pattern: Bytes) -> Frame {
+        if self.patterns.insert(pattern.clone()) {
+            let mut state = self.pubsub.shared.state.lock().unwrap();
+            state
+                .patterns
+                .entry(pattern.clone())
+                .or_default()
+                .insert(self.id);
+        }
+
+        self.reply("psubscribe", Some(pattern))
+    }
+
+    pub(crate) fn punsubscribe(&mut self, pattern: Bytes) -> Frame {
+        if self.patterns.remove(&pattern) {
+            let mut state = self.pubsub.shared.state.lock().unwrap();
+            remove(&mut state.patterns, &pattern, self.id);
+        }
+
+        self.reply("punsubscribe", Some(pattern))
+    }
+
+    pub(crate) fn channels(&self) -> Vec<Bytes> {
+        self.channels.iter().cloned().collect()
+    }
+
+    pub(crate) fn patterns(&self) -> Vec<Bytes> {
+        self.patterns.iter().cloned().collect()
+    }
+
+    /// Channels and patterns subscribed to
+    pub(crate) fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    /// The confirmation of a change, with the number of subscriptions left.
+    /// Unsubscribing from all when there was none confirms no channel.
+    pub(crate) fn reply(&self, kind: &'static str, channel: Option<Bytes>) -> Frame {
+        Frame::Push(vec![
+            Frame::Bulk(Bytes::from_static(kind.as_bytes())),
+            channel.map_or(Frame::Null, Frame::Bulk),
+            Frame::Integer(self.count() as i64),
+        ])
+    }
+
+    /// The next message published to the channels, `None` if this
+    /// subscriber fell behind and was dropped
+    pub(crate) async fn recv(&mut self) -> Option<Message> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut state = self.pubsub.shared.state.lock().unwrap();
+        state.remove(self.id);
+    }
+}
+
+impl Message {
+    /// The frame pushed to the subscriber
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = vec![];
+
+        match self.pattern {
+            Some(pattern) => {
+                frame.push(Frame::Bulk(Bytes::from_static(b"pmessage")));
+                frame.push(Frame::Bulk(pattern));
+            }
+            None => frame.push(Frame::Bulk(Bytes::from_static(b"message"))),
+        }
+        frame.push(Frame::Bulk(self.channel));
+        frame.push(Frame::Bulk(self.payload));
+
+        Frame::Push(frame)
+    }
+}
+
+/// Drop subscriber `id` from the subscribers of `name`
+fn remove(subscribers: &mut HashMap<Bytes, HashSet<u64>>, name: &Bytes, id: u64) {
+    if let Some(ids) = subscribers.get_mut(name) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            subscribers.remove(name);
+        }
+    }
+}
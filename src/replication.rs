@@ -0,0 +1,809 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use log::{info, warn};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::cmd::{Auth, Psync};
+use crate::script::Scripts;
+use crate::{Command, Connection, Db, Frame, Parse};
+
+/// Bytes of the replication stream kept when no size is configured
+pub const DEFAULT_BACKLOG_SIZE: usize = 1024 * 1024;
+
+/// Wait between two attempts of a follower to reach its leader
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How often a follower reports how much of the stream it applied
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sent after the commands of a snapshot, as a simple string so it can't be
+/// mistaken for one of them
+const SNAPSHOT_END: &str = "SNAPSHOTEND";
+
+/// The stream of writes applied to the keyspace, with its tail kept so a
+/// follower that was briefly disconnected can continue where it stopped.
+/// Offsets count the bytes of the stream since it started.
+#[derive(Debug)]
+pub(crate) struct Backlog {
+    /// Whether writes to the keyspace are appended. A follower appends the
+    /// stream of its leader as received instead.
+    propagate: bool,
+
+    /// Identifies the stream, offsets only compare within the same ID
+    replid: String,
+
+    /// Stream this one took over from and the offset it ended at, kept
+    /// after a promotion so the other followers of the old leader continue
+    replid2: Option<(String, u64)>,
+
+    /// Bytes ever appended to the stream
+    offset: u64,
+
+    /// Tail of the stream, ending at `offset`
+    buffer: VecDeque<u8>,
+    capacity: usize,
+
+    /// Database the stream selected last, writes to another one are
+    /// preceded by a `SELECT`
+    selected: Option<usize>,
+
+    /// Wakes up the tasks sending the stream to followers
+    notify: watch::Sender<u64>,
+}
+
+/// Copy of the keyspace and the point of the replication stream it matches
+#[derive(Debug)]
+pub(crate) struct Snapshot {
+    pub(crate) replid: String,
+    pub(crate) offset: u64,
+    pub(crate) selected: Option<usize>,
+    pub(crate) databases: Vec<Vec<(String, Bytes)>>,
+
+    /// Code of the function libraries
+    pub(crate) libraries: Vec<String>,
+}
+
+/// Handle to the replication state: the leader followed, if any, and the
+/// followers connected to this server
+#[derive(Debug, Clone)]
+pub(crate) struct Replication {
+    shared: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Port this server accepts clients on, told to the leader
+    port: u16,
+
+    /// Refuse writes from clients while following a leader
+    read_only: bool,
+
+    /// Credentials to authenticate to the leader with
+    leader_auth: Option<Auth>,
+
+    /// Set while following a leader
+    leader: Option<Leader>,
+
+    /// Followers streaming from this server, by connection
+    replicas: HashMap<u64, Replica>,
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct Leader {
+    host: String,
+    port: u16,
+
+    /// The stream is being received
+    link_up: bool,
+
+    /// Keeps the link up, reconnecting as needed
+    task: JoinHandle<()>,
+}
+
+#[derive(Debug)]
+struct Replica {
+    ip: IpAddr,
+    port: u16,
+
+    /// Offset the follower last reported having applied
+    offset: u64,
+    last_ack: Instant,
+}
+
+/// Unregisters a follower once its connection ends
+struct Registration {
+    replication: Replication,
+    id: u64,
+}
+
+impl Backlog {
+    pub(crate) fn new(capacity: usize) -> Backlog {
+        Backlog {
+            propagate: true,
+            replid: new_id(),
+            replid2: None,
+            offset: 0,
+            buffer: VecDeque::new(),
+            capacity,
+            selected: None,
+            notify: watch::channel(0).0,
+        }
+    }
+
+    pub(crate) fn replid(&self) -> &str {
+        &self.replid
+    }
+
+    pub(crate) fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub(crate) fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Append a write applied to database `db`, `None` for a write that is
+    /// not about a single database
+    pub(crate) fn append(&mut self, db: Option<usize>, args: &[&[u8]]) {
+        if !self.propagate {
+            return;
+        }
+
+        if let Some(db) = db
+            && self.selected != Some(db)
+        {
+            self.selected = Some(db);
+            self.feed(&encode(&[b"select", db.to_string().as_bytes()]));
+        }
+
+        self.feed(&encode(args));
+    }
+
+    /// Append bytes of the stream as they are
+    fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend(bytes);
+
+        let excess = self.buffer.len().saturating_sub(self.capacity);
+        self.buffer.drain(..excess);
+
+        self.offset += bytes.len() as u64;
+        self.notify.send_replace(self.offset);
+    }
+
+    /// Offset of the first byte still in the backlog
+    fn start(&self) -> u64 {
+        self.offset - self.buffer.len() as u64
+    }
+
+    /// The stream from `offset` on, `None` if that part is gone
+    fn since(&self, offset: u64) -> Option<Vec<u8>> {
+        if offset < self.start() || offset > self.offset {
+            return None;
+        }
+
+        let skip = (offset - self.start()) as usize;
+        Some(self.buffer.range(skip..).copied().collect())
+    }
+
+    /// Whether a follower that applied the stream `replid` up to `offset`
+    /// can get the rest of it from the backlog
+    fn can_continue(&self, replid: &str, offset: u64) -> bool {
+        let known = replid == self.replid
+            || matches!(&self.replid2, Some((id, end)) if id == replid && offset <= *end);
+
+        known && self.since(offset).is_some()
+    }
+
+    /// Continue the stream `replid` from `offset` with an empty backlog,
+    /// after a full resync
+    fn reset(&mut self, replid: String, offset: u64) {
+        self.replid = replid;
+        self.replid2 = None;
+        self.offset = offset;
+        self.buffer.clear();
+        self.notify.send_replace(offset);
+    }
+
+    /// Stop appending writes, the stream of a leader is fed instead
+    fn follow(&mut self) {
+        self.propagate = false;
+    }
+
+    /// Start a stream of our own that continues the one followed so far
+    fn promote(&mut self) {
+        let old = std::mem::replace(&mut self.replid, new_id());
+        self.replid2 = Some((old, self.offset));
+        self.propagate = true;
+    }
+
+    /// The leader goes on with the stream under a new ID
+    fn switch_id(&mut self, replid: &str) {
+        if replid != self.replid {
+            let old = std::mem::replace(&mut self.replid, replid.to_string());
+            self.replid2 = Some((old, self.offset));
+        }
+    }
+
+    fn info(&self, out: &mut String) {
+        let (replid2, second_offset) = match &self.replid2 {
+            Some((id, offset)) => (id.clone(), *offset as i64),
+            None => ("0".repeat(40), -1),
+        };
+
+        let _ = write!(
+            out,
+            "master_replid:{}\r\n\
+             master_replid2:{}\r\n\
+             master_repl_offset:{}\r\n\
+             second_repl_offset:{}\r\n\
+             repl_backlog_active:1\r\n\
+             repl_backlog_size:{}\r\n\
+             repl_backlog_first_byte_offset:{}\r\n\
+             repl_backlog_histlen:{}\r\n",
+            self.replid,
+            replid2,
+            self.offset,
+            second_offset,
+            self.capacity,
+            self.start(),
+            self.buffer.len(),
+        );
+    }
+}
+
+impl Snapshot {
+    /// The snapshot as a sequence of commands rebuilding it, sent one frame
+    /// each so no frame grows with the size of the keyspace
+    fn commands(&self) -> impl Iterator<Item = Frame> + '_ {
+        let libraries = self
+            .libraries
+            .iter()
+            .map(|code| command(&[b"function", b"load", b"replace", code.as_bytes()]));
+
+        let databases = self
+            .databases
+            .iter()
+            .enumerate()
+            .filter(|(_, db)| !db.is_empty())
+            .flat_map(|(index, db)| {
+                let select = command(&[b"select", index.to_string().as_bytes()]);
+                let sets = db.iter().map(|(key, value)| {
+                    let mut frame = command(&[b"set", key.as_bytes()]);
+                    frame.push_bulk(value.clone());
+                    frame
+                });
+                std::iter::once(select).chain(sets)
+            });
+
+        // Leave the follower on the database the stream goes on with
+        let selected = self
+            .selected
+            .map(|selected| command(&[b"select", selected.to_string().as_bytes()]));
+
+        std::iter::once(command(&[b"function", b"flush"]))
+            .chain(libraries)
+            .chain(databases)
+            .chain(selected)
+    }
+}
+
+impl Replication {
+    /// `port` is the one this server accepts clients on. With `read_only`
+    /// clients can't write while this server follows a leader.
+    pub(crate) fn new(port: u16, read_only: bool, leader_auth: Option<Auth>) -> Replication {
+        Replication {
+            shared: Arc::new(Mutex::new(State {
+                port,
+                read_only,
+                leader_auth,
+                leader: None,
+                replicas: HashMap::new(),
+                next_id: 0,
+            })),
+        }
+    }
+
+    /// Follow the leader at `host:port`, dropping the current leader if
+    /// another one. Returns `false` if already following that leader.
+    pub(crate) fn replicate(&self, db: &Db, scripts: &Scripts, host: String, port: u16) -> bool {
+        let mut state = self.shared.lock().unwrap();
+
+        if let Some(leader) = &state.leader {
+            if leader.host == host && leader.port == port {
+                return false;
+            }
+            leader.task.abort();
+        }
+
+        info!("following the leader at {}:{}", host, port);
+
+        db.backlog(|backlog| backlog.follow());
+
+        let task = tokio::spawn(follow(
+            self.clone(),
+            db.clone(),
+            scripts.clone(),
+            host.clone(),
+            port,
+        ));
+
+        state.leader = Some(Leader {
+            host,
+            port,
+            link_up: false,
+            task,
+        });
+
+        true
+    }
+
+    /// Stop following a leader and accept writes, the data is kept
+    pub(crate) fn promote(&self, db: &Db) {
+        let mut state = self.shared.lock().unwrap();
+
+        if let Some(leader) = state.leader.take() {
+            info!("no longer following {}:{}", leader.host, leader.port);
+
+            leader.task.abort();
+            db.backlog(|backlog| backlog.promote());
+        }
+    }
+
+    /// Whether writes from clients are refused
+    pub(crate) fn read_only(&self) -> bool {
+        let state = self.shared.lock().unwrap();
+        state.read_only && state.leader.is_some()
+    }
+
+    /// Role of this server, as `HELLO` reports it
+    pub(crate) fn role(&self) -> &'static str {
+        if self.shared.lock().unwrap().leader.is_some() {
+            "replica"
+        } else {
+            "master"
+        }
+    }
+
+    /// The replication section of `INFO`
+    pub(crate) fn info(&self, db: &Db) -> String {
+        let state = self.shared.lock().unwrap();
+        let offset = db.backlog(|backlog| backlog.offset());
+
+        let mut out = String::from("# Replication\r\n");
+
+        match &state.leader {
+            Some(leader) => {
+                let _ = write!(
+                    out,
+                    "role:slave\r\n\
+                     master_host:{}\r\n\
+                     master_port:{}\r\n\
+                     master_link_status:{}\r\n\
+                     slave_repl_offset:{}\r\n\
+                     slave_read_only:{}\r\n",
+                    leader.host,
+                    leader.port,
+                    if leader.link_up { "up" } else { "down" },
+                    offset,
+                    state.read_only as u8,
+                );
+            }
+            None => out.push_str("role:master\r\n"),
+        }
+
+        let _ = write!(out, "connected_slaves:{}\r\n", state.replicas.len());
+
+        let mut replicas: Vec<_> = state.replicas.iter().collect();
+        replicas.sort_by_key(|(id, _)| **id);
+
+        for (i, (_, replica)) in replicas.into_iter().enumerate() {
+            let _ = write!(
+                out,
+                "slave{}:ip={},port={},state=online,offset={},lag={}\r\n",
+                i,
+                replica.ip,
+                replica.port,
+                replica.offset,
+                replica.last_ack.elapsed().as_secs(),
+            );
+        }
+
+        db.backlog(|backlog| backlog.info(&mut out));
+
+        out
+    }
+
+    /// Send the replication stream to a follower that asked for it with
+    /// `PSYNC`, until the connection ends. The follower accepts clients on
+    /// `ip:port`.
+    pub(crate) async fn serve(
+        &self,
+        db: &Db,
+        scripts: &Scripts,
+        connection: &mut Connection,
+        psync: &Psync,
+        ip: IpAddr,
+        port: u16,
+    ) -> crate::Result<()> {
+        let resumed = db.backlog(|backlog| match psync.offset() {
+            Some(offset) if backlog.can_continue(psync.replid(), offset) => {
+                Some(backlog.replid().to_string())
+            }
+            _ => None,
+        });
+
+        let mut offset = match (resumed, psync.offset()) {
+            (Some(replid), Some(offset)) => {
+                info!("follower {}:{} continues from offset {}", ip, port, offset);
+
+                let response = Frame::Simple(format!("CONTINUE {}", replid));
+                connection.feed_frame(&response).await?;
+                offset
+            }
+            _ => {
+                let mut snapshot = db.snapshot();
+                snapshot.libraries = scripts
+                    .libraries()
+                    .into_iter()
+                    .map(|library| library.code)
+                    .collect();
+
+                info!(
+                    "full resync of follower {}:{} at offset {}",
+                    ip, port, snapshot.offset
+                );
+
+                let response = Frame::Simple(format!(
+                    "FULLRESYNC {} {}",
+                    snapshot.replid, snapshot.offset
+                ));
+                connection.feed_frame(&response).await?;
+                for frame in snapshot.commands() {
+                    connection.feed_frame(&frame).await?;
+                }
+                connection
+                    .feed_frame(&Frame::Simple(SNAPSHOT_END.to_string()))
+                    .await?;
+                snapshot.offset
+            }
+        };
+        connection.flush().await?;
+
+        let registration = self.register(ip, port, offset);
+
+        let mut changes = db.backlog(|backlog| backlog.notify.subscribe());
+
+        loop {
+            let stream = db
+                .backlog(|backlog| backlog.since(offset))
+                .ok_or("follower fell behind the replication backlog")?;
+
+            if !stream.is_empty() {
+                connection.feed_raw(&stream).await?;
+                connection.flush().await?;
+                offset += stream.len() as u64;
+            }
+
+            tokio::select! {
+                changed = changes.changed() => {
+                    if changed.is_err() {
+                        return Ok(());
+                    }
+                }
+                frame = connection.read_frame() => {
+                    let Some(frame) = frame? else {
+                        return Ok(());
+                    };
+
+                    if let Command::ReplConf(replconf) = Command::from_frame(frame)?
+                        && let Some(acked) = replconf.ack()
+                    {
+                        self.ack(registration.id, acked);
+                    }
+                }
+            }
+        }
+    }
+
+    fn register(&self, ip: IpAddr, port: u16, offset: u64) -> Registration {
+        let mut state = self.shared.lock().unwrap();
+
+        let id = state.next_id;
+        state.next_id += 1;
+
+        state.replicas.insert(
+            id,
+            Replica {
+                ip,
+                port,
+                offset,
+                last_ack: Instant::now(),
+            },
+        );
+
+        Registration {
+            replication: self.clone(),
+            id,
+        }
+    }
+
+    fn ack(&self, id: u64, offset: u64) {
+        let mut state = self.shared.lock().unwrap();
+
+        if let Some(replica) = state.replicas.get_mut(&id) {
+            replica.offset = offset;
+            replica.last_ack = Instant::now();
+        }
+    }
+
+    fn set_link_up(&self, up: bool) {
+        let mut state = self.shared.lock().unwrap();
+
+        if let Some(leader) = &mut state.leader {
+            leader.link_up = up;
+        }
+    }
+
+    /// Receive the stream of the leader at `host:port` and apply it, from a
+    /// full copy of its keyspace unless the stream can be continued
+    async fn sync(
+        &self,
+        db: &mut Db,
+        scripts: &Scripts,
+        host: &str,
+        port: u16,
+    ) -> crate::Result<()> {
+        let socket = TcpStream::connect((host, port)).await?;
+        let mut connection = Connection::boxed(socket);
+
+        let (listening_port, auth) = {
+            let state = self.shared.lock().unwrap();
+            (state.port, state.leader_auth.clone())
+        };
+
+        if let Some(auth) = auth {
+            request(&mut connection, auth.into_frame()).await?;
+        }
+
+        let listening_port = listening_port.to_string();
+        request(
+            &mut connection,
+            command(&[b"replconf", b"listening-port", listening_port.as_bytes()]),
+        )
+        .await?;
+
+        let (replid, offset, selected) = db.backlog(|backlog| {
+            (
+                backlog.replid().to_string(),
+                backlog.offset(),
+                backlog.selected(),
+            )
+        });
+        // Continue on the database the stream had selected
+        db.select(selected.unwrap_or(0))?;
+
+        let offset = offset.to_string();
+        let reply = request(
+            &mut connection,
+            command(&[b"psync", replid.as_bytes(), offset.as_bytes()]),
+        )
+        .await?;
+
+        let reply = match reply {
+            Frame::Simple(reply) => reply,
+            frame => return Err(format!("unexpected PSYNC reply: {:?}", frame).into()),
+        };
+
+        let mut words = reply.split(' ');
+        match (words.next(), words.next(), words.next()) {
+            (Some("FULLRESYNC"), Some(replid), Some(offset)) => {
+                let offset = offset.parse().map_err(|_| "invalid FULLRESYNC offset")?;
+
+                // Read the whole snapshot before applying it, the keyspace
+                // is only locked while it is replaced
+                let mut commands = vec![];
+                loop {
+                    match connection.read_frame().await? {
+                        Some(Frame::Simple(end)) if end == SNAPSHOT_END => break,
+                        Some(frame @ Frame::Array(_)) => commands.push(frame),
+                        _ => return Err("expected the keyspace snapshot after FULLRESYNC".into()),
+                    }
+                }
+
+                info!(
+                    "full resync from {}:{}, {} commands at offset {}",
+                    host,
+                    port,
+                    commands.len(),
+                    offset
+                );
+
+                load(db, scripts, commands, replid.to_string(), offset).await?;
+            }
+            (Some("CONTINUE"), replid, None) => {
+                info!("continuing the stream of {}:{}", host, port);
+
+                // The leader was promoted since, its stream goes on under
+                // a new ID
+                if let Some(replid) = replid {
+                    db.backlog(|backlog| backlog.switch_id(replid));
+                }
+            }
+            _ => return Err(format!("unexpected PSYNC reply: {}", reply).into()),
+        }
+
+        self.set_link_up(true);
+
+        let mut ack = time::interval(ACK_INTERVAL);
+
+        loop {
+            tokio::select! {
+                frame = connection.read_frame() => {
+                    let Some(frame) = frame? else {
+                        return Err("connection closed by the leader".into());
+                    };
+
+                    let mut bytes = BytesMut::new();
+                    frame.encode(&mut bytes);
+
+                    let _access = db.shared_access().await;
+                    apply(db, scripts, frame)?;
+                    db.backlog(|backlog| backlog.feed(&bytes));
+                }
+                _ = ack.tick() => {
+                    let offset = db.backlog(|backlog| backlog.offset()).to_string();
+                    let frame = command(&[b"replconf", b"ack", offset.as_bytes()]);
+                    connection.write_frame(&frame).await?;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut state = self.replication.shared.lock().unwrap();
+        state.replicas.remove(&self.id);
+    }
+}
+
+/// Keep a link to the leader at `host:port` up until the task is aborted
+async fn follow(replication: Replication, mut db: Db, scripts: Scripts, host: String, port: u16) {
+    loop {
+        if let Err(err) = replication.sync(&mut db, &scripts, &host, port).await {
+            warn!("replication link to {}:{} failed: {}", host, port, err);
+        }
+
+        replication.set_link_up(false);
+        time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Replace the keyspace with a snapshot sent by the leader, no client sees
+/// it half loaded
+async fn load(
+    db: &mut Db,
+    scripts: &Scripts,
+    commands: Vec<Frame>,
+    replid: String,
+    offset: u64,
+) -> crate::Result<()> {
+    let _access = db.exclusive_access().await;
+
+    db.flush_all(true);
+
+    for frame in commands {
+        apply(db, scripts, frame)?;
+    }
+
+    db.backlog(|backlog| backlog.reset(replid, offset));
+
+    Ok(())
+}
+
+/// Apply a write of the replication stream
+fn apply(db: &mut Db, scripts: &Scripts, frame: Frame) -> crate::Result<()> {
+    let mut parse = Parse::new(frame)?;
+
+    let name = parse.next_string()?.to_lowercase();
+
+    match name.as_str() {
+        "select" => {
+            let index = parse.next_int()? as usize;
+            db.select(index)?;
+            db.backlog(|backlog| backlog.selected = Some(index));
+        }
+        "set" => {
+            let key = parse.next_string()?;
+            let value = parse.next_bytes()?;
+            db.set(key, value);
+        }
+        "del" => {
+            let key = parse.next_string()?;
+            db.delete(&key);
+        }
+        "move" => {
+            let key = parse.next_string()?;
+            let dst = parse.next_int()?;
+            db.move_key(&key, dst as usize)?;
+        }
+        "swapdb" => {
+            let first = parse.next_int()?;
+            let second = parse.next_int()?;
+            db.swap(first as usize, second as usize)?;
+        }
+        "flushdb" => db.flush(true),
+        "flushall" => db.flush_all(true),
+        "function" => match parse.next_string()?.to_lowercase().as_str() {
+            "load" => {
+                let replace = parse.next_string()?.eq_ignore_ascii_case("replace");
+                let code = parse.next_string()?;
+                scripts.load_library(db, &code, replace)?;
+            }
+            "delete" => {
+                // The libraries are copied after the keyspace snapshot, one
+                // deleted in between is gone already
+                let name = parse.next_string()?;
+                let _ = scripts.delete_library(db, &name);
+            }
+            "flush" => scripts.flush_libraries(db)?,
+            other => {
+                return Err(
+                    format!("unexpected 'function {}' in the replication stream", other).into(),
+                );
+            }
+        },
+        "ping" => {}
+        _ => return Err(format!("unexpected '{}' in the replication stream", name).into()),
+    }
+
+    parse.finish()?;
+
+    Ok(())
+}
+
+/// Send a command to the leader and wait for its reply, failing on an error
+async fn request(connection: &mut Connection, frame: Frame) -> crate::Result<Frame> {
+    connection.write_frame(&frame).await?;
+
+    match connection.read_frame().await? {
+        Some(Frame::Error(msg)) => Err(msg.into()),
+        Some(frame) => Ok(frame),
+        None => Err("connection closed by the leader".into()),
+    }
+}
+
+fn command(args: &[&[u8]]) -> Frame {
+    let mut frame = Frame::array();
+    for arg in args {
+        frame.push_bulk(Bytes::copy_from_slice(arg));
+    }
+    frame
+}
+
+fn encode(args: &[&[u8]]) -> BytesMut {
+    let mut out = BytesMut::new();
+    command(args).encode(&mut out);
+    out
+}
+
+/// A random ID of 40 hex characters
+pub(crate) fn new_id() -> String {
+    let mut id = String::with_capacity(48);
+    for _ in 0..3 {
+        let _ = write!(id, "{:016x}", RandomState::new().build_hasher().finish());
+    }
+    id.truncate(40);
+    id
+}
@@ -0,0 +1,976 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use log::{info, warn};
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib, Table, Value, Variadic};
+use sha1::{Digest, Sha1};
+use tokio::runtime::Handle;
+use tokio_util::codec::Decoder;
+
+use crate::acl::{self, Users};
+use crate::cluster::Cluster;
+use crate::cmd::with_code;
+use crate::codec::FrameCodec;
+use crate::replication::Replication;
+use crate::{Command, Connection, Db, Frame, ParseError};
+
+/// How long a script runs before other clients are told the server is busy
+pub const DEFAULT_BUSY_REPLY_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Lua instructions run between two checks for `SCRIPT KILL`
+const HOOK_INSTRUCTIONS: u32 = 10_000;
+
+/// Most bytes of replies buffered between a command run by a script and
+/// the script reading them
+const REPLY_BUFFER: usize = 64 * 1024;
+
+/// Longest a library may run when loaded, it should only register its
+/// functions
+const LOAD_TIMEOUT: Duration = Duration::from_millis(500);
+
+const KILLED: &str = "ERR Script killed by user with SCRIPT KILL...";
+
+/// Flags a function can be registered with
+const FUNCTION_FLAGS: &[&str] = &[
+    "no-writes",
+    "allow-oom",
+    "allow-stale",
+    "no-cluster",
+    "allow-cross-slot-keys",
+];
+
+/// Registry table holding the callbacks of a library while it is loaded
+const CALLBACKS: &str = "library_callbacks";
+
+/// Commands a script can't run, they act on the connection or run scripts
+const NOSCRIPT: &[&str] = &[
+    "auth",
+    "hello",
+    "quit",
+    "multi",
+    "exec",
+    "discard",
+    "psync",
+    "replconf",
+    "replicaof",
+    "asking",
+    "eval",
+    "evalsha",
+    "script",
+    "function",
+    "fcall",
+    "fcall_ro",
+    "subscribe",
+    "unsubscribe",
+    "psubscribe",
+    "punsubscribe",
+];
+
+/// Handle to the scripts loaded, by the SHA1 digest of their body, the
+/// function libraries and the script running. A script holds exclusive
+/// access to the keyspace while it runs, so there is at most one.
+#[derive(Debug, Clone)]
+pub(crate) struct Scripts {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// Bodies of the scripts loaded, by their digest in hex
+    cache: Mutex<HashMap<String, String>>,
+
+    /// Function libraries, by name
+    libraries: Mutex<BTreeMap<String, Library>>,
+
+    /// Where the libraries are saved on every change, in the format of
+    /// `FUNCTION DUMP`
+    functions_file: Option<PathBuf>,
+
+    running: Mutex<Option<Running>>,
+
+    /// A script running longer than this gets other clients a `BUSY` reply
+    busy_threshold: Duration,
+}
+
+#[derive(Debug)]
+struct Running {
+    started: Instant,
+
+    /// Set by `SCRIPT KILL`, the script fails at its next check
+    killed: Arc<AtomicBool>,
+
+    /// Set once the script ran a write command, it can't be killed anymore
+    /// without leaving its writes half done
+    wrote: Arc<AtomicBool>,
+}
+
+/// A library loaded with `FUNCTION LOAD`
+#[derive(Debug, Clone)]
+pub(crate) struct Library {
+    pub(crate) name: String,
+
+    /// Source, metadata line included
+    pub(crate) code: String,
+
+    pub(crate) functions: Vec<Function>,
+}
+
+/// A function registered by a library
+#[derive(Debug, Clone)]
+pub(crate) struct Function {
+    pub(crate) name: String,
+    pub(crate) description: Option<String>,
+    pub(crate) flags: Vec<String>,
+}
+
+/// What `FUNCTION RESTORE` does with the libraries already loaded
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum RestorePolicy {
+    /// Fail if a library of the payload is loaded already
+    Append,
+
+    /// Replace the libraries of the same name
+    Replace,
+
+    /// Delete every library first
+    Flush,
+}
+
+/// Handles of the client running a script, its commands run as that
+/// client's
+pub(crate) struct Caller<'a> {
+    pub(crate) db: &'a Db,
+    pub(crate) users: &'a Users,
+    pub(crate) replication: &'a Replication,
+    pub(crate) cluster: &'a Cluster,
+}
+
+/// What `redis.call` runs commands with, owned by the thread running the
+/// script
+struct Context {
+    db: Db,
+    users: Users,
+    replication: Replication,
+    cluster: Cluster,
+    scripts: Scripts,
+
+    /// Refuse write commands
+    read_only: bool,
+
+    wrote: Arc<AtomicBool>,
+
+    /// Commands write their reply to `dst`, it is read back from `src`
+    dst: Connection,
+    src: Connection,
+
+    runtime: Handle,
+}
+
+impl Scripts {
+    /// The libraries saved in `functions_file` are loaded, if it exists
+    pub(crate) fn new(
+        busy_threshold: Duration,
+        functions_file: Option<PathBuf>,
+    ) -> crate::Result<Scripts> {
+        let mut libraries = BTreeMap::new();
+
+        if let Some(file) = &functions_file
+            && file.exists()
+        {
+            let contents = std::fs::read(file)
+                .map_err(|err| format!("cannot read {}: {}", file.display(), err))?;
+            let loaded = decode_libraries(&contents)
+                .map_err(|err| format!("cannot load {}: {}", file.display(), err))?;
+            install(&mut libraries, loaded, false)?;
+
+            info!(
+                "{} function libraries loaded from {}",
+                libraries.len(),
+                file.display()
+            );
+        }
+
+        Ok(Scripts {
+            shared: Arc::new(Shared {
+                cache: Mutex::new(HashMap::new()),
+                libraries: Mutex::new(libraries),
+                functions_file,
+                running: Mutex::new(None),
+                busy_threshold,
+            }),
+        })
+    }
+
+    /// Compile `body` and keep it, returns its digest
+    pub(crate) fn load(&self, body: &str) -> crate::Result<String> {
+        let lua = sandbox().map_err(|err| format!("ERR {}", err))?;
+        if let Err(err) = lua.load(body).set_name("@user_script").into_function() {
+            return Err(error_reply(&err).into());
+        }
+
+        let sha = sha1_hex(body.as_bytes());
+        self.shared
+            .cache
+            .lock()
+            .unwrap()
+            .insert(sha.clone(), body.to_string());
+
+        Ok(sha)
+    }
+
+    /// Body of the script loaded with digest `sha`
+    pub(crate) fn get(&self, sha: &str) -> Option<String> {
+        let cache = self.shared.cache.lock().unwrap();
+        cache.get(&sha.to_lowercase()).cloned()
+    }
+
+    pub(crate) fn exists(&self, sha: &str) -> bool {
+        self.get(sha).is_some()
+    }
+
+    /// Forget every script loaded
+    pub(crate) fn flush(&self) {
+        self.shared.cache.lock().unwrap().clear();
+    }
+
+    /// Load the library `code` and keep it, returns its name. A library of
+    /// the same name is only replaced with `replace`.
+    pub(crate) fn load_library(&self, db: &Db, code: &str, replace: bool) -> crate::Result<String> {
+        let library = Library::parse(code)?;
+        let name = library.name.clone();
+
+        {
+            let mut libraries = self.shared.libraries.lock().unwrap();
+            install(&mut libraries, vec![library], replace)?;
+
+            db.backlog(|backlog| {
+                backlog.append(None, &[b"function", b"load", b"replace", code.as_bytes()])
+            });
+        }
+
+        self.save()?;
+
+        Ok(name)
+    }
+
+    pub(crate) fn delete_library(&self, db: &Db, name: &str) -> crate::Result<()> {
+        {
+            let mut libraries = self.shared.libraries.lock().unwrap();
+            if libraries.remove(name).is_none() {
+                return Err("ERR Library not found".into());
+            }
+
+            db.backlog(|backlog| backlog.append(None, &[b"function", b"delete", name.as_bytes()]));
+        }
+
+        self.save()
+    }
+
+    /// Delete every library
+    pub(crate) fn flush_libraries(&self, db: &Db) -> crate::Result<()> {
+        {
+            let mut libraries = self.shared.libraries.lock().unwrap();
+            libraries.clear();
+
+            db.backlog(|backlog| backlog.append(None, &[b"function", b"flush"]));
+        }
+
+        self.save()
+    }
+
+    /// The libraries loaded, by name
+    pub(crate) fn libraries(&self) -> Vec<Library> {
+        let libraries = self.shared.libraries.lock().unwrap();
+        libraries.values().cloned().collect()
+    }
+
+    /// The libraries as a payload `FUNCTION RESTORE` accepts: an array of
+    /// their code
+    pub(crate) fn dump(&self) -> Bytes {
+        let libraries = self.shared.libraries.lock().unwrap();
+        encode_libraries(libraries.values())
+    }
+
+    /// Load the libraries of a `FUNCTION DUMP` payload, all of them or none
+    pub(crate) fn restore(
+        &self,
+        db: &Db,
+        payload: &[u8],
+        policy: RestorePolicy,
+    ) -> crate::Result<()> {
+        let restored = decode_libraries(payload)?;
+
+        {
+            let mut libraries = self.shared.libraries.lock().unwrap();
+
+            let mut updated = match policy {
+                RestorePolicy::Flush => BTreeMap::new(),
+                _ => libraries.clone(),
+            };
+            let replace = matches!(policy, RestorePolicy::Replace);
+            install(&mut updated, restored.clone(), replace)?;
+            *libraries = updated;
+
+            db.backlog(|backlog| {
+                if let RestorePolicy::Flush = policy {
+                    backlog.append(None, &[b"function", b"flush"]);
+                }
+                for library in &restored {
+                    let code = library.code.as_bytes();
+                    backlog.append(None, &[b"function", b"load", b"replace", code]);
+                }
+            });
+        }
+
+        self.save()
+    }
+
+    /// Run the function `name` with the `keys` and `args` tables as its
+    /// arguments. With `read_only`, for `FCALL_RO`, only functions flagged
+    /// `no-writes` can run. The caller holds exclusive access to the
+    /// keyspace.
+    pub(crate) async fn fcall(
+        &self,
+        caller: &Caller<'_>,
+        name: String,
+        keys: Vec<String>,
+        args: Vec<Bytes>,
+        read_only: bool,
+    ) -> Frame {
+        let found = {
+            let libraries = self.shared.libraries.lock().unwrap();
+            libraries.values().find_map(|library| {
+                library
+                    .functions
+                    .iter()
+                    .find(|function| function.name == name)
+                    .map(|function| (library.code.clone(), function.clone()))
+            })
+        };
+
+        let Some((code, function)) = found else {
+            return Frame::Error("ERR Function not found".to_string());
+        };
+
+        let no_writes = function.flags.iter().any(|flag| flag == "no-writes");
+        if read_only && !no_writes {
+            return Frame::Error(
+                "ERR Can not execute a script with write flag using *_ro command.".to_string(),
+            );
+        }
+
+        let body = match metadata(&code) {
+            Ok((_, body)) => body,
+            Err(err) => return Frame::Error(err.to_string()),
+        };
+
+        self.run(caller, read_only || no_writes, move |lua| {
+            let (callbacks, _) = run_library(lua, &body)?;
+            let callback: mlua::Function = callbacks.get(name)?;
+
+            callback.call((lua.create_sequence_from(keys)?, to_table(lua, args)?))
+        })
+        .await
+    }
+
+    /// Write the libraries to the functions file, if there is one
+    fn save(&self) -> crate::Result<()> {
+        let Some(file) = &self.shared.functions_file else {
+            return Ok(());
+        };
+
+        let contents = self.dump();
+
+        let tmp = file.with_extension("tmp");
+        std::fs::write(&tmp, contents)
+            .and_then(|()| std::fs::rename(&tmp, file))
+            .map_err(|err| format!("ERR cannot save {}: {}", file.display(), err).into())
+    }
+
+    /// Stop the running script, unless it wrote already
+    pub(crate) fn kill(&self) -> crate::Result<()> {
+        let running = self.shared.running.lock().unwrap();
+
+        match &*running {
+            None => Err("NOTBUSY No scripts in execution right now.".into()),
+            Some(running) if running.wrote.load(Ordering::Relaxed) => Err(
+                "UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.".into(),
+            ),
+            Some(running) => {
+                running.killed.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether a script has been running for longer than the threshold
+    pub(crate) fn busy(&self) -> bool {
+        let running = self.shared.running.lock().unwrap();
+
+        running
+            .as_ref()
+            .is_some_and(|running| running.started.elapsed() > self.shared.busy_threshold)
+    }
+
+    /// Run the script `body` with the `KEYS` and `ARGV` tables set. The
+    /// caller holds exclusive access to the keyspace.
+    pub(crate) async fn eval(
+        &self,
+        caller: &Caller<'_>,
+        body: String,
+        keys: Vec<String>,
+        args: Vec<Bytes>,
+    ) -> Frame {
+        self.run(caller, false, move |lua| {
+            let globals = lua.globals();
+            globals.set("KEYS", lua.create_sequence_from(keys)?)?;
+            globals.set("ARGV", to_table(lua, args)?)?;
+
+            lua.load(&body).set_name("@user_script").eval()
+        })
+        .await
+    }
+
+    /// Run `script` on a thread of its own, it may take long and Lua can't
+    /// yield to the runtime. Its reply is converted to a frame.
+    async fn run<F>(&self, caller: &Caller<'_>, read_only: bool, script: F) -> Frame
+    where
+        F: for<'lua> FnOnce(&'lua Lua) -> mlua::Result<Value<'lua>> + Send + 'static,
+    {
+        let killed = Arc::new(AtomicBool::new(false));
+        let wrote = Arc::new(AtomicBool::new(false));
+
+        *self.shared.running.lock().unwrap() = Some(Running {
+            started: Instant::now(),
+            killed: killed.clone(),
+            wrote: wrote.clone(),
+        });
+
+        let (dst, src) = tokio::io::duplex(REPLY_BUFFER);
+        let context = Context {
+            db: caller.db.clone(),
+            users: caller.users.clone(),
+            replication: caller.replication.clone(),
+            cluster: caller.cluster.session(),
+            scripts: self.clone(),
+            read_only,
+            wrote,
+            dst: Connection::boxed(dst),
+            src: Connection::boxed(src),
+            runtime: Handle::current(),
+        };
+
+        let hook_killed = killed.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let lua = sandbox()?;
+            lua.set_hook(
+                HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTIONS),
+                move |_, _| match hook_killed.load(Ordering::Relaxed) {
+                    true => Err(mlua::Error::RuntimeError(KILLED.to_string())),
+                    false => Ok(()),
+                },
+            );
+            register(&lua, context)?;
+
+            script(&lua).map(to_frame)
+        })
+        .await;
+
+        *self.shared.running.lock().unwrap() = None;
+
+        match result {
+            // The script may have caught the error raised when killed
+            _ if killed.load(Ordering::Relaxed) => Frame::Error(KILLED.to_string()),
+            Ok(Ok(frame)) => frame,
+            Ok(Err(err)) => Frame::Error(error_reply(&err)),
+            Err(err) => {
+                warn!("script failed: {}", err);
+                Frame::Error(format!("ERR Error running script: {}", err))
+            }
+        }
+    }
+}
+
+impl Library {
+    /// Run the library `code` to learn the functions it registers, it may
+    /// not call commands
+    fn parse(code: &str) -> crate::Result<Library> {
+        let (name, body) = metadata(code)?;
+
+        let lua = sandbox().map_err(|err| format!("ERR {}", err))?;
+        let started = Instant::now();
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTIONS),
+            move |_, _| match started.elapsed() > LOAD_TIMEOUT {
+                true => Err(mlua::Error::RuntimeError(
+                    "ERR FUNCTION LOAD timeout".to_string(),
+                )),
+                false => Ok(()),
+            },
+        );
+
+        let functions = match run_library(&lua, &body) {
+            Ok((_, functions)) => functions,
+            Err(err) => return Err(error_reply(&err).into()),
+        };
+
+        if functions.is_empty() {
+            return Err("ERR No functions registered".into());
+        }
+
+        Ok(Library {
+            name,
+            code: code.to_string(),
+            functions,
+        })
+    }
+}
+
+impl Context {
+    /// Run the command `args`, returns its reply
+    fn call(&mut self, args: Vec<Bytes>) -> Frame {
+        let frame = Frame::Array(args.into_iter().map(Frame::Bulk).collect());
+
+        let command = match Command::from_frame(frame) {
+            Ok(Command::Unknown(_)) => {
+                return Frame::Error("ERR Unknown Redis command called from script".to_string());
+            }
+            Ok(command) => command,
+            Err(err) => match err.downcast_ref::<ParseError>() {
+                Some(ParseError::EndOfStream | ParseError::Trailing) => {
+                    return Frame::Error(
+                        "ERR Wrong number of args calling Redis command from script".to_string(),
+                    );
+                }
+                _ => return Frame::Error(with_code(err.to_string())),
+            },
+        };
+
+        if NOSCRIPT.contains(&command.get_name()) {
+            return Frame::Error("ERR This Redis command is not allowed from script".to_string());
+        }
+
+        if acl::is_write(command.get_acl_name()) {
+            if self.read_only {
+                return Frame::Error(
+                    "ERR Write commands are not allowed from read-only scripts.".to_string(),
+                );
+            }
+            if self.replication.read_only() {
+                return Frame::Error(
+                    "READONLY You can't write against a read only replica.".to_string(),
+                );
+            }
+            self.wrote.store(true, Ordering::Relaxed);
+        }
+
+        let Context {
+            db,
+            users,
+            replication,
+            cluster,
+            scripts,
+            dst,
+            src,
+            runtime,
+            ..
+        } = self;
+
+        // The reply is read while it is written, it may not fit the buffer
+        let (applied, reply) = runtime.block_on(async {
+            tokio::join!(
+                async {
+                    command
+                        .apply(db, users, replication, cluster, scripts, dst)
+                        .await?;
+                    Ok::<_, crate::Error>(dst.flush().await?)
+                },
+                src.read_frame()
+            )
+        });
+
+        match (applied, reply) {
+            (Ok(()), Ok(Some(frame))) => frame,
+            (Err(err), _) | (_, Err(err)) => Frame::Error(format!("ERR {}", err)),
+            (Ok(()), Ok(None)) => Frame::Error("ERR command sent no reply".to_string()),
+        }
+    }
+}
+
+/// A Lua state with only the libraries scripts need, without file access
+fn sandbox() -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+
+    for name in ["dofile", "loadfile"] {
+        lua.globals().set(name, Value::Nil)?;
+    }
+
+    Ok(lua)
+}
+
+/// Run the `body` of a library with `redis.register_function` available,
+/// returns the callbacks it registered, by name, and their description
+fn run_library<'lua>(lua: &'lua Lua, body: &str) -> mlua::Result<(Table<'lua>, Vec<Function>)> {
+    let callbacks = lua.create_table()?;
+    lua.set_named_registry_value(CALLBACKS, callbacks.clone())?;
+
+    let registered = Rc::new(RefCell::new(Vec::new()));
+    let functions = registered.clone();
+
+    // Either `(name, callback)` or a table with named fields
+    let register_function = lua.create_function(move |lua, args: Variadic<Value>| {
+        let (name, callback, description, flags) = match &args[..] {
+            [Value::String(name), Value::Function(callback)] => {
+                (name.clone(), callback.clone(), None, Vec::new())
+            }
+            [Value::Table(args)] => {
+                let name = args.get::<_, Option<mlua::String>>("function_name")?;
+                let callback = args.get::<_, Option<mlua::Function>>("callback")?;
+                let description = args.get::<_, Option<String>>("description")?;
+                let flags = args.get::<_, Option<Vec<String>>>("flags")?;
+
+                let (Some(name), Some(callback)) = (name, callback) else {
+                    return Err(mlua::Error::RuntimeError(
+                        "ERR function_name and callback are required".to_string(),
+                    ));
+                };
+                (name, callback, description, flags.unwrap_or_default())
+            }
+            _ => {
+                return Err(mlua::Error::RuntimeError(
+                    "ERR wrong arguments to redis.register_function".to_string(),
+                ));
+            }
+        };
+
+        let name = name.to_str()?.to_string();
+        if !valid_name(&name) {
+            return Err(mlua::Error::RuntimeError(
+                "ERR Function names can only contain letters, numbers, or underscores(_) and must be at least one character long".to_string(),
+            ));
+        }
+        if let Some(flag) = flags.iter().find(|flag| !FUNCTION_FLAGS.contains(&flag.as_str())) {
+            return Err(mlua::Error::RuntimeError(format!(
+                "ERR Unknown flag given: {}",
+                flag
+            )));
+        }
+
+        let callbacks: Table = lua.named_registry_value(CALLBACKS)?;
+        if callbacks.contains_key(name.as_str())? {
+            return Err(mlua::Error::RuntimeError(
+                "ERR Function already exists in the library".to_string(),
+            ));
+        }
+        callbacks.set(name.as_str(), callback)?;
+
+        functions.borrow_mut().push(Function {
+            name,
+            description,
+            flags,
+        });
+
+        Ok(())
+    })?;
+
+    let redis = match lua.globals().get("redis")? {
+        Value::Table(redis) => redis,
+        _ => {
+            let redis = lua.create_table()?;
+            lua.globals().set("redis", redis.clone())?;
+            redis
+        }
+    };
+
+    // Functions are only registered while the library loads
+    redis.set("register_function", register_function)?;
+    lua.load(body).set_name("@user_function").exec()?;
+    redis.set("register_function", Value::Nil)?;
+
+    let functions = registered.take();
+
+    Ok((callbacks, functions))
+}
+
+/// The name in the `#!lua name=<name>` first line of a library, and the
+/// code after it. The line is left empty so line numbers don't change.
+fn metadata(code: &str) -> crate::Result<(String, String)> {
+    let (first, rest) = code.split_once('\n').unwrap_or((code, ""));
+
+    let Some(shebang) = first.strip_prefix("#!") else {
+        return Err("ERR Missing library metadata".into());
+    };
+
+    let mut fields = shebang.split_whitespace();
+    let engine = fields.next().unwrap_or_default();
+    if !engine.eq_ignore_ascii_case("lua") {
+        return Err(format!("ERR Engine '{}' not found", engine).into());
+    }
+
+    let mut name = None;
+    for field in fields {
+        match field.split_once('=') {
+            Some(("name", value)) => name = Some(value.to_string()),
+            _ => return Err(format!("ERR Invalid metadata value given: {}", field).into()),
+        }
+    }
+
+    let Some(name) = name else {
+        return Err("ERR Library name was not given".into());
+    };
+    if !valid_name(&name) {
+        return Err("ERR Library names can only contain letters, numbers, or underscores(_) and must be at least one character long".into());
+    }
+
+    Ok((name, format!("\n{}", rest)))
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Add the `new` libraries, all of them or none. A library of the same name
+/// is only replaced with `replace`, and no two libraries may register a
+/// function of the same name.
+fn install(
+    libraries: &mut BTreeMap<String, Library>,
+    new: Vec<Library>,
+    replace: bool,
+) -> crate::Result<()> {
+    for (i, library) in new.iter().enumerate() {
+        if new[..i].iter().any(|other| other.name == library.name)
+            || (!replace && libraries.contains_key(&library.name))
+        {
+            return Err(format!("ERR Library '{}' already exists", library.name).into());
+        }
+
+        // The libraries left after this one replaced its namesake
+        let others = libraries
+            .values()
+            .filter(|other| !new.iter().any(|new| new.name == other.name))
+            .chain(&new[..i]);
+
+        for other in others {
+            if let Some(function) = library
+                .functions
+                .iter()
+                .find(|function| other.functions.iter().any(|f| f.name == function.name))
+            {
+                return Err(format!("ERR Function {} already exists", function.name).into());
+            }
+        }
+    }
+
+    for library in new {
+        libraries.insert(library.name.clone(), library);
+    }
+
+    Ok(())
+}
+
+fn encode_libraries<'a>(libraries: impl Iterator<Item = &'a Library>) -> Bytes {
+    let codes = libraries
+        .map(|library| Frame::Bulk(Bytes::from(library.code.clone())))
+        .collect();
+
+    let mut out = BytesMut::new();
+    Frame::Array(codes).encode(&mut out);
+    out.freeze()
+}
+
+fn decode_libraries(payload: &[u8]) -> crate::Result<Vec<Library>> {
+    const INVALID: &str = "ERR payload version or checksum are wrong";
+
+    let mut payload = BytesMut::from(payload);
+    let frame = FrameCodec::new()
+        .decode_eof(&mut payload)
+        .map_err(|_| INVALID)?;
+
+    let Some(Frame::Array(codes)) = frame else {
+        return Err(INVALID.into());
+    };
+    if !payload.is_empty() {
+        return Err(INVALID.into());
+    }
+
+    codes
+        .into_iter()
+        .map(|code| match code {
+            Frame::Bulk(code) => {
+                let code = std::str::from_utf8(&code).map_err(|_| INVALID)?;
+                Library::parse(code)
+            }
+            _ => Err(INVALID.into()),
+        })
+        .collect()
+}
+
+/// Set up the `redis` table scripts call commands through
+fn register(lua: &Lua, context: Context) -> mlua::Result<()> {
+    let context = Rc::new(RefCell::new(context));
+    let redis = lua.create_table()?;
+
+    let call_context = context.clone();
+    let call = lua.create_function(move |lua, args: Variadic<Value>| {
+        let args = command_args(lua, args)?;
+        match call_context.borrow_mut().call(args) {
+            Frame::Error(msg) => Err(mlua::Error::RuntimeError(msg)),
+            frame => to_lua(lua, frame),
+        }
+    })?;
+    redis.set("call", call)?;
+
+    // Like `call`, but an error reply is returned as an `err` table
+    let pcall = lua.create_function(move |lua, args: Variadic<Value>| {
+        let args = command_args(lua, args)?;
+        let reply = context.borrow_mut().call(args);
+        to_lua(lua, reply)
+    })?;
+    redis.set("pcall", pcall)?;
+
+    let error_reply =
+        lua.create_function(|lua, msg: mlua::String| lua.create_table_from([("err", msg)]))?;
+    redis.set("error_reply", error_reply)?;
+
+    let status_reply =
+        lua.create_function(|lua, msg: mlua::String| lua.create_table_from([("ok", msg)]))?;
+    redis.set("status_reply", status_reply)?;
+
+    let sha1hex = lua.create_function(|_, data: mlua::String| Ok(sha1_hex(data.as_bytes())))?;
+    redis.set("sha1hex", sha1hex)?;
+
+    lua.globals().set("redis", redis)
+}
+
+/// The arguments of `redis.call`, strings or numbers
+fn command_args(lua: &Lua, args: Variadic<Value>) -> mlua::Result<Vec<Bytes>> {
+    if args.is_empty() {
+        return Err(mlua::Error::RuntimeError(
+            "ERR Please specify at least one argument for this redis lib call".to_string(),
+        ));
+    }
+
+    args.into_iter()
+        .map(|arg| match arg {
+            Value::String(_) | Value::Integer(_) | Value::Number(_) => {
+                let arg = lua.coerce_string(arg)?.unwrap();
+                Ok(Bytes::copy_from_slice(arg.as_bytes()))
+            }
+            _ => Err(mlua::Error::RuntimeError(
+                "ERR Lua redis lib command arguments must be strings or integers".to_string(),
+            )),
+        })
+        .collect()
+}
+
+/* Replies are converted the way redis does it for RESP2: */
+
+/// A reply as seen by a script. Status and error replies become tables
+/// with an `ok` or `err` field, a missing value becomes `false`.
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    let value = match frame {
+        Frame::Integer(n) => Value::Integer(n as mlua::Integer),
+        Frame::Boolean(b) => Value::Integer(b as mlua::Integer),
+        Frame::Bulk(data) | Frame::Verbatim { data, .. } => {
+            Value::String(lua.create_string(&data)?)
+        }
+        Frame::BigNumber(s) => Value::String(lua.create_string(&s)?),
+        Frame::Double(d) => Value::String(lua.create_string(d.to_string())?),
+        Frame::Simple(s) => Value::Table(lua.create_table_from([("ok", s)])?),
+        Frame::Error(s) => Value::Table(lua.create_table_from([("err", s)])?),
+        Frame::Null => Value::Boolean(false),
+        Frame::Attribute { data, .. } => to_lua(lua, *data)?,
+        Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+            let table = lua.create_table_with_capacity(items.len(), 0)?;
+            for item in items {
+                table.raw_push(to_lua(lua, item)?)?;
+            }
+            Value::Table(table)
+        }
+        Frame::Map(pairs) => {
+            let table = lua.create_table_with_capacity(pairs.len() * 2, 0)?;
+            for (key, value) in pairs {
+                table.raw_push(to_lua(lua, key)?)?;
+                table.raw_push(to_lua(lua, value)?)?;
+            }
+            Value::Table(table)
+        }
+    };
+
+    Ok(value)
+}
+
+/// The reply to a script returning `value`. Numbers are truncated to
+/// integers, a table is an array up to its first `nil` unless it has an
+/// `ok` or `err` field.
+fn to_frame(value: Value) -> Frame {
+    match value {
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Integer(n) => Frame::Integer(n),
+        Value::Number(n) => Frame::Integer(n as i64),
+        Value::String(s) => Frame::Bulk(Bytes::copy_from_slice(s.as_bytes())),
+        Value::Table(table) => table_frame(table),
+        _ => Frame::Null,
+    }
+}
+
+fn table_frame(table: Table) -> Frame {
+    if let Ok(Value::String(msg)) = table.raw_get("err") {
+        return Frame::Error(msg.to_string_lossy().into_owned());
+    }
+    if let Ok(Value::String(msg)) = table.raw_get("ok") {
+        return Frame::Simple(msg.to_string_lossy().into_owned());
+    }
+
+    let items = table
+        .sequence_values::<Value>()
+        .map_while(Result::ok)
+        .map(to_frame)
+        .collect();
+
+    Frame::Array(items)
+}
+
+fn to_table(lua: &Lua, values: Vec<Bytes>) -> mlua::Result<Table<'_>> {
+    let table = lua.create_table_with_capacity(values.len(), 0)?;
+    for value in values {
+        table.raw_push(lua.create_string(&value)?)?;
+    }
+    Ok(table)
+}
+
+/// The error reply of a failed script. An error reply of a command the
+/// script called is passed on as it is.
+fn error_reply(err: &mlua::Error) -> String {
+    let msg = match err {
+        mlua::Error::CallbackError { cause, .. } => return error_reply(cause),
+        mlua::Error::SyntaxError { message, .. } => {
+            format!("Error compiling script (new function): {}", message)
+        }
+        mlua::Error::RuntimeError(msg) => msg.clone(),
+        err => err.to_string(),
+    };
+
+    // An error reply is a single line, the Lua stack traceback is dropped
+    let msg = msg.lines().next().unwrap_or_default().to_string();
+
+    with_code(msg)
+}
+
+fn sha1_hex(data: &[u8]) -> String {
+    let mut hex = String::with_capacity(40);
+    for byte in Sha1::digest(data) {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
@@ -1,20 +1,178 @@
 use log::{debug, error, info};
+use rustls::ServerConfig;
 use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
 
-use crate::{Command, Connection, Db, DbDropGuard};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::acl::{self, Users};
+use crate::cluster::Cluster;
+use crate::cmd::{self, Auth, Psync};
+use crate::frame::{self, Frame, Limits};
+use crate::pubsub::{Message, Subscription};
+use crate::replication::Replication;
+use crate::script::Scripts;
+use crate::{
+    Command, Connection, DEFAULT_BACKLOG_SIZE, DEFAULT_BUSY_REPLY_THRESHOLD, DEFAULT_DATABASES, Db,
+    DbDropGuard, KeyspaceEvents, tls,
+};
+
+/// Server configuration
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Number of logical databases, selectable with `SELECT`
+    pub databases: usize,
+
+    /// Bounds on what a client may send
+    pub limits: Limits,
+
+    /// Password clients must `AUTH` with before running commands, it
+    /// belongs to the `default` user
+    pub requirepass: Option<String>,
+
+    /// File listing users and their permissions, one `user` line each
+    pub aclfile: Option<PathBuf>,
+
+    /// Also accept TLS connections, on a port of their own
+    pub tls: Option<TlsConfig>,
+
+    /// Start as a follower of the leader at this host and port
+    pub replicaof: Option<(String, u16)>,
+
+    /// Refuse writes from clients while following a leader
+    pub replica_read_only: bool,
+
+    /// User a follower authenticates to its leader as, `default` if unset
+    pub masteruser: Option<String>,
+
+    /// Password a follower authenticates to its leader with
+    pub masterauth: Option<String>,
+
+    /// Bytes of the replication stream kept so a follower that reconnects
+    /// can continue without a full resync
+    pub repl_backlog_size: usize,
+
+    /// Run as a cluster node, serving the hash slots assigned to it
+    pub cluster_enabled: bool,
+
+    /// File the cluster state is kept in, so a restarted node keeps its ID
+    /// and slots
+    pub cluster_config_file: Option<PathBuf>,
+
+    /// A script running longer than this gets other clients a `BUSY` reply
+    /// until it ends or `SCRIPT KILL` stops it
+    pub busy_reply_threshold: Duration,
+
+    /// File the function libraries are kept in, so they survive a restart
+    pub functions_file: Option<PathBuf>,
+
+    /// Keyspace events published to the `__keyspace@<db>__` and
+    /// `__keyevent@<db>__` channels
+    pub notify_keyspace_events: KeyspaceEvents,
+}
+
+/// TLS listener configuration, certificates and keys are PEM files
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// Port TLS connections are accepted on, on the same address as plain
+    /// ones
+    pub port: u16,
+
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+
+    /// Ask clients for a certificate signed by this CA
+    pub ca_cert_file: Option<PathBuf>,
+
+    /// With a CA set, refuse clients without a certificate. Otherwise a
+    /// certificate is only verified when one is presented.
+    pub auth_clients: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            databases: DEFAULT_DATABASES,
+            limits: Limits::default(),
+            requirepass: None,
+            aclfile: None,
+            tls: None,
+            replicaof: None,
+            replica_read_only: true,
+            masteruser: None,
+            masterauth: None,
+            repl_backlog_size: DEFAULT_BACKLOG_SIZE,
+            cluster_enabled: false,
+            cluster_config_file: None,
+            busy_reply_threshold: DEFAULT_BUSY_REPLY_THRESHOLD,
+            functions_file: None,
+            notify_keyspace_events: KeyspaceEvents::default(),
+        }
+    }
+}
 
 /// Server listener
 #[derive(Debug)]
 struct Listener {
     listener: TcpListener,
-    db_holder: DbDropGuard,
+
+    /// Set for a TLS listener, accepted connections start with a handshake
+    tls: Option<Arc<ServerConfig>>,
+
+    db: Db,
+    limits: Limits,
+    users: Users,
+    replication: Replication,
+    cluster: Cluster,
+    scripts: Scripts,
 }
 
 /// Per-connection handler
 #[derive(Debug)]
 struct Handler {
     connection: Connection,
+
+    /// Shared keyspace, the handle also tracks the database this connection
+    /// has selected
     db: Db,
+
+    /// Commands queued since `MULTI`, `None` outside a transaction
+    transaction: Option<Vec<Command>>,
+
+    /// A command sent since `MULTI` was refused, `EXEC` discards the
+    /// transaction
+    transaction_failed: bool,
+
+    /// Shared user table, the handle also tracks the user this connection
+    /// authenticated as. Commands other than `AUTH`, `HELLO` and `QUIT` are
+    /// refused until it does.
+    users: Users,
+
+    /// Leader followed and followers served
+    replication: Replication,
+
+    /// Slot table, the handle also tracks whether this connection sent
+    /// `ASKING`
+    cluster: Cluster,
+
+    /// Lua scripts loaded and the one running
+    scripts: Scripts,
+
+    /// Channels and patterns this connection receives the messages of,
+    /// `None` until it subscribes to one
+    subscription: Option<Subscription>,
+
+    /// Address of the peer, reported for the followers connected
+    peer: SocketAddr,
+
+    /// Port a follower told it accepts clients on, with `REPLCONF`
+    replica_port: Option<u16>,
+
+    /// `QUIT` was received, the connection is closed after the reply
+    closing: bool,
 }
 
 impl Listener {
@@ -22,15 +180,47 @@ impl Listener {
         info!("accepting inbound connections");
 
         loop {
-            let socket = self.listener.accept().await?;
+            let (socket, peer) = self.listener.accept().await?;
 
-            let mut handler = Handler {
-                connection: Connection::new(socket.0),
-                // get a clone of shared db
-                db: self.db_holder.db(),
-            };
+            let tls = self.tls.clone();
+            let limits = self.limits.clone();
+            // get a clone of shared db
+            let db = self.db.clone();
+            let users = self.users.session();
+            let replication = self.replication.clone();
+            let cluster = self.cluster.session();
+            let scripts = self.scripts.clone();
 
             tokio::spawn(async move {
+                // The handshake runs in the connection's task so a slow peer
+                // does not hold up accepting others
+                let mut connection = match tls {
+                    Some(config) => match TlsAcceptor::from(config).accept(socket).await {
+                        Ok(stream) => Connection::boxed(stream),
+                        Err(err) => {
+                            debug!("TLS handshake failed: {}", err);
+                            return;
+                        }
+                    },
+                    None => Connection::boxed(socket),
+                };
+                connection.set_limits(limits);
+
+                let mut handler = Handler {
+                    connection,
+                    db,
+                    transaction: None,
+                    transaction_failed: false,
+                    users,
+                    replication,
+                    cluster,
+                    scripts,
+                    subscription: None,
+                    peer,
+                    replica_port: None,
+                    closing: false,
+                };
+
                 if let Err(err) = handler.run().await {
                     error!("connection failed: {:?}", err);
                 }
@@ -43,31 +233,448 @@ impl Handler {
     async fn run(&mut self) -> crate::Result<()> {
         // TODO: we need exit if the connection is closed
         loop {
-            let maybe_frame = self.connection.read_frame().await?;
+            let maybe_frame = tokio::select! {
+                maybe_frame = self.connection.read_frame() => maybe_frame,
+                message = recv(&mut self.subscription) => {
+                    let Some(message) = message else {
+                        return Err("subscriber fell behind the messages published".into());
+                    };
+                    self.connection.write_frame(&message.into_frame()).await?;
+                    continue;
+                }
+            };
 
             // If `None` is returned, the stream is closed.
-            let frame = match maybe_frame {
+            let frame = match self.check_frame(maybe_frame).await? {
                 Some(frame) => frame,
                 None => return Ok(()),
             };
 
-            debug!("received frame: {:?}", frame);
+            self.execute(frame).await?;
+
+            // A pipelining client sends many requests at once, run every one
+            // already buffered before flushing so the batch costs one write
+            while !self.closing {
+                let maybe_frame = self.connection.read_buffered_frame();
 
-            let command = Command::from_frame(frame)?;
+                match self.check_frame(maybe_frame).await? {
+                    Some(frame) => self.execute(frame).await?,
+                    None => break,
+                }
+            }
 
-            debug!("command: {}", command.get_name());
+            self.connection.flush().await?;
 
-            command.apply(&mut self.db, &mut self.connection).await?;
+            if self.closing {
+                return Ok(());
+            }
         }
     }
+
+    /// Pass a read frame through, if the peer broke the protocol tell it why
+    /// before hanging up
+    async fn check_frame(
+        &mut self,
+        maybe_frame: crate::Result<Option<Frame>>,
+    ) -> crate::Result<Option<Frame>> {
+        match maybe_frame {
+            Ok(maybe_frame) => Ok(maybe_frame),
+            Err(err) => {
+                if let Some(err) = err.downcast_ref::<frame::Error>() {
+                    // Worded the way redis replies, like `ERR Protocol
+                    // error: invalid bulk length`
+                    let msg = err.to_string();
+                    let reason = msg.strip_prefix("protocol error; ").unwrap_or(&msg);
+                    let response = Frame::Error(format!("ERR Protocol error: {}", reason));
+                    // also flushes the replies of the batch so far
+                    let _ = self.connection.write_frame(&response).await;
+                }
+
+                Err(err)
+            }
+        }
+    }
+
+    async fn execute(&mut self, frame: Frame) -> crate::Result<()> {
+        debug!("received frame: {:?}", frame);
+
+        let name = match &frame {
+            // redis ignores an empty command
+            Frame::Array(args) if args.is_empty() => return Ok(()),
+            Frame::Array(args) => args[0].to_string().to_lowercase(),
+            _ => String::new(),
+        };
+
+        // A command that doesn't parse is refused on its own, the rest of
+        // the batch still runs
+        let command = match Command::from_frame(frame) {
+            Ok(command) => command,
+            Err(err) => return self.reject(&cmd::parse_error_reply(&name, &err)).await,
+        };
+
+        debug!("command: {}", command.get_name());
+
+        match &command {
+            Command::Auth(auth) => return self.authenticate(auth).await,
+            Command::Quit(_) => {
+                self.closing = true;
+                return self.reply_ok().await;
+            }
+            Command::Hello(hello) => match hello.credentials() {
+                Some(auth) => {
+                    let username = auth.username().unwrap_or("default");
+                    if let Err(err) = self.users.authenticate(username, auth.password()) {
+                        return self.reply_error(&err.to_string()).await;
+                    }
+                }
+                None if !self.users.authenticated() => {
+                    return self.reject(
+                        "NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time",
+                    ).await;
+                }
+                None => {}
+            },
+            _ if !self.users.authenticated() => {
+                return self.reject("NOAUTH Authentication required.").await;
+            }
+            _ => {}
+        }
+
+        // Checked here as well as when applied, since the commands below are
+        // applied by the handler itself or queued. An unknown command is
+        // refused as such whoever runs it.
+        if !matches!(command, Command::Unknown(_))
+            && let Err(err) =
+                self.users
+                    .check(command.get_acl_name(), &command.keys(), &command.channels())
+        {
+            return self.reject(&err.to_string()).await;
+        }
+
+        // A RESP2 client can't tell replies from the messages pushed to it,
+        // once subscribed it may only change its subscriptions
+        if self.subscription.is_some()
+            && self.connection.protocol() == 2
+            && !matches!(
+                command,
+                Command::Subscribe(_)
+                    | Command::Unsubscribe(_)
+                    | Command::PSubscribe(_)
+                    | Command::PUnsubscribe(_)
+                    | Command::Ping(_)
+                    | Command::Quit(_)
+            )
+        {
+            let msg = format!(
+                "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                command.get_name()
+            );
+            return self.reject(&msg).await;
+        }
+
+        // A script running for long holds the keyspace, only `SCRIPT KILL`
+        // gets through until it ends
+        if self.scripts.busy() && !matches!(&command, Command::Script(cmd) if cmd.is_kill()) {
+            return self
+                .reject("BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE.")
+                .await;
+        }
+
+        if self.replication.read_only() && acl::is_write(command.get_acl_name()) {
+            return self
+                .reject("READONLY You can't write against a read only replica.")
+                .await;
+        }
+
+        match (command, &mut self.transaction) {
+            (Command::ReplConf(replconf), _) => {
+                if let Some(port) = replconf.listening_port() {
+                    self.replica_port = Some(port);
+                }
+                self.reply_ok().await
+            }
+            (Command::Psync(psync), _) => self.serve_replica(psync).await,
+            (
+                command @ (Command::Subscribe(_)
+                | Command::Unsubscribe(_)
+                | Command::PSubscribe(_)
+                | Command::PUnsubscribe(_)),
+                _,
+            ) => self.subscribe(command).await,
+            (Command::Multi(_), Some(_)) => {
+                self.reply_error("ERR MULTI calls can not be nested").await
+            }
+            (Command::Multi(_), None) => {
+                self.transaction = Some(vec![]);
+                self.transaction_failed = false;
+                self.reply_ok().await
+            }
+            (Command::Exec(_), Some(_)) if self.transaction_failed => {
+                self.transaction = None;
+                self.reply_error("EXECABORT Transaction discarded because of previous errors.")
+                    .await
+            }
+            (Command::Exec(_), Some(_)) => self.exec().await,
+            (Command::Discard(_), Some(_)) => {
+                self.transaction = None;
+                self.reply_ok().await
+            }
+            (Command::Unknown(cmd), Some(_)) => self.reject(&cmd.error()).await,
+            (Command::Exec(_), None) => self.reply_error("ERR EXEC without MULTI").await,
+            (Command::Discard(_), None) => self.reply_error("ERR DISCARD without MULTI").await,
+            (command, Some(queued)) => {
+                queued.push(command);
+                let response = Frame::Simple("QUEUED".to_string());
+                Ok(self.connection.feed_frame(&response).await?)
+            }
+            (command, None) => self.apply(command).await,
+        }
+    }
+
+    /// Run a command holding the access to the keyspace it needs. A script
+    /// runs with no other command in between, `SCRIPT` doesn't wait so a
+    /// script running for long can be killed.
+    async fn apply(&mut self, command: Command) -> crate::Result<()> {
+        let _shared;
+        let _exclusive;
+        match &command {
+            Command::Script(_) => {}
+            Command::Eval(_) | Command::EvalSha(_) | Command::FCall(_) => {
+                _exclusive = self.db.exclusive_access().await;
+            }
+            _ => _shared = self.db.shared_access().await,
+        }
+
+        command
+            .apply(
+                &mut self.db,
+                &self.users,
+                &self.replication,
+                &mut self.cluster,
+                &self.scripts,
+                &mut self.connection,
+            )
+            .await
+    }
+
+    /// Run the queued commands with no other command running in between,
+    /// their replies are sent as one array
+    async fn exec(&mut self) -> crate::Result<()> {
+        let queued = self.transaction.take().unwrap_or_default();
+
+        let _access = self.db.exclusive_access().await;
+
+        self.connection.feed_array_len(queued.len()).await?;
+
+        for command in queued {
+            command
+                .apply(
+                    &mut self.db,
+                    &self.users,
+                    &self.replication,
+                    &mut self.cluster,
+                    &self.scripts,
+                    &mut self.connection,
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Turn the connection into a follower's, it receives the replication
+    /// stream until it hangs up
+    async fn serve_replica(&mut self, psync: Psync) -> crate::Result<()> {
+        self.closing = true;
+
+        // Replies to the commands pipelined before go out first
+        self.connection.flush().await?;
+
+        let port = self.replica_port.unwrap_or(self.peer.port());
+
+        self.replication
+            .serve(
+                &self.db,
+                &self.scripts,
+                &mut self.connection,
+                &psync,
+                self.peer.ip(),
+                port,
+            )
+            .await
+    }
+
+    /// Change the channels or patterns this connection receives the
+    /// messages of, every one changed is confirmed with a reply of its own
+    async fn subscribe(&mut self, command: Command) -> crate::Result<()> {
+        let subscription = self
+            .subscription
+            .get_or_insert_with(|| self.db.pubsub().subscription());
+
+        let replies = match command {
+            Command::Subscribe(cmd) => cmd
+                .into_channels()
+                .into_iter()
+                .map(|channel| subscription.subscribe(channel))
+                .collect(),
+            Command::PSubscribe(cmd) => cmd
+                .into_patterns()
+                .into_iter()
+                .map(|pattern| subscription.psubscribe(pattern))
+                .collect(),
+            Command::Unsubscribe(cmd) => {
+                let mut channels = cmd.into_channels();
+                if channels.is_empty() {
+                    channels = subscription.channels();
+                }
+
+                match channels.is_empty() {
+                    true => vec![subscription.reply("unsubscribe", None)],
+                    false => channels
+                        .into_iter()
+                        .map(|channel| subscription.unsubscribe(channel))
+                        .collect(),
+                }
+            }
+            Command::PUnsubscribe(cmd) => {
+                let mut patterns = cmd.into_patterns();
+                if patterns.is_empty() {
+                    patterns = subscription.patterns();
+                }
+
+                match patterns.is_empty() {
+                    true => vec![subscription.reply("punsubscribe", None)],
+                    false => patterns
+                        .into_iter()
+                        .map(|pattern| subscription.punsubscribe(pattern))
+                        .collect(),
+                }
+            }
+            _ => vec![],
+        };
+
+        if subscription.count() == 0 {
+            self.subscription = None;
+        }
+
+        for reply in replies {
+            self.connection.feed_frame(&reply).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn authenticate(&mut self, auth: &Auth) -> crate::Result<()> {
+        if auth.username().is_none() && !self.users.default_has_password() {
+            return self
+                .reply_error("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?")
+                .await;
+        }
+
+        let username = auth.username().unwrap_or("default");
+
+        match self.users.authenticate(username, auth.password()) {
+            Ok(()) => self.reply_ok().await,
+            Err(err) => self.reply_error(&err.to_string()).await,
+        }
+    }
+
+    async fn reply_ok(&mut self) -> crate::Result<()> {
+        let response = Frame::Simple("OK".to_string());
+        Ok(self.connection.feed_frame(&response).await?)
+    }
+
+    /// Refuse a command, failing the transaction it was sent in
+    async fn reject(&mut self, msg: &str) -> crate::Result<()> {
+        if self.transaction.is_some() {
+            self.transaction_failed = true;
+        }
+
+        self.reply_error(msg).await
+    }
+
+    async fn reply_error(&mut self, msg: &str) -> crate::Result<()> {
+        let response = Frame::Error(msg.to_string());
+        Ok(self.connection.feed_frame(&response).await?)
+    }
+}
+
+/// The next message published to the channels of `subscription`, never
+/// ready for a connection that didn't subscribe
+async fn recv(subscription: &mut Option<Subscription>) -> Option<Message> {
+    match subscription {
+        Some(subscription) => subscription.recv().await,
+        None => std::future::pending().await,
+    }
 }
 
-/// Run the tiny-redis server
-pub async fn run(listener: TcpListener) {
+/// Run the tiny-redis server, failing if the users file or the TLS
+/// certificates can't be loaded
+pub async fn run(listener: TcpListener, config: Config) -> crate::Result<()> {
+    let users = Users::new(config.requirepass.as_deref(), config.aclfile)?;
+    let db_holder = DbDropGuard::new(
+        config.databases,
+        config.repl_backlog_size,
+        config.notify_keyspace_events,
+    );
+
+    // The same credentials are used towards the leader and the other
+    // cluster nodes
+    let leader_auth = config
+        .masterauth
+        .map(|password| Auth::new(config.masteruser, password.into()));
+
+    let addr = listener.local_addr()?;
+    let replication = Replication::new(addr.port(), config.replica_read_only, leader_auth.clone());
+    let cluster = Cluster::new(
+        config.cluster_enabled,
+        addr.ip().to_string(),
+        addr.port(),
+        config.cluster_config_file,
+        leader_auth,
+    )?;
+    let scripts = Scripts::new(config.busy_reply_threshold, config.functions_file)?;
+
+    if let Some((host, port)) = config.replicaof {
+        replication.replicate(&db_holder.db(), &scripts, host, port);
+    }
+
     let mut server = Listener {
         listener,
-        db_holder: DbDropGuard::new(),
+        tls: None,
+        db: db_holder.db(),
+        limits: config.limits.clone(),
+        users: users.clone(),
+        replication: replication.clone(),
+        cluster: cluster.clone(),
+        scripts: scripts.clone(),
+    };
+
+    let Some(tls) = config.tls else {
+        return server.run().await;
+    };
+
+    let tls_config = tls::server_config(
+        &tls.cert_file,
+        &tls.key_file,
+        tls.ca_cert_file.as_deref(),
+        tls.auth_clients,
+    )?;
+
+    let ip = server.listener.local_addr()?.ip();
+
+    let mut tls_server = Listener {
+        listener: TcpListener::bind((ip, tls.port)).await?,
+        tls: Some(tls_config),
+        db: db_holder.db(),
+        limits: config.limits,
+        users,
+        replication,
+        cluster,
+        scripts,
     };
 
-    let _ = server.run().await;
+    tokio::try_join!(server.run(), tls_server.run())?;
+
+    Ok(())
 }
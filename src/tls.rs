@@ -0,0 +1,87 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+
+/// Server side TLS settings. Clients are asked for a certificate signed by
+/// `ca_cert_file` when it is set.
+pub(crate) fn server_config(
+    cert_file: &Path,
+    key_file: &Path,
+    ca_cert_file: Option<&Path>,
+    auth_clients: bool,
+) -> crate::Result<Arc<ServerConfig>> {
+    let builder = ServerConfig::builder();
+
+    let builder = match ca_cert_file {
+        Some(ca_cert_file) => {
+            let verifier = WebPkiClientVerifier::builder(Arc::new(root_store(ca_cert_file)?));
+            let verifier = if auth_clients {
+                verifier.build()?
+            } else {
+                verifier.allow_unauthenticated().build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let config = builder.with_single_cert(load_certs(cert_file)?, load_key(key_file)?)?;
+
+    Ok(Arc::new(config))
+}
+
+/// Client side TLS settings, the server certificate must be signed by
+/// `ca_cert_file`. `identity` is the certificate and key presented to a
+/// server that verifies clients.
+pub(crate) fn client_config(
+    ca_cert_file: &Path,
+    identity: Option<(&Path, &Path)>,
+) -> crate::Result<Arc<ClientConfig>> {
+    let builder = ClientConfig::builder().with_root_certificates(root_store(ca_cert_file)?);
+
+    let config = match identity {
+        Some((cert_file, key_file)) => {
+            builder.with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)?
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(Arc::new(config))
+}
+
+fn root_store(path: &Path) -> crate::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+/// Every certificate of a PEM file
+fn load_certs(path: &Path) -> crate::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(open(path)?);
+
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}", path.display()).into());
+    }
+
+    Ok(certs)
+}
+
+/// The first private key of a PEM file
+fn load_key(path: &Path) -> crate::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(open(path)?);
+
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| format!("no private key found in {}", path.display()).into())
+}
+
+fn open(path: &Path) -> crate::Result<File> {
+    File::open(path).map_err(|err| format!("cannot open {}: {}", path.display(), err).into())
+}
@@ -0,0 +1,343 @@
+use bytes::Bytes;
+use std::any::type_name;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::str::{self, FromStr};
+
+use crate::frame::Frame;
+
+/// Conversion of a reply into a Rust value, used by the client to return
+/// typed results
+pub trait FromFrame: Sized {
+    fn from_frame(frame: Frame) -> crate::Result<Self>;
+}
+
+/// Conversion of a Rust value into command arguments
+pub trait ToArgs {
+    /// Append the arguments for this value to `out`
+    fn write_args(&self, out: &mut Vec<Bytes>);
+
+    /// Collect the arguments for this value
+    fn to_args(&self) -> Vec<Bytes> {
+        let mut out = vec![];
+        self.write_args(&mut out);
+        out
+    }
+}
+
+/// Error for a reply that cannot be turned into a `T`
+fn incompatible<T>(frame: &Frame) -> crate::Error {
+    let kind = match frame {
+        Frame::Simple(_) => "simple string",
+        Frame::Error(_) => "error",
+        Frame::Integer(_) => "integer",
+        Frame::Bulk(_) => "bulk string",
+        Frame::Null => "nil",
+        Frame::Array(_) => "array",
+        Frame::Map(_) => "map",
+        Frame::Set(_) => "set",
+        Frame::Double(_) => "double",
+        Frame::Boolean(_) => "boolean",
+        Frame::BigNumber(_) => "big number",
+        Frame::Verbatim { .. } => "verbatim string",
+        Frame::Attribute { .. } => "attribute",
+        Frame::Push(_) => "push",
+    };
+
+    format!(
+        "response type not compatible: cannot convert {} reply `{}` to {}",
+        kind,
+        frame,
+        type_name::<T>()
+    )
+    .into()
+}
+
+/// Parse the textual form of a reply, for numbers sent as strings
+fn parse_text<T: FromStr>(frame: &Frame, text: &[u8]) -> crate::Result<T> {
+    str::from_utf8(text)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| incompatible::<T>(frame))
+}
+
+impl FromFrame for Frame {
+    fn from_frame(frame: Frame) -> crate::Result<Frame> {
+        Ok(frame)
+    }
+}
+
+impl FromFrame for () {
+    fn from_frame(_frame: Frame) -> crate::Result<()> {
+        Ok(())
+    }
+}
+
+impl FromFrame for Bytes {
+    fn from_frame(frame: Frame) -> crate::Result<Bytes> {
+        match frame {
+            Frame::Bulk(data) | Frame::Verbatim { data, .. } => Ok(data),
+            Frame::Simple(s) | Frame::BigNumber(s) => Ok(Bytes::from(s)),
+            Frame::Integer(n) => Ok(Bytes::from(n.to_string())),
+            Frame::Double(n) => Ok(Bytes::from(n.to_string())),
+            frame => Err(incompatible::<Bytes>(&frame)),
+        }
+    }
+}
+
+impl FromFrame for String {
+    fn from_frame(frame: Frame) -> crate::Result<String> {
+        match frame {
+            Frame::Simple(s) | Frame::BigNumber(s) => Ok(s),
+            Frame::Bulk(ref data) | Frame::Verbatim { ref data, .. } => str::from_utf8(data)
+                .map(|s| s.to_string())
+                .map_err(|_| incompatible::<String>(&frame)),
+            Frame::Integer(n) => Ok(n.to_string()),
+            Frame::Double(n) => Ok(n.to_string()),
+            frame => Err(incompatible::<String>(&frame)),
+        }
+    }
+}
+
+macro_rules! from_frame_for_number {
+    ($($t:ty),*) => {
+        $(
+            impl FromFrame for $t {
+                fn from_frame(frame: Frame) -> crate::Result<$t> {
+                    match frame {
+                        Frame::Integer(n) => {
+                            <$t>::try_from(n).map_err(|_| incompatible::<$t>(&frame))
+                        }
+                        Frame::Boolean(b) => Ok(b as $t),
+                        Frame::Bulk(ref data) => parse_text(&frame, data),
+                        Frame::Simple(ref s) | Frame::BigNumber(ref s) => {
+                            parse_text(&frame, s.as_bytes())
+                        }
+                        frame => Err(incompatible::<$t>(&frame)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_frame_for_number!(
+    i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize
+);
+
+macro_rules! from_frame_for_float {
+    ($($t:ty),*) => {
+        $(
+            impl FromFrame for $t {
+                fn from_frame(frame: Frame) -> crate::Result<$t> {
+                    match frame {
+                        Frame::Double(n) => Ok(n as $t),
+                        Frame::Integer(n) => Ok(n as $t),
+                        Frame::Bulk(ref data) => parse_text(&frame, data),
+                        Frame::Simple(ref s) | Frame::BigNumber(ref s) => {
+                            parse_text(&frame, s.as_bytes())
+                        }
+                        frame => Err(incompatible::<$t>(&frame)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_frame_for_float!(f32, f64);
+
+impl FromFrame for bool {
+    fn from_frame(frame: Frame) -> crate::Result<bool> {
+        match frame {
+            Frame::Boolean(b) => Ok(b),
+            Frame::Integer(0) => Ok(false),
+            Frame::Integer(1) => Ok(true),
+            Frame::Simple(ref s) if s == "OK" => Ok(true),
+            Frame::Bulk(ref data) if &data[..] == b"0" => Ok(false),
+            Frame::Bulk(ref data) if &data[..] == b"1" => Ok(true),
+            frame => Err(incompatible::<bool>(&frame)),
+        }
+    }
+}
+
+impl<T: FromFrame> FromFrame for Option<T> {
+    fn from_frame(frame: Frame) -> crate::Result<Option<T>> {
+        match frame {
+            Frame::Null => Ok(None),
+            frame => T::from_frame(frame).map(Some),
+        }
+    }
+}
+
+impl<T: FromFrame> FromFrame for Vec<T> {
+    fn from_frame(frame: Frame) -> crate::Result<Vec<T>> {
+        match frame {
+            Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+                items.into_iter().map(T::from_frame).collect()
+            }
+            Frame::Null => Ok(vec![]),
+            frame => Err(incompatible::<Vec<T>>(&frame)),
+        }
+    }
+}
+
+impl<K: FromFrame + Eq + Hash, V: FromFrame> FromFrame for HashMap<K, V> {
+    fn from_frame(frame: Frame) -> crate::Result<HashMap<K, V>> {
+        match frame {
+            Frame::Map(pairs) => pairs
+                .into_iter()
+                .map(|(k, v)| Ok((K::from_frame(k)?, V::from_frame(v)?)))
+                .collect(),
+            // RESP2 sends maps as flat arrays of key value pairs
+            Frame::Array(items) if items.len() % 2 == 0 => {
+                let mut map = HashMap::with_capacity(items.len() / 2);
+                let mut items = items.into_iter();
+
+                while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                    map.insert(K::from_frame(k)?, V::from_frame(v)?);
+                }
+
+                Ok(map)
+            }
+            Frame::Null => Ok(HashMap::new()),
+            frame => Err(incompatible::<HashMap<K, V>>(&frame)),
+        }
+    }
+}
+
+macro_rules! from_frame_for_tuple {
+    ($len:expr => $($name:ident),+) => {
+        impl<$($name: FromFrame),+> FromFrame for ($($name,)+) {
+            fn from_frame(frame: Frame) -> crate::Result<($($name,)+)> {
+                match frame {
+                    Frame::Array(items) if items.len() == $len => {
+                        let mut items = items.into_iter();
+
+                        Ok(($($name::from_frame(items.next().unwrap())?,)+))
+                    }
+                    frame => Err(incompatible::<($($name,)+)>(&frame)),
+                }
+            }
+        }
+    };
+}
+
+from_frame_for_tuple!(1 => A);
+from_frame_for_tuple!(2 => A, B);
+from_frame_for_tuple!(3 => A, B, C);
+from_frame_for_tuple!(4 => A, B, C, D);
+from_frame_for_tuple!(5 => A, B, C, D, E);
+from_frame_for_tuple!(6 => A, B, C, D, E, F);
+from_frame_for_tuple!(7 => A, B, C, D, E, F, G);
+from_frame_for_tuple!(8 => A, B, C, D, E, F, G, H);
+
+impl ToArgs for Bytes {
+    fn write_args(&self, out: &mut Vec<Bytes>) {
+        out.push(self.clone());
+    }
+}
+
+impl ToArgs for str {
+    fn write_args(&self, out: &mut Vec<Bytes>) {
+        out.push(Bytes::copy_from_slice(self.as_bytes()));
+    }
+}
+
+impl ToArgs for String {
+    fn write_args(&self, out: &mut Vec<Bytes>) {
+        self.as_str().write_args(out);
+    }
+}
+
+macro_rules! to_args_with_display {
+    ($($t:ty),*) => {
+        $(
+            impl ToArgs for $t {
+                fn write_args(&self, out: &mut Vec<Bytes>) {
+                    out.push(Bytes::from(self.to_string()));
+                }
+            }
+        )*
+    };
+}
+
+to_args_with_display!(
+    i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64, bool
+);
+
+impl<T: ToArgs + ?Sized> ToArgs for &T {
+    fn write_args(&self, out: &mut Vec<Bytes>) {
+        (**self).write_args(out);
+    }
+}
+
+impl<T: ToArgs> ToArgs for [T] {
+    fn write_args(&self, out: &mut Vec<Bytes>) {
+        for item in self {
+            item.write_args(out);
+        }
+    }
+}
+
+impl<T: ToArgs> ToArgs for Vec<T> {
+    fn write_args(&self, out: &mut Vec<Bytes>) {
+        self.as_slice().write_args(out);
+    }
+}
+
+impl<T: ToArgs> ToArgs for Option<T> {
+    fn write_args(&self, out: &mut Vec<Bytes>) {
+        if let Some(value) = self {
+            value.write_args(out);
+        }
+    }
+}
+
+macro_rules! to_args_for_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: ToArgs),+> ToArgs for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn write_args(&self, out: &mut Vec<Bytes>) {
+                let ($($name,)+) = self;
+                $($name.write_args(out);)+
+            }
+        }
+    };
+}
+
+to_args_for_tuple!(A);
+to_args_for_tuple!(A, B);
+to_args_for_tuple!(A, B, C);
+to_args_for_tuple!(A, B, C, D);
+to_args_for_tuple!(A, B, C, D, E);
+to_args_for_tuple!(A, B, C, D, E, F);
+to_args_for_tuple!(A, B, C, D, E, F, G);
+to_args_for_tuple!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_integers_convert() {
+        assert_eq!(i64::from_frame(Frame::Integer(-1)).unwrap(), -1);
+        assert_eq!(i8::from_frame(Frame::Integer(-128)).unwrap(), -128);
+        assert_eq!(f64::from_frame(Frame::Integer(-2)).unwrap(), -2.0);
+        assert_eq!(String::from_frame(Frame::Integer(-3)).unwrap(), "-3");
+    }
+
+    #[test]
+    fn out_of_range_integers_are_rejected() {
+        assert!(u64::from_frame(Frame::Integer(-1)).is_err());
+        assert!(u8::from_frame(Frame::Integer(256)).is_err());
+        assert!(bool::from_frame(Frame::Integer(2)).is_err());
+    }
+
+    #[test]
+    fn numbers_sent_as_strings_convert() {
+        let frame = Frame::Bulk(Bytes::from_static(b"-42"));
+        assert_eq!(i64::from_frame(frame).unwrap(), -42);
+        assert!(u32::from_frame(Frame::Simple("x".into())).is_err());
+    }
+}
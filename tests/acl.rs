@@ -0,0 +1,120 @@
+mod common;
+
+use common::{Raw, assert_error, start, text};
+use std::net::SocketAddr;
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+
+/// Create `alice` with `rules` and connect as her
+async fn connect_as_alice(addr: SocketAddr, rules: &[&str]) -> Raw {
+    let mut admin = Raw::connect(addr).await;
+    let mut args = vec!["ACL", "SETUSER", "alice", "on", ">secret"];
+    args.extend(rules);
+    assert_eq!(text(&admin.call(&args).await), "OK");
+
+    let mut conn = Raw::connect(addr).await;
+    assert_eq!(text(&conn.call(&["AUTH", "alice", "secret"]).await), "OK");
+    conn
+}
+
+#[tokio::test]
+async fn subscribe_commands_are_checked() {
+    let addr = start(Config::default()).await;
+    let mut conn = connect_as_alice(addr, &["+get", "allchannels"]).await;
+
+    for args in [
+        &["SUBSCRIBE", "news"][..],
+        &["PSUBSCRIBE", "*"],
+        &["UNSUBSCRIBE"],
+        &["PUNSUBSCRIBE"],
+    ] {
+        let reply = conn.call(args).await;
+        assert_error(&reply, "NOPERM User alice has no permissions to run");
+    }
+}
+
+#[tokio::test]
+async fn channels_are_checked() {
+    let addr = start(Config::default()).await;
+    let mut conn = connect_as_alice(addr, &["+@pubsub", "&news.*"]).await;
+
+    let reply = conn.call(&["PUBLISH", "secret", "x"]).await;
+    assert_error(&reply, "NOPERM No permissions to access a channel");
+    let reply = conn.call(&["SUBSCRIBE", "news.a", "secret"]).await;
+    assert_error(&reply, "NOPERM No permissions to access a channel");
+    let reply = conn.call(&["PSUBSCRIBE", "*"]).await;
+    assert_error(&reply, "NOPERM No permissions to access a channel");
+
+    let reply = conn.call(&["PUBLISH", "news.a", "x"]).await;
+    assert!(matches!(reply, Frame::Integer(0)), "{:?}", reply);
+    let reply = conn.call(&["SUBSCRIBE", "news.a"]).await;
+    assert!(
+        matches!(reply, Frame::Array(_) | Frame::Push(_)),
+        "{:?}",
+        reply
+    );
+}
+
+#[tokio::test]
+async fn denied_commands_abort_the_transaction() {
+    let addr = start(Config::default()).await;
+    let mut conn = connect_as_alice(addr, &["+@transaction", "+set", "~*"]).await;
+
+    assert_eq!(text(&conn.call(&["MULTI"]).await), "OK");
+    assert_eq!(text(&conn.call(&["SET", "k", "v"]).await), "QUEUED");
+    assert_error(&conn.call(&["GET", "k"]).await, "NOPERM");
+    assert_error(&conn.call(&["EXEC"]).await, "EXECABORT");
+}
+
+#[tokio::test]
+async fn disabled_users_are_logged_out() {
+    let addr = start(Config::default()).await;
+    let mut conn = connect_as_alice(addr, &["+@all", "~*"]).await;
+    assert_eq!(text(&conn.call(&["PING"]).await), "PONG");
+
+    let mut admin = Raw::connect(addr).await;
+    assert_eq!(
+        text(&admin.call(&["ACL", "SETUSER", "alice", "off"]).await),
+        "OK"
+    );
+
+    assert_error(&conn.call(&["PING"]).await, "NOAUTH");
+    let reply = conn.call(&["AUTH", "alice", "secret"]).await;
+    assert_error(&reply, "WRONGPASS");
+}
+
+#[tokio::test]
+async fn long_key_patterns_do_not_stall_the_server() {
+    let addr = start(Config::default()).await;
+    let pattern = format!("~{}", "*a".repeat(500));
+    let mut conn = connect_as_alice(addr, &["+@all", &pattern]).await;
+
+    let key = "a".repeat(100_000) + "b";
+    assert_error(&conn.call(&["GET", &key]).await, "NOPERM");
+}
+
+#[tokio::test]
+async fn requirepass_protects_a_default_user_missing_from_the_aclfile() {
+    let file = std::env::temp_dir().join(format!("tiny-redis-users-{}.acl", std::process::id()));
+    std::fs::write(&file, "user alice on >secret +@all ~*\n").unwrap();
+
+    let config = Config {
+        requirepass: Some("pw".to_string()),
+        aclfile: Some(file.clone()),
+        ..Config::default()
+    };
+    let addr = start(config).await;
+
+    let mut conn = Raw::connect(addr).await;
+    assert_error(&conn.call(&["GET", "k"]).await, "NOAUTH");
+    assert_eq!(text(&conn.call(&["AUTH", "pw"]).await), "OK");
+
+    // Loading the file again keeps the password
+    assert_eq!(text(&conn.call(&["ACL", "LOAD"]).await), "OK");
+    let mut conn = Raw::connect(addr).await;
+    assert_error(&conn.call(&["GET", "k"]).await, "NOAUTH");
+    assert_error(&conn.call(&["AUTH", "wrong"]).await, "WRONGPASS");
+    assert_eq!(text(&conn.call(&["AUTH", "pw"]).await), "OK");
+
+    std::fs::remove_file(&file).unwrap();
+}
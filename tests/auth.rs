@@ -0,0 +1,71 @@
+mod common;
+
+use common::{Raw, assert_error, start, text};
+use tiny_redis::client::ClientBuilder;
+use tiny_redis::server::Config;
+
+fn protected() -> Config {
+    Config {
+        requirepass: Some("pw".to_string()),
+        ..Config::default()
+    }
+}
+
+#[tokio::test]
+async fn commands_need_authentication() {
+    let addr = start(protected()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    assert_error(&conn.call(&["GET", "k"]).await, "NOAUTH");
+    assert_error(&conn.call(&["AUTH", "wrong"]).await, "WRONGPASS");
+    assert_error(&conn.call(&["AUTH", "nobody", "pw"]).await, "WRONGPASS");
+    assert_error(&conn.call(&["GET", "k"]).await, "NOAUTH");
+
+    assert_eq!(text(&conn.call(&["AUTH", "pw"]).await), "OK");
+    assert_eq!(text(&conn.call(&["SET", "k", "v"]).await), "OK");
+
+    // A failed attempt keeps the connection authenticated
+    assert_error(&conn.call(&["AUTH", "wrong"]).await, "WRONGPASS");
+    assert_eq!(text(&conn.call(&["GET", "k"]).await), "v");
+
+    let mut conn = Raw::connect(addr).await;
+    assert_eq!(text(&conn.call(&["AUTH", "default", "pw"]).await), "OK");
+    assert_eq!(text(&conn.call(&["GET", "k"]).await), "v");
+}
+
+#[tokio::test]
+async fn quit_works_before_authenticating() {
+    let addr = start(protected()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    assert_eq!(text(&conn.call(&["QUIT"]).await), "OK");
+    assert!(conn.read().await.is_none());
+}
+
+#[tokio::test]
+async fn auth_without_a_password_configured_is_an_error() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    let reply = conn.call(&["AUTH", "pw"]).await;
+    assert_error(
+        &reply,
+        "ERR AUTH <password> called without any password configured",
+    );
+    assert_eq!(text(&conn.call(&["PING"]).await), "PONG");
+}
+
+#[tokio::test]
+async fn clients_authenticate_with_their_password() {
+    let addr = start(protected()).await;
+
+    let mut client = ClientBuilder::new(addr)
+        .password("pw")
+        .connect()
+        .await
+        .unwrap();
+    client.set("k", "v").await.unwrap();
+
+    let result = ClientBuilder::new(addr).password("wrong").connect().await;
+    assert!(result.unwrap_err().to_string().starts_with("WRONGPASS"));
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use std::process::Command;
+use tiny_redis::server::Config;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn default_tests_run_against_tiny_redis() {
+    let addr = common::start(Config::default()).await;
+
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new(env!("CARGO_BIN_EXE_tiny-redis-benchmark"))
+            .args(["--port", &addr.port().to_string()])
+            .args(["-n", "200", "-c", "2", "--csv"])
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("skipped"), "{}", stderr);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let tests: Vec<&str> = stdout
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split(',').next())
+        .collect();
+    assert_eq!(tests, ["\"PING\"", "\"SET\"", "\"GET\""]);
+}
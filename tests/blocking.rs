@@ -0,0 +1,99 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::thread;
+use tiny_redis::blocking::Client;
+use tiny_redis::client::{Cmd, Pipeline};
+use tiny_redis::server::Config;
+use tokio::runtime::Runtime;
+
+/// Start a server on its own runtime, the blocking client can't run on one
+fn start() -> (Runtime, SocketAddr) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let addr = rt.block_on(common::start(Config::default()));
+    (rt, addr)
+}
+
+#[test]
+fn commands_match_the_async_client() {
+    let (_rt, addr) = start();
+    let mut client = Client::connect(addr).unwrap();
+
+    client.set("a", 1).unwrap();
+    client.set("b", "two").unwrap();
+    let values: Vec<Option<String>> = client.mget(&["a", "b", "c"]).unwrap();
+    assert_eq!(values, [Some("1".into()), Some("two".into()), None]);
+
+    let value: String = client.query(Cmd::new("GET").arg("b")).unwrap();
+    assert_eq!(value, "two");
+    let size: u64 = client.cmd("DBSIZE").query().unwrap();
+    assert_eq!(size, 2);
+
+    assert_eq!(client.del(&["a", "b", "c"]).unwrap(), 2);
+
+    let mut pipeline = Pipeline::new();
+    pipeline.set("k", "v").get("k");
+    let ((), value): ((), String) = client.query_pipeline(&pipeline).unwrap();
+    assert_eq!(value, "v");
+}
+
+#[test]
+fn subscriber_receives_messages() {
+    let (_rt, addr) = start();
+
+    let mut subscriber = Client::connect(addr).unwrap().subscribe(&["news"]).unwrap();
+    subscriber.psubscribe(&["weather.*"]).unwrap();
+    assert_eq!(subscriber.count(), 2);
+
+    let publisher = thread::spawn(move || {
+        let mut client = Client::connect(addr).unwrap();
+        for (channel, payload) in [("news", "first"), ("weather.today", "sun")] {
+            let received: u64 = client
+                .cmd("PUBLISH")
+                .arg(channel)
+                .arg(payload)
+                .query()
+                .unwrap();
+            assert_eq!(received, 1);
+        }
+    });
+
+    let message = subscriber.next_message().unwrap();
+    assert_eq!(
+        (&message.channel[..], &message.payload[..]),
+        (&b"news"[..], &b"first"[..])
+    );
+    assert_eq!(message.pattern, None);
+
+    let message = subscriber.next_message().unwrap();
+    assert_eq!(&message.channel[..], b"weather.today");
+    assert_eq!(message.pattern.as_deref(), Some(&b"weather.*"[..]));
+    publisher.join().unwrap();
+
+    subscriber.unsubscribe(&[]).unwrap();
+    subscriber.punsubscribe(&[]).unwrap();
+    assert_eq!(subscriber.count(), 0);
+}
+
+#[test]
+fn subscribe_errors_are_returned() {
+    let (_rt, addr) = start();
+    let mut client = Client::connect(addr).unwrap();
+    client
+        .cmd("ACL")
+        .arg(&["SETUSER", "alice", "on", ">pw", "+@all", "&allowed"][..])
+        .query::<()>()
+        .unwrap();
+    client
+        .cmd("AUTH")
+        .arg("alice")
+        .arg("pw")
+        .query::<()>()
+        .unwrap();
+
+    let err = client.subscribe(&["secret"]).unwrap_err();
+    assert!(err.to_string().starts_with("NOPERM"), "{}", err);
+}
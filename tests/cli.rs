@@ -0,0 +1,79 @@
+mod common;
+
+use common::{Raw, start, text};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::process::{Command, Output, Stdio};
+use tiny_redis::server::Config;
+
+/// Run `tiny-redis-cli --pipe` against `addr` with `input` on stdin
+async fn pipe(addr: SocketAddr, input: Vec<u8>) -> Output {
+    tokio::task::spawn_blocking(move || {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tiny-redis-cli"))
+            .args(["--port", &addr.port().to_string(), "--pipe"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        child.stdin.take().unwrap().write_all(&input).unwrap();
+        child.wait_with_output().unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pipe_mode_sends_every_command() {
+    let addr = start(Config::default()).await;
+
+    // More commands than fit one batch, inline and RESP mixed
+    let mut input = vec![];
+    for i in 0..3000 {
+        writeln!(input, "SET key:{} {}", i, i).unwrap();
+    }
+    input.extend_from_slice(b"*3\r\n$3\r\nSET\r\n$4\r\nresp\r\n$5\r\nvalue\r\n");
+    // The last command may lack a newline
+    input.extend_from_slice(b"SET last \"with space\"");
+
+    let output = pipe(addr, input).await;
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("errors: 0, replies: 3002"), "{}", stdout);
+
+    let mut conn = Raw::connect(addr).await;
+    assert_eq!(text(&conn.call(&["GET", "key:2999"]).await), "2999");
+    assert_eq!(text(&conn.call(&["GET", "resp"]).await), "value");
+    assert_eq!(text(&conn.call(&["GET", "last"]).await), "with space");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pipe_mode_reports_errors() {
+    let addr = start(Config::default()).await;
+
+    let output = pipe(addr, b"SET a 1\nNOPE\nGET a\n".to_vec()).await;
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("errors: 1, replies: 3"), "{}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ERR unknown command"), "{}", stderr);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pipe_mode_reads_replies_while_writing() {
+    let addr = start(Config::default()).await;
+
+    let mut conn = Raw::connect(addr).await;
+    let big = "x".repeat(64 * 1024);
+    assert_eq!(text(&conn.call(&["SET", "big", &big]).await), "OK");
+
+    // Far more reply bytes than the socket buffers hold
+    let input = "GET big\n".repeat(500).into_bytes();
+
+    let output = pipe(addr, input).await;
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("errors: 0, replies: 500"), "{}", stdout);
+}
@@ -0,0 +1,399 @@
+mod common;
+
+use common::start;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tiny_redis::client::{
+    Client, ClientBuilder, Cmd, MultiplexedClient, Pipeline, Pool, PoolConfig, ReconnectPolicy,
+};
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// Forwards connections to a server, and can cut them all at once
+struct Proxy {
+    addr: SocketAddr,
+    links: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Proxy {
+    async fn start(server: SocketAddr) -> Proxy {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let links = Arc::new(Mutex::new(vec![]));
+
+        let accepted = links.clone();
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                let link = tokio::spawn(async move {
+                    let mut server = TcpStream::connect(server).await.unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+                });
+                accepted.lock().unwrap().push(link);
+            }
+        });
+
+        Proxy { addr, links }
+    }
+
+    /// Drop every connection made so far, new ones are still accepted
+    fn cut(&self) {
+        for link in self.links.lock().unwrap().drain(..) {
+            link.abort();
+        }
+    }
+}
+
+/// A server that accepts connections and never answers
+async fn silent() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut sockets = vec![];
+        while let Ok((socket, _)) = listener.accept().await {
+            sockets.push(socket);
+        }
+    });
+
+    addr
+}
+
+fn reconnect() -> ReconnectPolicy {
+    ReconnectPolicy {
+        max_retries: 3,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(50),
+    }
+}
+
+#[tokio::test]
+async fn reconnect_selects_the_database_again() {
+    let server = start(Config::default()).await;
+    let proxy = Proxy::start(server).await;
+
+    let mut client = ClientBuilder::new(proxy.addr)
+        .reconnect(reconnect())
+        .connect()
+        .await
+        .unwrap();
+
+    client.select(1).await.unwrap();
+    client.set("k", "in db 1").await.unwrap();
+
+    proxy.cut();
+
+    // The first request after the cut reconnects, and is sent again
+    let value: Option<String> = client.get("k").await.unwrap();
+    assert_eq!(value.as_deref(), Some("in db 1"));
+
+    // A database selected with an arbitrary command is remembered too
+    client.cmd("SELECT").arg(2).query::<()>().await.unwrap();
+    proxy.cut();
+    let value: Option<String> = client.get("k").await.unwrap();
+    assert_eq!(value, None);
+}
+
+#[tokio::test]
+async fn db_follows_successful_selects() {
+    let server = start(Config::default()).await;
+    let proxy = Proxy::start(server).await;
+
+    let mut client = ClientBuilder::new(proxy.addr)
+        .reconnect(reconnect())
+        .connect()
+        .await
+        .unwrap();
+    assert_eq!(client.db(), 0);
+
+    client.cmd("select").arg(3).query::<()>().await.unwrap();
+    assert_eq!(client.db(), 3);
+    assert!(client.select(1000).await.is_err());
+    assert_eq!(client.db(), 3);
+
+    // What the prompt of the CLI shows after a silent reconnect
+    proxy.cut();
+    client.ping(None).await.unwrap();
+    assert_eq!(client.db(), 3);
+}
+
+fn protected() -> Config {
+    Config {
+        requirepass: Some("pw".to_string()),
+        ..Config::default()
+    }
+}
+
+#[tokio::test]
+async fn pool_connections_use_the_builder_settings() {
+    let addr = start(protected()).await;
+
+    let config = PoolConfig {
+        min_size: 1,
+        max_size: 2,
+        ..PoolConfig::default()
+    };
+    let pool = ClientBuilder::new(addr)
+        .password("pw")
+        .protocol(3)
+        .connect_pool(config)
+        .await
+        .unwrap();
+    assert_eq!(pool.idle(), 1);
+
+    // Both the idle connection and one opened on demand are authenticated
+    let mut first = pool.get().await.unwrap();
+    let mut second = pool.get().await.unwrap();
+    for client in [&mut first, &mut second] {
+        client.set("k", "v").await.unwrap();
+        let hello: Frame = client.query(Cmd::new("HELLO")).await.unwrap();
+        assert!(matches!(hello, Frame::Map(_)), "{:?}", hello);
+    }
+
+    let unauthenticated = tiny_redis::client::Pool::new(addr, PoolConfig::default())
+        .await
+        .unwrap();
+    let mut client = unauthenticated.get().await.unwrap();
+    let err = client.get::<Option<String>>("k").await.unwrap_err();
+    assert!(err.to_string().starts_with("NOAUTH"), "{}", err);
+}
+
+#[tokio::test]
+async fn broken_connections_are_not_returned_to_the_pool() {
+    let config = PoolConfig {
+        min_size: 0,
+        max_size: 1,
+        ..PoolConfig::default()
+    };
+
+    let addr = start(Config::default()).await;
+    let pool = Pool::new(addr, config.clone()).await.unwrap();
+    pool.get().await.unwrap().ping(None).await.unwrap();
+    assert_eq!(pool.idle(), 1);
+
+    // A request given up on still has its reply on the way
+    let addr = silent().await;
+    let pool = Pool::new(addr, config).await.unwrap();
+    let mut client = pool.get().await.unwrap();
+    let request = time::timeout(Duration::from_millis(50), client.ping(None));
+    assert!(request.await.is_err());
+    drop(client);
+    assert_eq!(pool.idle(), 0);
+}
+
+#[tokio::test]
+async fn multiplexed_client_uses_the_builder_settings() {
+    let addr = start(protected()).await;
+
+    let mut admin = ClientBuilder::new(addr)
+        .password("pw")
+        .connect()
+        .await
+        .unwrap();
+    admin
+        .cmd("ACL")
+        .arg(&["SETUSER", "alice", "on", ">secret", "+@all", "~*"][..])
+        .query::<()>()
+        .await
+        .unwrap();
+
+    let client = ClientBuilder::new(addr)
+        .user("alice", "secret")
+        .connect_multiplexed()
+        .await
+        .unwrap();
+    let whoami: String = client.query(Cmd::new("ACL").arg("WHOAMI")).await.unwrap();
+    assert_eq!(whoami, "alice");
+
+    let tasks: Vec<_> = (0..8)
+        .map(|i| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                client.set(&format!("k{}", i), i).await.unwrap();
+                client.get::<i64>(&format!("k{}", i)).await.unwrap()
+            })
+        })
+        .collect();
+    for (i, task) in tasks.into_iter().enumerate() {
+        assert_eq!(task.await.unwrap(), i as i64);
+    }
+}
+
+#[tokio::test]
+async fn unsupported_protocols_are_refused() {
+    let addr = start(Config::default()).await;
+
+    let err = ClientBuilder::new(addr)
+        .protocol(4)
+        .connect()
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "unsupported protocol version 4");
+}
+
+#[tokio::test]
+async fn pipeline_replies_convert_per_command() {
+    let addr = start(Config::default()).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.set("n", 42).get("n").get("missing").cmd("DBSIZE");
+    assert_eq!(pipeline.len(), 4);
+
+    let (_, n, missing, size): ((), i64, Option<String>, u64) =
+        pipeline.query(&mut client).await.unwrap();
+    assert_eq!((n, missing, size), (42, None, 1));
+}
+
+#[tokio::test]
+async fn pipeline_errors_fail_only_their_entry() {
+    let addr = start(Config::default()).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.set("k", "v").cmd("NOPE").get("k");
+
+    let replies = pipeline.execute(&mut client).await.unwrap();
+    assert!(matches!(&replies[0], Ok(Frame::Simple(s)) if s == "OK"));
+    assert!(
+        replies[1]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .starts_with("ERR unknown command")
+    );
+    assert!(matches!(&replies[2], Ok(Frame::Bulk(b)) if b == "v"));
+
+    assert!(pipeline.query::<Vec<Frame>>(&mut client).await.is_err());
+}
+
+#[tokio::test]
+async fn atomic_pipelines_run_as_a_transaction() {
+    let addr = start(Config::default()).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    let mut pipeline = Pipeline::new();
+    pipeline
+        .atomic()
+        .set("a", 1)
+        .set("b", 2)
+        .cmd("MGET")
+        .arg(&["a", "b"][..]);
+
+    let (_, _, values): ((), (), Vec<i64>) = pipeline.query(&mut client).await.unwrap();
+    assert_eq!(values, [1, 2]);
+
+    // A command the server refuses to queue aborts the whole transaction
+    let mut pipeline = Pipeline::new();
+    pipeline.atomic().set("a", 10).cmd("NOPE");
+
+    assert!(pipeline.execute(&mut client).await.is_err());
+    assert_eq!(client.get::<i64>("a").await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn multiplexed_replies_reach_their_caller() {
+    let addr = start(Config::default()).await;
+    let client = MultiplexedClient::connect(addr).await.unwrap();
+
+    let tasks: Vec<_> = (0..100)
+        .map(|i| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                if i % 10 == 0 {
+                    return client.query::<Frame>(Cmd::new("NOPE")).await.is_err();
+                }
+                let key = format!("k{}", i);
+                client.set(&key, i).await.unwrap();
+                client.get::<i64>(&key).await.unwrap() == i
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        assert!(task.await.unwrap());
+    }
+}
+
+#[tokio::test]
+async fn multiplexed_requests_fail_once_the_connection_is_gone() {
+    let server = start(Config::default()).await;
+    let proxy = Proxy::start(server).await;
+
+    let client = MultiplexedClient::connect(proxy.addr).await.unwrap();
+    client.ping(None).await.unwrap();
+
+    proxy.cut();
+
+    assert!(client.ping(None).await.is_err());
+    assert!(client.clone().ping(None).await.is_err());
+}
+
+#[tokio::test]
+async fn requests_give_up_after_the_request_timeout() {
+    let addr = silent().await;
+
+    let mut client = ClientBuilder::new(addr)
+        .request_timeout(Duration::from_millis(50))
+        .connect()
+        .await
+        .unwrap();
+
+    let err = client.ping(None).await.unwrap_err();
+    assert_eq!(err.to_string(), "request timed out");
+}
+
+#[tokio::test]
+async fn connecting_gives_up_after_the_connect_timeout() {
+    let addr = silent().await;
+    let ca = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/ca.pem");
+
+    // The TLS handshake never completes
+    let err = ClientBuilder::new(addr)
+        .tls(ca)
+        .connect_timeout(Duration::from_millis(50))
+        .connect()
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "connect timed out");
+}
+
+#[tokio::test]
+async fn arbitrary_commands_convert_their_replies() {
+    let addr = start(Config::default()).await;
+    let mut client = Client::connect(addr).await.unwrap();
+
+    client
+        .cmd("SET")
+        .arg(("k", 1.5))
+        .query::<()>()
+        .await
+        .unwrap();
+
+    let values: Vec<Option<f64>> = client
+        .cmd("MGET")
+        .arg(vec!["k", "missing"])
+        .query()
+        .await
+        .unwrap();
+    assert_eq!(values, [Some(1.5), None]);
+
+    let reply: Frame = client.query(Cmd::new("DBSIZE")).await.unwrap();
+    assert!(matches!(reply, Frame::Integer(1)));
+
+    let err = client.cmd("NOPE").query::<Frame>().await.unwrap_err();
+    assert!(err.to_string().starts_with("ERR unknown command"));
+
+    // A reply of the wrong shape is an error too
+    assert!(
+        client
+            .cmd("GET")
+            .arg("k")
+            .query::<Vec<String>>()
+            .await
+            .is_err()
+    );
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use common::{Raw, assert_error, start, text};
+use std::net::SocketAddr;
+use tiny_redis::client::ClusterClient;
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+
+fn node() -> Config {
+    Config {
+        cluster_enabled: true,
+        ..Config::default()
+    }
+}
+
+/// Two nodes that met, the first serving slots 0 to 8191 and the second the
+/// rest
+async fn cluster() -> (SocketAddr, SocketAddr) {
+    let a = start(node()).await;
+    let b = start(node()).await;
+
+    let mut conn = Raw::connect(a).await;
+    let reply = conn.call(&["CLUSTER", "ADDSLOTSRANGE", "0", "8191"]).await;
+    assert_eq!(text(&reply), "OK");
+
+    let mut conn = Raw::connect(b).await;
+    let reply = conn
+        .call(&["CLUSTER", "ADDSLOTSRANGE", "8192", "16383"])
+        .await;
+    assert_eq!(text(&reply), "OK");
+
+    let port = a.port().to_string();
+    let reply = conn.call(&["CLUSTER", "MEET", "127.0.0.1", &port]).await;
+    assert_eq!(text(&reply), "OK");
+
+    (a, b)
+}
+
+async fn myid(addr: SocketAddr) -> String {
+    text(&Raw::connect(addr).await.call(&["CLUSTER", "MYID"]).await)
+}
+
+#[tokio::test]
+async fn keys_are_served_by_the_node_owning_their_slot() {
+    let (a, b) = cluster().await;
+    let mut conn = Raw::connect(a).await;
+
+    assert!(matches!(
+        conn.call(&["CLUSTER", "KEYSLOT", "foo"]).await,
+        Frame::Integer(12182)
+    ));
+    assert!(matches!(
+        conn.call(&["CLUSTER", "KEYSLOT", "bar"]).await,
+        Frame::Integer(5061)
+    ));
+
+    assert_eq!(text(&conn.call(&["SET", "bar", "1"]).await), "OK");
+    let reply = conn.call(&["SET", "foo", "1"]).await;
+    assert_eq!(text(&reply), format!("MOVED 12182 {}", b));
+
+    // Keys sharing a hash tag share a slot
+    let reply = conn.call(&["MGET", "{bar}.1", "{bar}.2"]).await;
+    assert!(matches!(reply, Frame::Array(_)), "{:?}", reply);
+    assert_error(&conn.call(&["MGET", "bar", "foo"]).await, "CROSSSLOT");
+
+    let info = text(&conn.call(&["CLUSTER", "INFO"]).await);
+    assert!(info.contains("cluster_state:ok"), "{}", info);
+    assert!(info.contains("cluster_known_nodes:2"), "{}", info);
+}
+
+#[tokio::test]
+async fn slots_being_migrated_redirect_with_ask() {
+    let (a, b) = cluster().await;
+    let (id_a, id_b) = (myid(a).await, myid(b).await);
+
+    let mut conn_a = Raw::connect(a).await;
+    let mut conn_b = Raw::connect(b).await;
+    assert_eq!(text(&conn_a.call(&["SET", "bar", "here"]).await), "OK");
+
+    let reply = conn_b
+        .call(&["CLUSTER", "SETSLOT", "5061", "IMPORTING", &id_a])
+        .await;
+    assert_eq!(text(&reply), "OK");
+    let reply = conn_a
+        .call(&["CLUSTER", "SETSLOT", "5061", "MIGRATING", &id_b])
+        .await;
+    assert_eq!(text(&reply), "OK");
+
+    // Keys still on the source are served there, missing ones are asked for
+    // on the target
+    assert_eq!(text(&conn_a.call(&["GET", "bar"]).await), "here");
+    let reply = conn_a.call(&["GET", "{bar}.moved"]).await;
+    assert_eq!(text(&reply), format!("ASK 5061 {}", b));
+
+    // The target only serves the slot right after ASKING
+    let reply = conn_b.call(&["SET", "{bar}.moved", "1"]).await;
+    assert_eq!(text(&reply), format!("MOVED 5061 {}", a));
+    assert_eq!(text(&conn_b.call(&["ASKING"]).await), "OK");
+    assert_eq!(text(&conn_b.call(&["SET", "{bar}.moved", "1"]).await), "OK");
+    let reply = conn_b.call(&["GET", "{bar}.moved"]).await;
+    assert_eq!(text(&reply), format!("MOVED 5061 {}", a));
+
+    // Once assigned on both nodes the slot belongs to the target
+    for conn in [&mut conn_a, &mut conn_b] {
+        let reply = conn
+            .call(&["CLUSTER", "SETSLOT", "5061", "NODE", &id_b])
+            .await;
+        assert_eq!(text(&reply), "OK");
+    }
+    assert_eq!(text(&conn_b.call(&["GET", "{bar}.moved"]).await), "1");
+    let reply = conn_a.call(&["GET", "bar"]).await;
+    assert_eq!(text(&reply), format!("MOVED 5061 {}", b));
+}
+
+#[tokio::test]
+async fn unassigned_slots_and_disabled_cluster_mode_are_errors() {
+    let addr = start(node()).await;
+    let mut conn = Raw::connect(addr).await;
+    assert_error(&conn.call(&["GET", "k"]).await, "CLUSTERDOWN");
+    assert_error(&conn.call(&["CLUSTER", "ADDSLOTS", "16384"]).await, "ERR");
+
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+    assert_error(&conn.call(&["CLUSTER", "INFO"]).await, "ERR");
+    assert_eq!(text(&conn.call(&["SET", "foo", "1"]).await), "OK");
+}
+
+#[tokio::test]
+async fn cluster_client_routes_by_slot() {
+    let (a, b) = cluster().await;
+    let mut client = ClusterClient::connect(&[a]).await.unwrap();
+
+    client.set("foo", "on b").await.unwrap();
+    client.set("bar", "on a").await.unwrap();
+
+    let mut conn = Raw::connect(b).await;
+    assert_eq!(text(&conn.call(&["GET", "foo"]).await), "on b");
+
+    let values: Vec<Option<String>> = client.mget(&["foo", "missing", "bar"]).await.unwrap();
+    assert_eq!(
+        values,
+        [Some("on b".to_string()), None, Some("on a".to_string())]
+    );
+
+    assert_eq!(client.del(&["foo", "bar", "missing"]).await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn cluster_client_follows_redirects() {
+    let (a, b) = cluster().await;
+    let (id_a, id_b) = (myid(a).await, myid(b).await);
+
+    let mut client = ClusterClient::connect(&[a]).await.unwrap();
+    client.set("bar", "1").await.unwrap();
+
+    let mut conn_a = Raw::connect(a).await;
+    let mut conn_b = Raw::connect(b).await;
+    conn_b
+        .call(&["CLUSTER", "SETSLOT", "5061", "IMPORTING", &id_a])
+        .await;
+    conn_a
+        .call(&["CLUSTER", "SETSLOT", "5061", "MIGRATING", &id_b])
+        .await;
+
+    // A key missing on the source is written to the target after ASK
+    client.set("{bar}.new", "2").await.unwrap();
+    conn_b.call(&["ASKING"]).await;
+    assert_eq!(text(&conn_b.call(&["GET", "{bar}.new"]).await), "2");
+
+    // MOVED once the slot belongs to the target, the old key went with it
+    conn_b.call(&["ASKING"]).await;
+    assert_eq!(text(&conn_b.call(&["SET", "bar", "moved"]).await), "OK");
+    for conn in [&mut conn_a, &mut conn_b] {
+        conn.call(&["CLUSTER", "SETSLOT", "5061", "NODE", &id_b])
+            .await;
+    }
+    let value: String = client.get("bar").await.unwrap();
+    assert_eq!(value, "moved");
+}
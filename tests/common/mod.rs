@@ -0,0 +1,106 @@
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::BytesMut;
+use tiny_redis::codec::FrameCodec;
+use tiny_redis::frame::Frame;
+use tiny_redis::server::{self, Config};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+use tokio_util::codec::Decoder;
+
+/// How long a test waits for a reply before failing
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start a server with `config` on a free local port
+pub async fn start(config: Config) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        server::run(listener, config).await.unwrap();
+    });
+
+    addr
+}
+
+/// A connection speaking raw RESP, for sending what a well behaved client
+/// never would
+pub struct Raw {
+    stream: TcpStream,
+    buffer: BytesMut,
+    codec: FrameCodec,
+}
+
+impl Raw {
+    pub async fn connect(addr: SocketAddr) -> Raw {
+        Raw {
+            stream: TcpStream::connect(addr).await.unwrap(),
+            buffer: BytesMut::new(),
+            codec: FrameCodec::new(),
+        }
+    }
+
+    pub async fn send_raw(&mut self, bytes: &[u8]) {
+        self.stream.write_all(bytes).await.unwrap();
+    }
+
+    /// Send a command made of `args`
+    pub async fn send(&mut self, args: &[&str]) {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(arg.to_string().into()))
+                .collect(),
+        );
+
+        let mut bytes = BytesMut::new();
+        frame.encode(&mut bytes);
+        self.send_raw(&bytes).await;
+    }
+
+    /// The next reply, `None` once the server closed the connection
+    pub async fn read(&mut self) -> Option<Frame> {
+        time::timeout(TIMEOUT, async {
+            loop {
+                if let Some(frame) = self.codec.decode(&mut self.buffer).unwrap() {
+                    return Some(frame);
+                }
+
+                match self.stream.read_buf(&mut self.buffer).await {
+                    Ok(0) | Err(_) => return None,
+                    Ok(_) => {}
+                }
+            }
+        })
+        .await
+        .expect("no reply in time")
+    }
+
+    /// Send a command and read its reply
+    pub async fn call(&mut self, args: &[&str]) -> Frame {
+        self.send(args).await;
+        self.read().await.expect("connection closed")
+    }
+}
+
+/// The text of a simple, bulk or error reply
+pub fn text(frame: &Frame) -> String {
+    match frame {
+        Frame::Simple(s) | Frame::Error(s) => s.clone(),
+        Frame::Bulk(data) => String::from_utf8_lossy(data).into_owned(),
+        frame => panic!("expected a string reply, got {:?}", frame),
+    }
+}
+
+pub fn assert_error(frame: &Frame, prefix: &str) {
+    match frame {
+        Frame::Error(msg) if msg.starts_with(prefix) => {}
+        frame => panic!(
+            "expected an error starting with {:?}, got {:?}",
+            prefix, frame
+        ),
+    }
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{Raw, assert_error, start, text};
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+
+fn integer(frame: Frame) -> i64 {
+    match frame {
+        Frame::Integer(n) => n,
+        frame => panic!("expected an integer reply, got {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn databases_are_separate_per_connection() {
+    let config = Config {
+        databases: 4,
+        ..Config::default()
+    };
+    let addr = start(config).await;
+
+    let mut first = Raw::connect(addr).await;
+    let mut second = Raw::connect(addr).await;
+
+    assert_eq!(text(&first.call(&["SELECT", "1"]).await), "OK");
+    first.call(&["SET", "k", "one"]).await;
+
+    // The other connection is still on database 0
+    assert!(matches!(second.call(&["GET", "k"]).await, Frame::Null));
+    second.call(&["SELECT", "1"]).await;
+    assert_eq!(text(&second.call(&["GET", "k"]).await), "one");
+
+    let reply = first.call(&["SELECT", "4"]).await;
+    assert_error(&reply, "ERR DB index is out of range");
+}
+
+#[tokio::test]
+async fn move_swapdb_and_dbsize() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    conn.call(&["SET", "a", "1"]).await;
+    conn.call(&["SET", "b", "2"]).await;
+    assert_eq!(integer(conn.call(&["DBSIZE"]).await), 2);
+
+    assert_eq!(integer(conn.call(&["MOVE", "a", "1"]).await), 1);
+    assert_eq!(integer(conn.call(&["MOVE", "missing", "1"]).await), 0);
+    assert_error(
+        &conn.call(&["MOVE", "b", "0"]).await,
+        "ERR source and destination",
+    );
+    assert_eq!(integer(conn.call(&["DBSIZE"]).await), 1);
+
+    // Every connection sees the swap at once
+    let mut other = Raw::connect(addr).await;
+    assert_eq!(text(&conn.call(&["SWAPDB", "0", "1"]).await), "OK");
+    assert_eq!(text(&other.call(&["GET", "a"]).await), "1");
+    assert!(matches!(other.call(&["GET", "b"]).await, Frame::Null));
+}
+
+#[tokio::test]
+async fn flushdb_and_flushall() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    for db in ["0", "1", "2"] {
+        conn.call(&["SELECT", db]).await;
+        conn.call(&["SET", "k", db]).await;
+    }
+
+    assert_eq!(text(&conn.call(&["FLUSHDB"]).await), "OK");
+    assert_eq!(integer(conn.call(&["DBSIZE"]).await), 0);
+    conn.call(&["SELECT", "1"]).await;
+    assert_eq!(integer(conn.call(&["DBSIZE"]).await), 1);
+
+    assert_eq!(text(&conn.call(&["FLUSHALL", "ASYNC"]).await), "OK");
+    for db in ["0", "1"] {
+        conn.call(&["SELECT", db]).await;
+        assert_eq!(integer(conn.call(&["DBSIZE"]).await), 0);
+    }
+}
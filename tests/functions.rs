@@ -0,0 +1,163 @@
+mod common;
+
+use bytes::{Bytes, BytesMut};
+use common::{Raw, assert_error, start, text};
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+
+const LIBRARY: &str = "#!lua name=counters
+local function bump(keys, args)
+    local n = tonumber(redis.call('GET', keys[1]) or '0') + tonumber(args[1])
+    redis.call('SET', keys[1], n)
+    return n
+end
+local function peek(keys)
+    return redis.call('GET', keys[1])
+end
+redis.register_function('bump', bump)
+redis.register_function{function_name='peek', callback=peek, flags={'no-writes'}}";
+
+#[tokio::test(flavor = "multi_thread")]
+async fn libraries_load_and_run() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    assert_eq!(
+        text(&conn.call(&["FUNCTION", "LOAD", LIBRARY]).await),
+        "counters"
+    );
+    let reply = conn.call(&["FUNCTION", "LOAD", LIBRARY]).await;
+    assert_error(&reply, "ERR Library 'counters' already exists");
+    let reply = conn.call(&["FUNCTION", "LOAD", "REPLACE", LIBRARY]).await;
+    assert_eq!(text(&reply), "counters");
+
+    let reply = conn.call(&["FCALL", "bump", "1", "n", "5"]).await;
+    assert!(matches!(reply, Frame::Integer(5)), "{:?}", reply);
+    let reply = conn.call(&["FCALL", "bump", "1", "n", "-7"]).await;
+    assert!(matches!(reply, Frame::Integer(-2)), "{:?}", reply);
+
+    assert_eq!(
+        text(&conn.call(&["FCALL_RO", "peek", "1", "n"]).await),
+        "-2"
+    );
+    let reply = conn.call(&["FCALL_RO", "bump", "1", "n", "1"]).await;
+    assert_error(&reply, "ERR Can not execute a script with write flag");
+    assert_error(
+        &conn.call(&["FCALL", "nope", "0"]).await,
+        "ERR Function not found",
+    );
+
+    let reply = conn
+        .call(&["FUNCTION", "LIST", "LIBRARYNAME", "count*", "WITHCODE"])
+        .await;
+    let Frame::Array(libraries) = reply else {
+        panic!("expected an array, got {:?}", reply);
+    };
+    assert_eq!(libraries.len(), 1);
+
+    assert_eq!(
+        text(&conn.call(&["FUNCTION", "DELETE", "counters"]).await),
+        "OK"
+    );
+    let reply = conn.call(&["FUNCTION", "DELETE", "counters"]).await;
+    assert_error(&reply, "ERR Library not found");
+    assert_error(
+        &conn.call(&["FCALL", "bump", "1", "n", "1"]).await,
+        "ERR Function not found",
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invalid_libraries_are_refused() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    let cases = [
+        ("return 1", "ERR Missing library metadata"),
+        ("#!js name=x\nreturn 1", "ERR Engine 'js' not found"),
+        ("#!lua name=empty\nreturn 1", "ERR No functions registered"),
+        (
+            "#!lua name=twice\nredis.register_function('f', function() end)\nredis.register_function('f', function() end)",
+            "ERR Function already exists in the library",
+        ),
+    ];
+
+    for (code, error) in cases {
+        assert_error(&conn.call(&["FUNCTION", "LOAD", code]).await, error);
+    }
+
+    // A function name is unique across libraries
+    assert_eq!(
+        text(&conn.call(&["FUNCTION", "LOAD", LIBRARY]).await),
+        "counters"
+    );
+    let other = LIBRARY.replace("name=counters", "name=others");
+    let reply = conn.call(&["FUNCTION", "LOAD", &other]).await;
+    assert_error(&reply, "ERR Function bump already exists");
+}
+
+/// `FUNCTION RESTORE` with a binary `payload`
+async fn restore(conn: &mut Raw, payload: &Bytes, policy: &[&str]) -> Frame {
+    let mut args = vec![
+        Frame::Bulk("FUNCTION".into()),
+        Frame::Bulk("RESTORE".into()),
+        Frame::Bulk(payload.clone()),
+    ];
+    args.extend(policy.iter().map(|arg| Frame::Bulk(arg.to_string().into())));
+
+    let mut bytes = BytesMut::new();
+    Frame::Array(args).encode(&mut bytes);
+    conn.send_raw(&bytes).await;
+    conn.read().await.unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn libraries_are_dumped_and_restored() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    conn.call(&["FUNCTION", "LOAD", LIBRARY]).await;
+    let Frame::Bulk(payload) = conn.call(&["FUNCTION", "DUMP"]).await else {
+        panic!("expected a payload");
+    };
+
+    assert_eq!(text(&conn.call(&["FUNCTION", "FLUSH"]).await), "OK");
+    assert_error(
+        &conn.call(&["FCALL", "bump", "1", "n", "1"]).await,
+        "ERR Function not found",
+    );
+
+    let reply = restore(&mut conn, &payload, &[]).await;
+    assert_eq!(text(&reply), "OK");
+    let reply = conn.call(&["FCALL", "bump", "1", "n", "1"]).await;
+    assert!(matches!(reply, Frame::Integer(1)), "{:?}", reply);
+
+    // Appending clashes with the loaded library
+    let reply = restore(&mut conn, &payload, &[]).await;
+    assert_error(&reply, "ERR Library 'counters' already exists");
+    let reply = restore(&mut conn, &payload, &["REPLACE"]).await;
+    assert_eq!(text(&reply), "OK");
+
+    let reply = conn.call(&["FUNCTION", "RESTORE", "garbage"]).await;
+    assert_error(&reply, "ERR payload version or checksum are wrong");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn libraries_survive_a_restart() {
+    let file = std::env::temp_dir().join(format!("tiny-redis-functions-{}", std::process::id()));
+    let config = || Config {
+        functions_file: Some(file.clone()),
+        ..Config::default()
+    };
+
+    let addr = start(config()).await;
+    let mut conn = Raw::connect(addr).await;
+    conn.call(&["FUNCTION", "LOAD", LIBRARY]).await;
+
+    let addr = start(config()).await;
+    let mut conn = Raw::connect(addr).await;
+    let reply = conn.call(&["FCALL", "bump", "1", "n", "3"]).await;
+    assert!(matches!(reply, Frame::Integer(3)), "{:?}", reply);
+
+    std::fs::remove_file(&file).unwrap();
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use bytes::BytesMut;
+use common::{Raw, assert_error, start, text};
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+
+/// `commands` encoded back to back, as a pipelining client writes them
+fn batch(commands: &[Vec<String>]) -> BytesMut {
+    let mut bytes = BytesMut::new();
+    for args in commands {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(arg.clone().into()))
+                .collect(),
+        );
+        frame.encode(&mut bytes);
+    }
+    bytes
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(ToString::to_string).collect()
+}
+
+#[tokio::test]
+async fn pipelined_replies_come_back_in_order() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    let mut commands = vec![];
+    for i in 0..1000 {
+        commands.push(args(&["SET", &format!("key:{}", i), &i.to_string()]));
+        commands.push(args(&["GET", &format!("key:{}", i)]));
+    }
+    conn.send_raw(&batch(&commands)).await;
+
+    for i in 0..1000 {
+        assert_eq!(text(&conn.read().await.unwrap()), "OK");
+        assert_eq!(text(&conn.read().await.unwrap()), i.to_string());
+    }
+}
+
+#[tokio::test]
+async fn quit_stops_the_rest_of_the_batch() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    let commands = [
+        args(&["SET", "k", "before"]),
+        args(&["QUIT"]),
+        args(&["SET", "k", "after"]),
+    ];
+    conn.send_raw(&batch(&commands)).await;
+
+    assert_eq!(text(&conn.read().await.unwrap()), "OK");
+    assert_eq!(text(&conn.read().await.unwrap()), "OK");
+    assert!(conn.read().await.is_none());
+
+    let mut conn = Raw::connect(addr).await;
+    assert_eq!(text(&conn.call(&["GET", "k"]).await), "before");
+}
+
+#[tokio::test]
+async fn replies_before_a_protocol_error_are_sent() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    let mut bytes = batch(&[args(&["PING"]), args(&["PING"])]);
+    bytes.extend_from_slice(b"*1\r\n$-5\r\n");
+    conn.send_raw(&bytes).await;
+
+    assert_eq!(text(&conn.read().await.unwrap()), "PONG");
+    assert_eq!(text(&conn.read().await.unwrap()), "PONG");
+    assert_error(&conn.read().await.unwrap(), "ERR Protocol error: ");
+    assert!(conn.read().await.is_none());
+}
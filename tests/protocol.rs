@@ -0,0 +1,73 @@
+mod common;
+
+use common::{Raw, assert_error, start};
+use tiny_redis::frame::{Frame, Limits};
+use tiny_redis::server::Config;
+
+#[tokio::test]
+async fn chained_attributes_get_a_protocol_error() {
+    let addr = start(Config::default()).await;
+
+    let mut conn = Raw::connect(addr).await;
+    conn.send_raw(&b"|0\r\n".repeat(300_000)).await;
+
+    let reply = conn.read().await.unwrap();
+    assert_error(&reply, "ERR Protocol error: too many nested aggregates");
+    assert!(conn.read().await.is_none());
+
+    // The server is still up
+    let mut conn = Raw::connect(addr).await;
+    assert!(matches!(conn.call(&["PING"]).await, Frame::Simple(s) if s == "PONG"));
+}
+
+#[tokio::test]
+async fn limits_get_a_protocol_error_and_a_disconnect() {
+    let config = Config {
+        limits: Limits {
+            max_bulk_len: 16,
+            max_multibulk_len: 8,
+            max_depth: 4,
+            query_buffer_limit: 1024,
+        },
+        ..Config::default()
+    };
+    let addr = start(config).await;
+
+    let cases: [&[u8]; 4] = [
+        b"*1\r\n$17\r\n",
+        b"*9\r\n",
+        b"*1\r\n*1\r\n*1\r\n*1\r\n*1\r\n",
+        &[b'x'; 2048],
+    ];
+
+    for case in cases {
+        let mut conn = Raw::connect(addr).await;
+        conn.send_raw(case).await;
+
+        let reply = conn.read().await.unwrap();
+        assert_error(&reply, "ERR Protocol error: ");
+        assert!(conn.read().await.is_none());
+    }
+}
+
+#[tokio::test]
+async fn inline_commands_work_like_a_telnet_session() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    conn.send_raw(b"PING\r\n\r\nSET greeting \"hello world\"\nGET greeting\r\n")
+        .await;
+    assert!(matches!(conn.read().await, Some(Frame::Simple(s)) if s == "PONG"));
+    assert!(matches!(conn.read().await, Some(Frame::Simple(s)) if s == "OK"));
+    assert!(matches!(conn.read().await, Some(Frame::Bulk(b)) if b == "hello world"));
+
+    // Inline and RESP commands mix on the same connection
+    conn.send_raw(b"*2\r\n$3\r\nGET\r\n$8\r\ngreeting\r\nPING\n")
+        .await;
+    assert!(matches!(conn.read().await, Some(Frame::Bulk(b)) if b == "hello world"));
+    assert!(matches!(conn.read().await, Some(Frame::Simple(s)) if s == "PONG"));
+
+    conn.send_raw(b"GET \"greeting\r\n").await;
+    let reply = conn.read().await.unwrap();
+    assert_error(&reply, "ERR Protocol error: unbalanced quotes");
+}
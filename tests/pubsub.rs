@@ -0,0 +1,107 @@
+mod common;
+
+use common::{Raw, start, text};
+use tiny_redis::KeyspaceEvents;
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+
+/// The strings of an array or push reply, numbers included
+fn texts(frame: Frame) -> Vec<String> {
+    match frame {
+        Frame::Array(items) | Frame::Push(items) => items
+            .iter()
+            .map(|item| match item {
+                Frame::Integer(n) => n.to_string(),
+                item => text(item),
+            })
+            .collect(),
+        frame => panic!("expected an array reply, got {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn messages_reach_channel_and_pattern_subscribers() {
+    let addr = start(Config::default()).await;
+
+    let mut subscriber = Raw::connect(addr).await;
+    let reply = subscriber.call(&["SUBSCRIBE", "news"]).await;
+    assert_eq!(texts(reply), ["subscribe", "news", "1"]);
+    let reply = subscriber.call(&["PSUBSCRIBE", "n*"]).await;
+    assert_eq!(texts(reply), ["psubscribe", "n*", "2"]);
+
+    let mut publisher = Raw::connect(addr).await;
+    let reply = publisher.call(&["PUBLISH", "news", "hi"]).await;
+    assert!(matches!(reply, Frame::Integer(2)), "{:?}", reply);
+
+    let first = texts(subscriber.read().await.unwrap());
+    let second = texts(subscriber.read().await.unwrap());
+    assert_eq!(first, ["message", "news", "hi"]);
+    assert_eq!(second, ["pmessage", "n*", "news", "hi"]);
+
+    let reply = subscriber.call(&["UNSUBSCRIBE"]).await;
+    assert_eq!(texts(reply), ["unsubscribe", "news", "1"]);
+    let reply = publisher.call(&["PUBLISH", "news", "hi"]).await;
+    assert!(matches!(reply, Frame::Integer(1)), "{:?}", reply);
+}
+
+#[tokio::test]
+async fn long_patterns_do_not_crash_the_server() {
+    let addr = start(Config::default()).await;
+    let long = "a".repeat(300_000);
+
+    let mut subscriber = Raw::connect(addr).await;
+    let reply = subscriber.call(&["PSUBSCRIBE", &long]).await;
+    assert_eq!(texts(reply)[0], "psubscribe");
+    subscriber.call(&["PSUBSCRIBE", &"*a".repeat(500)]).await;
+
+    let mut publisher = Raw::connect(addr).await;
+    let reply = publisher.call(&["PUBLISH", &long, "x"]).await;
+    assert!(matches!(reply, Frame::Integer(1)), "{:?}", reply);
+    let reply = publisher
+        .call(&["PUBLISH", &format!("{}b", long), "x"])
+        .await;
+    assert!(matches!(reply, Frame::Integer(0)), "{:?}", reply);
+
+    assert_eq!(text(&publisher.call(&["PING"]).await), "PONG");
+}
+
+#[tokio::test]
+async fn keyspace_events_are_published() {
+    let config = Config {
+        notify_keyspace_events: KeyspaceEvents::parse("KEA").unwrap(),
+        ..Config::default()
+    };
+    let addr = start(config).await;
+
+    let mut subscriber = Raw::connect(addr).await;
+    subscriber.call(&["SUBSCRIBE", "__keyevent@0__:set"]).await;
+    subscriber.call(&["PSUBSCRIBE", "__keyspace@*__:k"]).await;
+
+    let mut conn = Raw::connect(addr).await;
+    assert_eq!(text(&conn.call(&["SET", "k", "v"]).await), "OK");
+    assert_eq!(text(&conn.call(&["GET", "k"]).await), "v");
+    assert!(matches!(conn.call(&["DEL", "k"]).await, Frame::Integer(1)));
+
+    let events = [
+        texts(subscriber.read().await.unwrap()),
+        texts(subscriber.read().await.unwrap()),
+        texts(subscriber.read().await.unwrap()),
+    ];
+    assert!(events.contains(&vec![
+        "pmessage".into(),
+        "__keyspace@*__:k".into(),
+        "__keyspace@0__:k".into(),
+        "set".into()
+    ]));
+    assert!(events.contains(&vec![
+        "message".into(),
+        "__keyevent@0__:set".into(),
+        "k".into()
+    ]));
+    assert!(events.contains(&vec![
+        "pmessage".into(),
+        "__keyspace@*__:k".into(),
+        "__keyspace@0__:k".into(),
+        "del".into()
+    ]));
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use common::{Raw, assert_error, start, text};
+use std::net::{SocketAddr, TcpListener};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+use tokio::time;
+
+/// A server process, killed when dropped
+struct Server {
+    child: Child,
+    addr: SocketAddr,
+}
+
+impl Server {
+    async fn spawn(args: &[&str]) -> Server {
+        // The port is free once the probe listener is dropped
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_tiny-redis-server"))
+            .arg("--port")
+            .arg(port.to_string())
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let server = Server {
+            child,
+            addr: SocketAddr::from(([127, 0, 0, 1], port)),
+        };
+
+        let started = Instant::now();
+        while tokio::net::TcpStream::connect(server.addr).await.is_err() {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "server did not start"
+            );
+            time::sleep(Duration::from_millis(20)).await;
+        }
+
+        server
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Wait for `key` to hold `value` on the server at `conn`
+async fn wait_for(conn: &mut Raw, key: &str, value: &str) {
+    let started = Instant::now();
+    loop {
+        if let Frame::Bulk(data) = conn.call(&["GET", key]).await
+            && data == value.as_bytes()
+        {
+            return;
+        }
+
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "{} never replicated",
+            key
+        );
+        time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn follower_process_receives_the_leader_keyspace() {
+    let leader = Server::spawn(&[]).await;
+
+    let mut conn = Raw::connect(leader.addr).await;
+    let reply = conn
+        .call(&["ACL", "SETUSER", "replicator", "on", ">pw", "+psync"])
+        .await;
+    assert_eq!(text(&reply), "OK");
+    assert_eq!(text(&conn.call(&["SET", "before", "1"]).await), "OK");
+
+    let port = leader.addr.port().to_string();
+    let follower = Server::spawn(&[
+        "--replicaof",
+        "127.0.0.1",
+        &port,
+        "--masteruser",
+        "replicator",
+        "--masterauth",
+        "pw",
+    ])
+    .await;
+
+    let mut replica = Raw::connect(follower.addr).await;
+    wait_for(&mut replica, "before", "1").await;
+
+    // Writes made after the full copy follow through the stream
+    assert_eq!(text(&conn.call(&["SET", "after", "2"]).await), "OK");
+    wait_for(&mut replica, "after", "2").await;
+
+    let reply = replica.call(&["SET", "k", "v"]).await;
+    assert_error(&reply, "READONLY");
+}
+
+#[tokio::test]
+async fn psync_needs_permission() {
+    let addr = start(Config::default()).await;
+
+    let mut admin = Raw::connect(addr).await;
+    admin.call(&["SET", "secret", "s3cr3t"]).await;
+    let reply = admin
+        .call(&[
+            "ACL", "SETUSER", "alice", "on", ">pw", "+@all", "-@admin", "~*",
+        ])
+        .await;
+    assert_eq!(text(&reply), "OK");
+
+    let mut conn = Raw::connect(addr).await;
+    assert_eq!(text(&conn.call(&["AUTH", "alice", "pw"]).await), "OK");
+
+    let reply = conn.call(&["REPLCONF", "listening-port", "1"]).await;
+    assert_error(&reply, "NOPERM");
+    let reply = conn.call(&["PSYNC", "?", "-1"]).await;
+    assert_error(&reply, "NOPERM");
+
+    // The connection stays a client's
+    assert_eq!(text(&conn.call(&["GET", "secret"]).await), "s3cr3t");
+}
+
+#[tokio::test]
+async fn full_resync_sends_the_keyspace_one_command_at_a_time() {
+    let addr = start(Config::default()).await;
+
+    let mut admin = Raw::connect(addr).await;
+    for i in 0..1000 {
+        admin.send(&["SET", &format!("key:{}", i), "value"]).await;
+    }
+    for _ in 0..1000 {
+        admin.read().await.unwrap();
+    }
+    admin.call(&["SELECT", "2"]).await;
+    admin.call(&["SET", "other", "db"]).await;
+
+    let mut conn = Raw::connect(addr).await;
+    let reply = conn.call(&["PSYNC", "?", "-1"]).await;
+    assert!(text(&reply).starts_with("FULLRESYNC "), "{:?}", reply);
+
+    // No frame holds more than one key, however large the keyspace
+    let mut sets = 0;
+    loop {
+        match conn.read().await.unwrap() {
+            Frame::Simple(end) if end == "SNAPSHOTEND" => break,
+            Frame::Array(args) if text(&args[0]) == "set" => {
+                assert_eq!(args.len(), 3);
+                sets += 1;
+            }
+            Frame::Array(_) => {}
+            frame => panic!("unexpected frame in the snapshot {:?}", frame),
+        }
+    }
+    assert_eq!(sets, 1001);
+}
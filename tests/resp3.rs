@@ -0,0 +1,66 @@
+mod common;
+
+use common::{Raw, assert_error, start, text};
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+
+/// The value of `field` in a `HELLO` reply, sent as a map or a flat array
+fn field(reply: &Frame, field: &str) -> Frame {
+    let pairs: Vec<(Frame, Frame)> = match reply {
+        Frame::Map(pairs) => pairs.clone(),
+        Frame::Array(items) => items
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect(),
+        reply => panic!("expected a HELLO reply, got {:?}", reply),
+    };
+
+    pairs
+        .into_iter()
+        .find(|(name, _)| text(name) == field)
+        .map(|(_, value)| value)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn hello_negotiates_the_protocol() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    // RESP2 until asked otherwise
+    let reply = conn.call(&["HELLO"]).await;
+    assert!(matches!(reply, Frame::Array(_)), "{:?}", reply);
+    assert!(matches!(field(&reply, "proto"), Frame::Integer(2)));
+
+    let reply = conn.call(&["HELLO", "3"]).await;
+    assert!(matches!(reply, Frame::Map(_)), "{:?}", reply);
+    assert!(matches!(field(&reply, "proto"), Frame::Integer(3)));
+    assert_eq!(text(&field(&reply, "server")), "tiny-redis");
+
+    assert_error(&conn.call(&["HELLO", "4"]).await, "NOPROTO");
+
+    // Still on RESP3 after the refused switch
+    let reply = conn.call(&["SUBSCRIBE", "news"]).await;
+    assert!(matches!(reply, Frame::Push(_)), "{:?}", reply);
+
+    // A RESP3 client may run commands while subscribed
+    assert_eq!(text(&conn.call(&["SET", "k", "v"]).await), "OK");
+}
+
+#[tokio::test]
+async fn hello_authenticates() {
+    let config = Config {
+        requirepass: Some("pw".to_string()),
+        ..Config::default()
+    };
+    let addr = start(config).await;
+    let mut conn = Raw::connect(addr).await;
+
+    assert_error(&conn.call(&["HELLO", "3"]).await, "NOAUTH");
+    let reply = conn.call(&["HELLO", "3", "AUTH", "default", "bad"]).await;
+    assert_error(&reply, "WRONGPASS");
+
+    let reply = conn.call(&["HELLO", "3", "AUTH", "default", "pw"]).await;
+    assert!(matches!(field(&reply, "proto"), Frame::Integer(3)));
+    assert_eq!(text(&conn.call(&["PING"]).await), "PONG");
+}
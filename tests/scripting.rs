@@ -0,0 +1,66 @@
+mod common;
+
+use common::{Raw, assert_error, start, text};
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn numbers_convert_to_integer_replies() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    let reply = conn.call(&["EVAL", "return -1", "0"]).await;
+    assert!(matches!(reply, Frame::Integer(-1)), "{:?}", reply);
+    let reply = conn.call(&["EVAL", "return -2.7", "0"]).await;
+    assert!(matches!(reply, Frame::Integer(-2)), "{:?}", reply);
+    let reply = conn.call(&["EVAL", "return {1, -1, 'a'}", "0"]).await;
+    let Frame::Array(items) = reply else {
+        panic!("expected an array, got {:?}", reply);
+    };
+    assert!(matches!(
+        items[..],
+        [Frame::Integer(1), Frame::Integer(-1), Frame::Bulk(_)]
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scripts_call_commands_with_keys_and_args() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    let script = "return redis.call('SET', KEYS[1], ARGV[1])";
+    let reply = conn.call(&["EVAL", script, "1", "k", "v"]).await;
+    assert_eq!(text(&reply), "OK");
+    assert_eq!(text(&conn.call(&["GET", "k"]).await), "v");
+
+    let script = "return redis.call('DEL', KEYS[1]) - 2";
+    let reply = conn.call(&["EVAL", script, "1", "k"]).await;
+    assert!(matches!(reply, Frame::Integer(-1)), "{:?}", reply);
+
+    let reply = conn
+        .call(&["EVAL", "return redis.call('NOSUCH')", "0"])
+        .await;
+    assert_error(&reply, "ERR");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scripts_are_cached_by_sha() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    let sha = text(&conn.call(&["SCRIPT", "LOAD", "return ARGV[1]"]).await);
+    assert_eq!(sha.len(), 40);
+
+    let reply = conn.call(&["EVALSHA", &sha, "0", "hi"]).await;
+    assert_eq!(text(&reply), "hi");
+
+    let reply = conn.call(&["SCRIPT", "EXISTS", &sha, "0000"]).await;
+    let Frame::Array(items) = reply else {
+        panic!("expected an array, got {:?}", reply);
+    };
+    assert!(matches!(items[..], [Frame::Integer(1), Frame::Integer(0)]));
+
+    assert_eq!(text(&conn.call(&["SCRIPT", "FLUSH"]).await), "OK");
+    let reply = conn.call(&["EVALSHA", &sha, "0"]).await;
+    assert_error(&reply, "NOSCRIPT");
+}
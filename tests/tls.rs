@@ -0,0 +1,166 @@
+mod common;
+
+use common::start;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use tiny_redis::client::ClientBuilder;
+use tiny_redis::server::{Config, TlsConfig};
+use tokio::net::TcpStream;
+use tokio::time;
+
+/// A certificate or key under `tests/tls`
+fn file(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/tls")
+        .join(name)
+}
+
+/// Start a server presenting `cert`, which asks clients for a certificate
+/// signed by the test CA if `auth_clients` is set. Returns the TLS address.
+async fn start_tls(cert: &str, auth_clients: bool) -> SocketAddr {
+    // The port is free once the probe listener is dropped
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let config = Config {
+        tls: Some(TlsConfig {
+            port,
+            cert_file: file(&format!("{}.pem", cert)),
+            key_file: file(&format!("{}.key", cert)),
+            ca_cert_file: auth_clients.then(|| file("ca.pem")),
+            auth_clients,
+        }),
+        ..Config::default()
+    };
+    start(config).await;
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    for _ in 0..500 {
+        if TcpStream::connect(addr).await.is_ok() {
+            return addr;
+        }
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("TLS listener did not start");
+}
+
+#[tokio::test]
+async fn handshake_with_a_trusted_certificate() {
+    let addr = start_tls("server", false).await;
+
+    // The certificate is valid for the address connected to
+    let mut client = ClientBuilder::new(addr)
+        .tls(file("ca.pem"))
+        .connect()
+        .await
+        .unwrap();
+    assert_eq!(client.ping(None).await.unwrap(), "PONG");
+
+    let mut client = ClientBuilder::new(addr)
+        .tls(file("ca.pem"))
+        .server_name("localhost")
+        .connect()
+        .await
+        .unwrap();
+    client.set("k", "v").await.unwrap();
+    assert_eq!(client.get::<String>("k").await.unwrap(), "v");
+}
+
+#[tokio::test]
+async fn untrusted_server_certificates_are_rejected() {
+    let addr = start_tls("untrusted", false).await;
+
+    let result = ClientBuilder::new(addr).tls(file("ca.pem")).connect().await;
+    assert!(result.is_err());
+
+    // Nor is a trusted certificate accepted for another name
+    let addr = start_tls("server", false).await;
+    let result = ClientBuilder::new(addr)
+        .tls(file("ca.pem"))
+        .server_name("example.com")
+        .connect()
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn client_certificates_are_verified() {
+    let addr = start_tls("server", true).await;
+
+    let mut client = ClientBuilder::new(addr)
+        .tls(file("ca.pem"))
+        .client_cert(file("client.pem"), file("client.key"))
+        .connect()
+        .await
+        .unwrap();
+    assert_eq!(client.ping(None).await.unwrap(), "PONG");
+
+    // TLS 1.3 reports a refused client certificate after the handshake, so
+    // the failure may only show on the first request
+    for cert in [None, Some("untrusted")] {
+        let mut builder = ClientBuilder::new(addr).tls(file("ca.pem"));
+        if let Some(cert) = cert {
+            let (pem, key) = (format!("{}.pem", cert), format!("{}.key", cert));
+            builder = builder.client_cert(file(&pem), file(&key));
+        }
+
+        let result = async { builder.connect().await?.ping(None).await }.await;
+        assert!(result.is_err(), "{:?} was accepted", cert);
+    }
+}
+
+#[tokio::test]
+async fn tls_settings_need_tls() {
+    let addr = start(Config::default()).await;
+
+    let err = ClientBuilder::new(addr)
+        .client_cert(file("client.pem"), file("client.key"))
+        .connect()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("needs TLS"), "{}", err);
+
+    let err = ClientBuilder::new(addr)
+        .server_name("localhost")
+        .connect()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("needs TLS"), "{}", err);
+}
+
+#[test]
+fn tls_files_need_tls_enabled() {
+    let ca = file("ca.pem");
+    let cert = file("server.pem");
+    let key = file("server.key");
+    let (ca, cert, key) = (
+        ca.to_str().unwrap(),
+        cert.to_str().unwrap(),
+        key.to_str().unwrap(),
+    );
+
+    let cli = env!("CARGO_BIN_EXE_tiny-redis-cli");
+    let server = env!("CARGO_BIN_EXE_tiny-redis-server");
+
+    let cases = [
+        (cli, vec!["--cacert", ca, "ping"]),
+        (server, vec!["--tls-cert-file", cert, "--tls-key-file", key]),
+        (server, vec!["--tls-ca-cert-file", ca]),
+    ];
+
+    // Refused by the argument parser, before anything is started
+    for (bin, args) in cases {
+        let status = Command::new(bin)
+            .args(&args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(2), "{:?}", args);
+    }
+}
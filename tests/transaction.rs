@@ -0,0 +1,46 @@
+mod common;
+
+use common::{Raw, assert_error, start, text};
+use tiny_redis::frame::Frame;
+use tiny_redis::server::Config;
+
+#[tokio::test]
+async fn malformed_commands_keep_the_connection() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    let reply = conn.call(&["GET"]).await;
+    assert_error(&reply, "ERR wrong number of arguments for 'get' command");
+    let reply = conn.call(&["SET", "a"]).await;
+    assert_error(&reply, "ERR wrong number of arguments for 'set' command");
+    let reply = conn.call(&["GET", "a", "b"]).await;
+    assert_error(&reply, "ERR wrong number of arguments for 'get' command");
+    let reply = conn.call(&["SELECT", "abc"]).await;
+    assert_error(&reply, "ERR value is not an integer or out of range");
+
+    assert_eq!(text(&conn.call(&["SET", "a", "1"]).await), "OK");
+    assert_eq!(text(&conn.call(&["GET", "a"]).await), "1");
+}
+
+#[tokio::test]
+async fn errors_while_queueing_abort_the_transaction() {
+    let addr = start(Config::default()).await;
+    let mut conn = Raw::connect(addr).await;
+
+    assert_eq!(text(&conn.call(&["MULTI"]).await), "OK");
+    assert_eq!(text(&conn.call(&["SET", "x", "1"]).await), "QUEUED");
+    assert_error(&conn.call(&["GET"]).await, "ERR wrong number of arguments");
+    assert_error(&conn.call(&["NOSUCHCOMMAND"]).await, "ERR unknown command");
+    assert_error(&conn.call(&["EXEC"]).await, "EXECABORT");
+
+    // Nothing ran and the connection is out of the transaction
+    assert!(matches!(conn.call(&["GET", "x"]).await, Frame::Null));
+    assert_error(&conn.call(&["EXEC"]).await, "ERR EXEC without MULTI");
+
+    // The next transaction starts clean
+    assert_eq!(text(&conn.call(&["MULTI"]).await), "OK");
+    assert_eq!(text(&conn.call(&["SET", "x", "2"]).await), "QUEUED");
+    let reply = conn.call(&["EXEC"]).await;
+    assert!(matches!(&reply, Frame::Array(replies) if replies.len() == 1));
+    assert_eq!(text(&conn.call(&["GET", "x"]).await), "2");
+}